@@ -0,0 +1,270 @@
+use crate::core::error::{Error, Result};
+use crate::core::types::{AppHandle, ApprovalOptions};
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How long a connecting endpoint waits for an answer when the options leave it unset.
+const DEFAULT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An endpoint waiting to be let in, as sent in `connection-request` events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionRequest {
+    /// What to pass to [`Approvals::answer`].
+    pub id: u64,
+    pub endpoint_id: EndpointId,
+    /// The name the endpoint is known by, e.g. a contact's nickname.
+    pub name: Option<String>,
+}
+
+/// The answers a share is waiting for before serving the endpoints that connect.
+///
+/// Each endpoint is asked about once, with a `connection-request` event; its other
+/// connections wait for the same answer. Once answered or timed out, a
+/// `connection-request-closed` event carries the id and whether the endpoint was let
+/// in. Endpoints that are not let in in time are turned away, and endpoints the user
+/// turned away are not asked about again.
+#[derive(Clone)]
+pub struct Approvals(Arc<Inner>);
+
+struct Inner {
+    app_handle: AppHandle,
+    timeout: Duration,
+    names: HashMap<EndpointId, String>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    pending: HashMap<u64, Pending>,
+    approved: HashSet<EndpointId>,
+    rejected: HashSet<EndpointId>,
+}
+
+struct Pending {
+    request: ConnectionRequest,
+    answer: watch::Sender<Option<bool>>,
+}
+
+impl fmt::Debug for Approvals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Approvals")
+            .field("timeout", &self.0.timeout)
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+impl Approvals {
+    pub(crate) fn new(app_handle: AppHandle, options: &ApprovalOptions) -> Self {
+        Self(Arc::new(Inner {
+            app_handle,
+            timeout: options.timeout.unwrap_or(DEFAULT_APPROVAL_TIMEOUT),
+            names: options.names.clone(),
+            state: Mutex::new(State::default()),
+        }))
+    }
+
+    /// The endpoints waiting for an answer, oldest first.
+    pub fn pending(&self) -> Vec<ConnectionRequest> {
+        let mut pending: Vec<_> = self
+            .lock()
+            .pending
+            .values()
+            .map(|pending| pending.request.clone())
+            .collect();
+        pending.sort_by_key(|request| request.id);
+        pending
+    }
+
+    /// Lets the endpoint behind request `id` in, or turns it away for good.
+    pub fn answer(&self, id: u64, accept: bool) -> Result<()> {
+        let pending = {
+            let mut state = self.lock();
+            let pending = state
+                .pending
+                .remove(&id)
+                .ok_or_else(|| Error::other(format!("no connection request {id} is waiting")))?;
+            if !accept {
+                state.rejected.insert(pending.request.endpoint_id);
+            }
+            pending
+        };
+        self.resolve(pending, accept);
+        Ok(())
+    }
+
+    pub(crate) fn is_approved(&self, endpoint_id: &EndpointId) -> bool {
+        self.lock().approved.contains(endpoint_id)
+    }
+
+    /// Waits until the user lets `endpoint_id` in or turns it away, asking if nobody did yet.
+    pub(crate) async fn ask(&self, endpoint_id: EndpointId) -> bool {
+        let (id, mut answer) = {
+            let mut state = self.lock();
+            if state.approved.contains(&endpoint_id) {
+                return true;
+            }
+            if state.rejected.contains(&endpoint_id) {
+                return false;
+            }
+            let waiting = state
+                .pending
+                .iter()
+                .find(|(_, pending)| pending.request.endpoint_id == endpoint_id)
+                .map(|(id, pending)| (*id, pending.answer.subscribe()));
+            match waiting {
+                Some(waiting) => waiting,
+                None => {
+                    let id = state.next_id;
+                    state.next_id += 1;
+                    let request = ConnectionRequest {
+                        id,
+                        endpoint_id,
+                        name: self.0.names.get(&endpoint_id).cloned(),
+                    };
+                    self.emit("connection-request", &request);
+                    let (answer, receiver) = watch::channel(None);
+                    state.pending.insert(id, Pending { request, answer });
+                    (id, receiver)
+                }
+            }
+        };
+
+        let answered = tokio::time::timeout(self.0.timeout, answer.wait_for(Option::is_some)).await;
+        let answered = answered.map(|answer| answer.map(|accepted| accepted.unwrap_or(false)));
+        match answered {
+            Ok(Ok(accepted)) => accepted,
+            _ => {
+                // Nobody answered in time; the first connection to give up closes the request
+                let pending = self.lock().pending.remove(&id);
+                match pending {
+                    Some(pending) => {
+                        tracing::info!("nobody let {} in, turning it away", endpoint_id);
+                        self.resolve(pending, false);
+                        false
+                    }
+                    // Answered just as the time ran out
+                    None => answer.borrow().unwrap_or(false),
+                }
+            }
+        }
+    }
+
+    fn resolve(&self, pending: Pending, accept: bool) {
+        if accept {
+            self.lock().approved.insert(pending.request.endpoint_id);
+        }
+        pending.answer.send_replace(Some(accept));
+        let closed = serde_json::json!({ "id": pending.request.id, "accepted": accept });
+        self.emit("connection-request-closed", &closed);
+    }
+
+    fn emit(&self, event_name: &str, payload: &impl Serialize) {
+        let Some(handle) = &self.0.app_handle else {
+            return;
+        };
+        match serde_json::to_string(payload) {
+            Ok(payload) => {
+                if let Err(e) = handle.emit_event_with_payload(event_name, &payload) {
+                    tracing::warn!("Failed to emit {}: {}", event_name, e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize {}: {}", event_name, e),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.0.state.lock().expect("approvals poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::receive::download;
+    use crate::core::send::start_share;
+    use crate::core::types::{ApprovalOptions, ReceiveOptions, SendOptions};
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn asks_before_serving_each_receiver() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("draft.txt");
+        std::fs::write(&src, "not final").unwrap();
+
+        let friend = iroh::SecretKey::generate(&mut rand::rng());
+        let stranger = iroh::SecretKey::generate(&mut rand::rng());
+        let silent = iroh::SecretKey::generate(&mut rand::rng());
+        let share = start_share(
+            src,
+            SendOptions {
+                approval: Some(ApprovalOptions {
+                    timeout: Some(Duration::from_secs(2)),
+                    names: [(friend.public(), "Ada".to_string())].into_iter().collect(),
+                }),
+                ..SendOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let approvals = share.approvals.clone().unwrap();
+        // Plays the user: lets Ada in and turns the stranger away, ignoring anyone else
+        let answering = {
+            let approvals = approvals.clone();
+            let (friend, stranger) = (friend.public(), stranger.public());
+            tokio::spawn(async move {
+                loop {
+                    for request in approvals.pending() {
+                        if request.endpoint_id == friend {
+                            assert_eq!(request.name.as_deref(), Some("Ada"));
+                            approvals.answer(request.id, true).unwrap();
+                        } else if request.endpoint_id == stranger {
+                            approvals.answer(request.id, false).unwrap();
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            })
+        };
+        let receive_options = |key: &iroh::SecretKey, out: &Path| ReceiveOptions {
+            output_dir: Some(out.to_path_buf()),
+            secret_key: Some(key.clone()),
+            ..ReceiveOptions::local()
+        };
+
+        let out = td.path().join("friend");
+        download(share.ticket.clone(), receive_options(&friend, &out), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("draft.txt")).unwrap(),
+            "not final"
+        );
+        for key in [&stranger, &silent] {
+            let out = td.path().join(key.public().to_string());
+            let result = download(share.ticket.clone(), receive_options(key, &out), None).await;
+            assert!(result.is_err());
+            assert!(!out.join("draft.txt").exists());
+        }
+        // Nobody answered for the silent one, so it was turned away once the time ran out
+        assert!(approvals.pending().is_empty());
+        assert!(approvals.answer(0, true).is_err());
+
+        // The stranger was turned away for good, without asking again
+        answering.abort();
+        let asked =
+            tokio::time::timeout(Duration::from_millis(100), approvals.ask(stranger.public()));
+        assert_eq!(asked.await, Ok(false));
+        assert!(approvals.pending().is_empty());
+
+        share.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+    }
+}
@@ -0,0 +1,395 @@
+use iroh::EndpointId;
+use iroh_blobs::{
+    api::Store,
+    hashseq::HashSeq,
+    protocol::{ChunkRanges, ChunkRangesExt, GetRequest},
+    Hash,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The blobs a receiver needs for the whole collection, with their sizes: the hash
+/// sequence, the collection metadata and the files.
+///
+/// Empty until the collection is known, e.g. while a streaming share still hashes.
+/// Until then no receiver can complete.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CollectionBlobs(Arc<Mutex<Blobs>>);
+
+#[derive(Debug, Default)]
+struct Blobs {
+    /// The hash sequence, then its children in order.
+    order: Vec<Hash>,
+    sizes: HashMap<Hash, u64>,
+}
+
+impl CollectionBlobs {
+    /// Makes the collection `hash` in `db` the one receivers have to get.
+    pub(crate) async fn set(&self, db: &Store, hash: Hash) -> anyhow::Result<()> {
+        let bytes = db.get_bytes(hash).await?;
+        let mut blobs = Blobs::default();
+        blobs.order.push(hash);
+        blobs.sizes.insert(hash, bytes.len() as u64);
+        for child in HashSeq::try_from(bytes)? {
+            blobs.order.push(child);
+            blobs.sizes.insert(child, db.observe(child).await?.size());
+        }
+        *self.lock() = blobs;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Blobs> {
+        self.0.lock().expect("collection blobs poisoned")
+    }
+}
+
+/// Who a completion is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Receiver {
+    Endpoint(EndpointId),
+    /// A connection whose endpoint the provider was not told about.
+    Connection(u64),
+}
+
+/// Tracks, for each receiver, which parts of the collection's blobs were fully served.
+///
+/// A receiver is complete once every blob of the collection was served to it whole,
+/// counting all of its connections: a download may fetch the hash sequence on one
+/// connection and the files on another.
+#[derive(Debug, Default)]
+pub(crate) struct CompletionTracker {
+    collection: CollectionBlobs,
+    connections: HashMap<u64, Receiver>,
+    served: HashMap<Receiver, HashMap<Hash, ChunkRanges>>,
+}
+
+impl CompletionTracker {
+    pub(crate) fn new(collection: CollectionBlobs) -> Self {
+        Self {
+            collection,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn connected(&mut self, connection_id: u64, endpoint_id: Option<EndpointId>) {
+        let receiver = endpoint_id.map_or(Receiver::Connection(connection_id), Receiver::Endpoint);
+        self.connections.insert(connection_id, receiver);
+    }
+
+    /// Forgets the connection. What an endpoint was served is kept for its next connection.
+    pub(crate) fn closed(&mut self, connection_id: u64) {
+        if let Some(Receiver::Connection(id)) = self.connections.remove(&connection_id) {
+            self.served.remove(&Receiver::Connection(id));
+        }
+    }
+
+    /// Records that `ranges` of `hash` went out whole on `connection_id`.
+    ///
+    /// Returns the receiver if this completed the collection for it. Its record is then
+    /// dropped, so that downloading the collection again completes again.
+    pub(crate) fn served(
+        &mut self,
+        connection_id: u64,
+        hash: Hash,
+        ranges: &ChunkRanges,
+    ) -> Option<Receiver> {
+        let receiver = self.record(connection_id, hash, ranges);
+        self.check(receiver)
+    }
+
+    /// Records a request made on `connection_id`. A request for the collection's hash
+    /// sequence and some of its children skips the blobs the receiver already has or
+    /// chose not to download, so those count as served. A request for the hash sequence
+    /// alone asks for no children at all, so it says nothing about them.
+    ///
+    /// Returns the receiver if it turns out to have the whole collection already.
+    pub(crate) fn requested(
+        &mut self,
+        connection_id: u64,
+        request: &GetRequest,
+    ) -> Option<Receiver> {
+        let held = {
+            let collection = self.collection.lock();
+            let asks_for_children = request
+                .ranges
+                .iter_non_empty_infinite()
+                .any(|(offset, _)| offset > 0);
+            if collection.order.first() != Some(&request.hash) || !asks_for_children {
+                return None;
+            }
+            collection
+                .order
+                .iter()
+                .zip(request.ranges.iter_infinite())
+                .filter(|(_, asked)| asked.is_empty())
+                .map(|(hash, _)| *hash)
+                .collect::<Vec<_>>()
+        };
+        let mut receiver = None;
+        for hash in held {
+            receiver = Some(self.record(connection_id, hash, &ChunkRanges::all()));
+        }
+        self.check(receiver?)
+    }
+
+    fn record(&mut self, connection_id: u64, hash: Hash, ranges: &ChunkRanges) -> Receiver {
+        let receiver = *self
+            .connections
+            .entry(connection_id)
+            .or_insert(Receiver::Connection(connection_id));
+        let served = self.served.entry(receiver).or_default();
+        let blob = served.entry(hash).or_insert_with(ChunkRanges::empty);
+        *blob |= ranges.clone();
+        receiver
+    }
+
+    /// Whether `receiver` now has the whole collection, dropping its record if so.
+    fn check(&mut self, receiver: Receiver) -> Option<Receiver> {
+        let served = self.served.get(&receiver)?;
+        let collection = self.collection.lock();
+        let complete = !collection.sizes.is_empty()
+            && collection.sizes.iter().all(|(hash, size)| {
+                let needed = ChunkRanges::bytes(..*size);
+                needed.is_empty() || served.get(hash).is_some_and(|got| needed.is_subset(got))
+            });
+        drop(collection);
+        if complete {
+            self.served.remove(&receiver);
+            Some(receiver)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::offer::{OfferAnswer, OfferPrompt};
+    use crate::core::receive::download;
+    use crate::core::send::start_share;
+    use crate::core::types::{
+        Events, ReceiveOptions, ReceiverProgress, ReceiverState, SendOptions,
+    };
+    use std::time::{Duration, Instant};
+
+    fn tracker(blobs: &[(Hash, u64)]) -> CompletionTracker {
+        let collection = CollectionBlobs::default();
+        *collection.lock() = Blobs {
+            order: blobs.iter().map(|(hash, _)| *hash).collect(),
+            sizes: blobs.iter().copied().collect(),
+        };
+        CompletionTracker::new(collection)
+    }
+
+    #[test]
+    fn completes_once_every_blob_is_served_whole() {
+        let (root, meta, file) = (Hash::new(b"root"), Hash::new(b"meta"), Hash::new(b"file"));
+        let mut tracker = tracker(&[(root, 64), (meta, 100), (file, 5 * 1024 * 1024)]);
+        let peer = iroh::SecretKey::generate(&mut rand::rng()).public();
+        tracker.connected(1, Some(peer));
+        tracker.connected(2, Some(peer));
+
+        assert_eq!(tracker.served(1, root, &ChunkRanges::all()), None);
+        assert_eq!(tracker.served(1, meta, &ChunkRanges::all()), None);
+        // Half of the file is not the file
+        assert_eq!(
+            tracker.served(1, file, &ChunkRanges::bytes(..4 * 1024 * 1024)),
+            None
+        );
+        // The rest arrives on another connection of the same receiver
+        tracker.closed(1);
+        assert_eq!(
+            tracker.served(2, file, &ChunkRanges::bytes(4 * 1024 * 1024..)),
+            Some(Receiver::Endpoint(peer))
+        );
+        // A second download starts from scratch
+        assert_eq!(tracker.served(2, root, &ChunkRanges::all()), None);
+    }
+
+    #[test]
+    fn parts_a_request_leaves_out_count_as_served() {
+        let (root, meta, stored, changed) = (
+            Hash::new(b"root"),
+            Hash::new(b"meta"),
+            Hash::new(b"stored"),
+            Hash::new(b"changed"),
+        );
+        let mut tracker = tracker(&[(root, 96), (meta, 100), (stored, 5000), (changed, 10)]);
+        tracker.connected(1, None);
+
+        // The hash sequence alone says nothing about the files
+        let raw = GetRequest::blob(root);
+        assert_eq!(tracker.requested(1, &raw), None);
+        assert_eq!(tracker.served(1, root, &ChunkRanges::all()), None);
+        // Asking for the end of every blob, as for their sizes, skips none of them
+        let sizes = GetRequest::builder()
+            .child(0, ChunkRanges::last_chunk())
+            .child(1, ChunkRanges::last_chunk())
+            .child(2, ChunkRanges::last_chunk())
+            .build(root);
+        assert_eq!(tracker.requested(1, &sizes), None);
+
+        // The stored file is left out, so only the changed one is still missing
+        let missing = GetRequest::builder()
+            .child(0, ChunkRanges::all())
+            .child(2, ChunkRanges::all())
+            .build(root);
+        assert_eq!(tracker.requested(1, &missing), None);
+        assert_eq!(tracker.served(1, meta, &ChunkRanges::all()), None);
+        assert_eq!(
+            tracker.served(1, changed, &ChunkRanges::all()),
+            Some(Receiver::Connection(1))
+        );
+    }
+
+    #[test]
+    fn nothing_completes_before_the_collection_is_known() {
+        let mut tracker = tracker(&[]);
+        tracker.connected(1, None);
+        let hash = Hash::new(b"file");
+        assert_eq!(tracker.served(1, hash, &ChunkRanges::all()), None);
+        tracker.closed(1);
+        assert!(tracker.served.is_empty());
+    }
+
+    #[tokio::test]
+    async fn completion_fires_once_the_receiver_has_everything() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("photos");
+        std::fs::create_dir(&dir).unwrap();
+        for i in 0..3 {
+            std::fs::write(dir.join(format!("img{i}.jpg")), vec![i as u8; 40_000]).unwrap();
+        }
+
+        // Streaming receivers fetch the files and the collection on separate connections
+        for streaming in [false, true] {
+            let events = Arc::new(Events::default());
+            let share = start_share(
+                dir.clone(),
+                SendOptions {
+                    streaming,
+                    ..SendOptions::local()
+                },
+                Some(events.clone()),
+            )
+            .await
+            .unwrap();
+
+            let receiver = iroh::SecretKey::generate(&mut rand::rng());
+            download(
+                share.ticket.clone(),
+                ReceiveOptions {
+                    output_dir: Some(td.path().join(format!("out-{streaming}"))),
+                    secret_key: Some(receiver.clone()),
+                    ..ReceiveOptions::local()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+            // The provider learns about the last blob shortly after the receiver has it
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while events.named("receiver-completed").is_empty() && Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            share.router.shutdown().await.unwrap();
+            let completed = events.named("receiver-completed");
+            assert_eq!(completed.len(), 1, "streaming: {streaming}");
+            let completed: serde_json::Value = serde_json::from_str(&completed[0]).unwrap();
+            assert_eq!(completed["endpoint_id"], receiver.public().to_string());
+            assert_eq!(events.named("transfer-completed").len(), 1);
+            assert!(events.named("transfer-failed").is_empty());
+            // Per-receiver progress names the receiver and ends with it complete
+            let progress: Vec<ReceiverProgress> = events
+                .named("receiver-progress")
+                .iter()
+                .map(|payload| serde_json::from_str(payload).unwrap())
+                .collect();
+            assert!(progress
+                .iter()
+                .all(|p| p.endpoint_id == Some(receiver.public())));
+            assert!(progress.iter().any(|p| p.state == ReceiverState::Completed));
+            // Each connection counts what went out on it; together that is everything
+            let mut sent = HashMap::new();
+            for p in &progress {
+                sent.insert(p.connection_id, p.bytes);
+            }
+            assert!(sent.values().sum::<u64>() >= share.size);
+            std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn completion_fires_for_incremental_and_partial_downloads() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("project");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("assets.bin"), vec![7u8; 200_000]).unwrap();
+        std::fs::write(dir.join("notes.md"), "v1").unwrap();
+        let store_dir = td.path().join("store");
+
+        // Waits for the share to see its receiver through, then stops it
+        let completions = |share: crate::core::types::SendResult, events: Arc<Events>| async move {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while events.named("receiver-completed").is_empty() && Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            share.router.shutdown().await.unwrap();
+            std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+            events.named("receiver-completed").len()
+        };
+
+        for version in ["v1", "v2"] {
+            std::fs::write(dir.join("notes.md"), version).unwrap();
+            let events = Arc::new(Events::default());
+            let share = start_share(dir.clone(), SendOptions::local(), Some(events.clone()))
+                .await
+                .unwrap();
+            download(
+                share.ticket.clone(),
+                ReceiveOptions {
+                    output_dir: Some(td.path().join("out")),
+                    store_dir: Some(store_dir.clone()),
+                    update: true,
+                    ..ReceiveOptions::local()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+            // The second time only the changed note is asked for
+            assert_eq!(completions(share, events).await, 1, "{version}");
+        }
+
+        let events = Arc::new(Events::default());
+        let share = start_share(dir.clone(), SendOptions::local(), Some(events.clone()))
+            .await
+            .unwrap();
+        let prompt = OfferPrompt::new();
+        let answering = tokio::spawn({
+            let prompt = prompt.clone();
+            async move {
+                while prompt.offer().is_none() {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                let notes = vec!["project/notes.md".to_string()];
+                prompt.answer(OfferAnswer::AcceptSome(notes)).unwrap();
+            }
+        });
+        download(
+            share.ticket.clone(),
+            ReceiveOptions {
+                output_dir: Some(td.path().join("partial")),
+                confirm: Some(prompt),
+                ..ReceiveOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        answering.await.unwrap();
+        assert_eq!(completions(share, events).await, 1);
+    }
+}
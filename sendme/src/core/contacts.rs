@@ -0,0 +1,156 @@
+use crate::core::error::Result;
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A device this one has paired with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub nickname: String,
+    pub endpoint_id: EndpointId,
+    /// Seconds since the unix epoch at which the device was last paired or heard from.
+    pub last_seen: u64,
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The list of paired devices, persisted as a JSON file.
+#[derive(Debug)]
+pub struct ContactBook {
+    path: PathBuf,
+    contacts: Vec<Contact>,
+}
+
+impl ContactBook {
+    /// Loads the contact book at `path`; a missing file is an empty book.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let contacts = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, contacts })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.contacts)?)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    /// Adds a contact, replacing any existing entry for the same endpoint.
+    pub fn upsert(&mut self, contact: Contact) {
+        match self
+            .contacts
+            .iter_mut()
+            .find(|c| c.endpoint_id == contact.endpoint_id)
+        {
+            Some(existing) => *existing = contact,
+            None => self.contacts.push(contact),
+        }
+    }
+
+    /// Removes the contact for `endpoint_id`, returning whether there was one.
+    pub fn remove(&mut self, endpoint_id: &EndpointId) -> bool {
+        let before = self.contacts.len();
+        self.contacts.retain(|c| &c.endpoint_id != endpoint_id);
+        self.contacts.len() != before
+    }
+
+    pub fn rename(&mut self, endpoint_id: &EndpointId, nickname: String) -> bool {
+        match self.get_mut(endpoint_id) {
+            Some(contact) => {
+                contact.nickname = nickname;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records that `endpoint_id` was just seen. Unknown endpoints are ignored.
+    pub fn touch(&mut self, endpoint_id: &EndpointId) -> bool {
+        match self.get_mut(endpoint_id) {
+            Some(contact) => {
+                contact.last_seen = now_secs();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, endpoint_id: &EndpointId) -> Option<&Contact> {
+        self.contacts.iter().find(|c| &c.endpoint_id == endpoint_id)
+    }
+
+    fn get_mut(&mut self, endpoint_id: &EndpointId) -> Option<&mut Contact> {
+        self.contacts
+            .iter_mut()
+            .find(|c| &c.endpoint_id == endpoint_id)
+    }
+
+    /// Looks a contact up by endpoint id or, failing that, by nickname.
+    pub fn find(&self, id_or_nickname: &str) -> Option<&Contact> {
+        if let Ok(endpoint_id) = id_or_nickname.parse::<EndpointId>() {
+            if let Some(contact) = self.get(&endpoint_id) {
+                return Some(contact);
+            }
+        }
+        self.contacts.iter().find(|c| c.nickname == id_or_nickname)
+    }
+
+    pub fn endpoint_ids(&self) -> HashSet<EndpointId> {
+        self.contacts.iter().map(|c| c.endpoint_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contact_book_roundtrip() {
+        let td = tempfile::TempDir::new().unwrap();
+        let path = td.path().join("contacts.json");
+        let alice = iroh::SecretKey::generate(&mut rand::rng()).public();
+
+        let mut book = ContactBook::load(&path).unwrap();
+        assert!(book.contacts().is_empty());
+        book.upsert(Contact {
+            nickname: "alice".to_string(),
+            endpoint_id: alice,
+            last_seen: 1,
+        });
+        book.upsert(Contact {
+            nickname: "alice laptop".to_string(),
+            endpoint_id: alice,
+            last_seen: 2,
+        });
+        book.save().unwrap();
+
+        let book = ContactBook::load(&path).unwrap();
+        assert_eq!(book.contacts().len(), 1);
+        assert_eq!(book.find("alice laptop").unwrap().endpoint_id, alice);
+        assert_eq!(book.find(&alice.to_string()).unwrap().last_seen, 2);
+        assert!(book.find("bob").is_none());
+    }
+}
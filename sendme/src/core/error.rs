@@ -0,0 +1,193 @@
+use crate::core::proxy::ProxyError;
+use serde::ser::SerializeStruct;
+use std::path::PathBuf;
+
+/// Errors returned by the public sendme API.
+///
+/// Each variant has a stable [`code`](Error::code) that frontends can match on
+/// instead of parsing messages. Internally the library still uses `anyhow`; typed
+/// errors raised deep inside survive the trip because [`From<anyhow::Error>`] looks
+/// for them in the error chain.
+#[derive(Debug)]
+pub enum Error {
+    /// The ticket could not be parsed, or is of the wrong kind.
+    InvalidTicket(String),
+    /// The peer could not be reached, directly, through a relay or through the proxy.
+    ConnectFailed(String),
+    /// The peer went away or refused the transfer part way through.
+    PeerAborted(String),
+    /// The peer requires a passphrase or a paired device, and we could not prove either.
+    Unauthorized(String),
+    /// A file that would be exported already exists.
+    ExportConflict(PathBuf),
+    /// A path is unsafe or unusable, e.g. traversal in a received name or a missing source.
+    PathRejected { path: String, reason: String },
+    /// The transfer was refused up front because it would not fit on disk.
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+    /// The transfer was refused up front because it exceeds the receiver's size or file limits.
+    LimitExceeded(String),
+    /// The disk ran out of space.
+    StorageFull(String),
+    /// Files on disk no longer match what was received.
+    VerificationFailed(String),
+    /// The operation was cancelled by the user.
+    Cancelled,
+    /// Anything else.
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// A stable, machine readable identifier for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidTicket(_) => "invalid_ticket",
+            Error::ConnectFailed(_) => "connect_failed",
+            Error::PeerAborted(_) => "peer_aborted",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::ExportConflict(_) => "export_conflict",
+            Error::PathRejected { .. } => "path_rejected",
+            Error::InsufficientSpace { .. } => "insufficient_space",
+            Error::LimitExceeded(_) => "limit_exceeded",
+            Error::StorageFull(_) => "storage_full",
+            Error::VerificationFailed(_) => "verification_failed",
+            Error::Cancelled => "cancelled",
+            Error::Other(_) => "internal",
+        }
+    }
+
+    /// Whether trying the same operation again may succeed without the user changing anything.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::ConnectFailed(_) | Error::PeerAborted(_) | Error::VerificationFailed(_)
+        )
+    }
+
+    pub fn other(message: impl std::fmt::Display) -> Self {
+        Error::Other(anyhow::anyhow!("{}", message))
+    }
+
+    pub(crate) fn path_rejected(path: impl Into<String>, reason: impl Into<String>) -> Self {
+        Error::PathRejected {
+            path: path.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidTicket(reason) => write!(f, "invalid ticket: {reason}"),
+            Error::ConnectFailed(reason) => write!(f, "connection failed: {reason}"),
+            Error::PeerAborted(reason) => write!(f, "peer aborted the transfer: {reason}"),
+            Error::Unauthorized(reason) => write!(f, "not authorized: {reason}"),
+            Error::ExportConflict(path) => write!(f, "target {} already exists", path.display()),
+            Error::PathRejected { path, reason } => write!(f, "path {path:?} rejected: {reason}"),
+            Error::InsufficientSpace {
+                path,
+                needed,
+                available,
+            } => write!(
+                f,
+                "not enough space in {}: {needed} bytes needed, {available} available",
+                path.display()
+            ),
+            Error::LimitExceeded(reason) => write!(f, "transfer exceeds the limits: {reason}"),
+            Error::StorageFull(reason) => write!(f, "not enough disk space: {reason}"),
+            Error::VerificationFailed(reason) => write!(f, "verification failed: {reason}"),
+            Error::Cancelled => write!(f, "operation cancelled"),
+            Error::Other(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Serialized as `{ code, message, retryable }` for the frontend.
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Error", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("retryable", &self.is_retryable())?;
+        state.end()
+    }
+}
+
+/// Whether an I/O error means the disk is full. `ErrorKind::StorageFull` needs a newer
+/// Rust than our minimum, so this looks at the OS error code.
+fn is_storage_full(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const CODES: &[i32] = &[28]; // ENOSPC
+    #[cfg(windows)]
+    const CODES: &[i32] = &[39, 112]; // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+    e.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        if is_storage_full(&e) {
+            return Error::StorageFull(e.to_string());
+        }
+        Error::Other(e.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Other(e.into())
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        if e.is::<Error>() {
+            return e.downcast::<Error>().expect("checked above");
+        }
+        for cause in e.chain() {
+            if let Some(proxy) = cause.downcast_ref::<ProxyError>() {
+                return Error::ConnectFailed(proxy.to_string());
+            }
+            if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+                if is_storage_full(io) {
+                    return Error::StorageFull(io.to_string());
+                }
+            }
+        }
+        Error::Other(e)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_errors_survive_anyhow_context() {
+        let e = anyhow::Error::from(Error::ExportConflict(PathBuf::from("a.txt")));
+        assert_eq!(Error::from(e).code(), "export_conflict");
+
+        let full = std::io::Error::from_raw_os_error(if cfg!(windows) { 112 } else { 28 });
+        let e = anyhow::Error::from(full).context("writing a.txt");
+        assert_eq!(Error::from(e).code(), "storage_full");
+
+        assert_eq!(Error::from(anyhow::anyhow!("boom")).code(), "internal");
+    }
+
+    #[test]
+    fn errors_serialize_with_code() {
+        let json = serde_json::to_value(Error::ConnectFailed("offline".to_string())).unwrap();
+        assert_eq!(json["code"], "connect_failed");
+        assert_eq!(json["message"], "connection failed: offline");
+        assert_eq!(json["retryable"], true);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::core::error::Error;
+use crate::core::receive::{get_export_path, portable_name};
+use icu_normalizer::ComposingNormalizerBorrowed;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// What to do with a received name that Windows, macOS or Linux cannot store as is.
+///
+/// Names that try to escape the output directory are always rejected, whatever the policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilenamePolicy {
+    /// Refuse the whole collection before anything is exported.
+    Reject,
+    /// Rename such files to names that work everywhere, e.g. `aux.txt` to `_aux.txt`.
+    #[default]
+    Map,
+}
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+fn is_hex_pair(s: &str) -> bool {
+    s.len() >= 2 && s.as_bytes()[..2].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// Leads a collection name component that is escaped.
+///
+/// Senders refused names with a backslash before escaping existed, so components without
+/// it are always taken literally, and older receivers refuse escaped ones instead of
+/// exporting them under the wrong name.
+pub(crate) const ESCAPE_MARKER: char = '\\';
+
+/// Escapes a file name component for use in a collection name.
+///
+/// Collection names are UTF-8 and receivers refuse `\`, so a name with bytes that are not
+/// UTF-8 or with a backslash is marked with [`ESCAPE_MARKER`], and those bytes and
+/// backslashes are written as `%XX`, as is a `%` that would otherwise read as an escape.
+/// Every other name is kept as is.
+pub(crate) fn escape_component(component: &OsStr) -> String {
+    match component.to_str() {
+        Some(name) if !name.contains('\\') => return name.to_string(),
+        _ => {}
+    }
+    let mut escaped = String::from(ESCAPE_MARKER);
+    for chunk in component.as_encoded_bytes().utf8_chunks() {
+        let valid = chunk.valid();
+        for (i, c) in valid.char_indices() {
+            match c {
+                '\\' => escaped.push_str("%5C"),
+                '%' if is_hex_pair(&valid[i + 1..]) => escaped.push_str("%25"),
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(escaped, "%{byte:02X}").expect("writing to a string");
+        }
+    }
+    escaped
+}
+
+/// The byte an escape stands for, if `escape_component` could have written it.
+fn unescape_byte(hex: &[u8]) -> Option<u8> {
+    if hex.len() != 2 || !hex.iter().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F')) {
+        return None;
+    }
+    let byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+    (byte == b'%' || byte == b'\\' || byte >= 0x80).then_some(byte)
+}
+
+fn unescape_bytes(component: &str) -> Cow<'_, [u8]> {
+    let Some(component) = component.strip_prefix(ESCAPE_MARKER) else {
+        return Cow::Borrowed(component.as_bytes());
+    };
+    let bytes = component.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = bytes.get(i + 1..i + 3).and_then(unescape_byte) {
+                unescaped.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        unescaped.push(bytes[i]);
+        i += 1;
+    }
+    Cow::Owned(unescaped)
+}
+
+/// The file name an escaped component stands for.
+///
+/// Anywhere but on unix the component must have gone through [`portable_component`] first,
+/// which makes sure it is valid UTF-8.
+pub(crate) fn unescape_component(component: &str) -> OsString {
+    let bytes = unescape_bytes(component).into_owned();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        OsString::from_vec(bytes)
+    }
+    #[cfg(not(unix))]
+    {
+        OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// Whether this platform can store the file name an escaped component stands for.
+/// Unix file names are bytes; elsewhere they must be UTF-8 without backslashes.
+fn is_native(component: &str) -> bool {
+    cfg!(unix)
+        || std::str::from_utf8(&unescape_bytes(component)).is_ok_and(|name| !name.contains('\\'))
+}
+
+/// A version of a path component that every platform can store; borrowed if it already is one.
+///
+/// Components are in escaped form. Non-UTF-8 names and backslashes are only mapped where
+/// the local platform cannot store them, so they survive a transfer between unix machines.
+pub(crate) fn portable_component(component: &str) -> Cow<'_, str> {
+    let invalid = |c: char| c.is_control() || matches!(c, '<' | '>' | '"' | '|' | '?' | '*');
+    let trimmed = component.trim_end_matches(['.', ' ']);
+    if !component.contains(invalid)
+        && trimmed.len() == component.len()
+        && !is_reserved(component)
+        && is_native(component)
+    {
+        return Cow::Borrowed(component);
+    }
+    // Windows silently drops trailing dots and spaces, so two such names would collide anyway
+    let mut name: String = trimmed
+        .chars()
+        .map(|c| if invalid(c) { '_' } else { c })
+        .collect();
+    if !is_native(&name) {
+        let lossy =
+            String::from_utf8_lossy(&unescape_bytes(&name)).replace(['\u{FFFD}', '\\'], "_");
+        name = escape_component(OsStr::new(&lossy));
+    }
+    if name.is_empty() || is_reserved(&name) {
+        name.insert(0, '_');
+    }
+    Cow::Owned(name)
+}
+
+/// Names that map to the same key end up as the same file on a case-insensitive or
+/// normalizing filesystem, such as the defaults on Windows and macOS.
+fn collision_key(relative: &str) -> String {
+    ComposingNormalizerBorrowed::new_nfc()
+        .normalize(relative)
+        .to_lowercase()
+}
+
+/// Inserts ` (n)` before the extension of the last component.
+fn numbered(relative: &str, n: usize) -> String {
+    let (dir, last) = match relative.rsplit_once('/') {
+        Some((dir, last)) => (Some(dir), last),
+        None => (None, relative),
+    };
+    let last = match last.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{stem} ({n}).{ext}"),
+        _ => format!("{last} ({n})"),
+    };
+    match dir {
+        Some(dir) => format!("{dir}/{last}"),
+        None => last,
+    }
+}
+
+/// Works out where each name of a collection is exported below `root`.
+///
+/// Every name is checked before any path is returned, so a collection is refused as a
+/// whole rather than half exported.
+pub(crate) fn export_paths<'a>(
+    root: &Path,
+    names: impl IntoIterator<Item = &'a str>,
+    policy: FilenamePolicy,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut taken: HashMap<String, &str> = HashMap::new();
+    let mut paths = Vec::new();
+    for name in names {
+        let mut relative = portable_name(name, policy)?;
+        if let Some(first) = taken.get(collision_key(&relative).as_str()) {
+            if policy == FilenamePolicy::Reject {
+                return Err(Error::path_rejected(
+                    name,
+                    format!("differs from {first:?} only in case or Unicode normalization"),
+                ));
+            }
+            relative = (2..)
+                .map(|n| numbered(&relative, n))
+                .find(|candidate| !taken.contains_key(collision_key(candidate).as_str()))
+                .expect("some number is free");
+        }
+        taken.insert(collision_key(&relative), name);
+        paths.push(get_export_path(root, &relative, policy)?);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portable_names() {
+        assert_eq!(portable_component("report.pdf"), "report.pdf");
+        assert_eq!(portable_component("CON"), "_CON");
+        assert_eq!(portable_component("aux.txt"), "_aux.txt");
+        assert_eq!(portable_component("Lpt1.tar.gz"), "_Lpt1.tar.gz");
+        assert_eq!(portable_component("console.log"), "console.log");
+        assert_eq!(portable_component("notes. . "), "notes");
+        assert_eq!(portable_component("..."), "_");
+        assert_eq!(portable_component("what?<now>|*\"\t"), "what__now_____");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escaped_names_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+
+        for name in [
+            &b"plain.txt"[..],
+            b"back\\slash",
+            b"caf\xe9",
+            b"100%",
+            b"%41%5C%e9",
+            b"%\xff\\",
+        ] {
+            let escaped = escape_component(OsStr::from_bytes(name));
+            assert!(!escaped[1..].contains('\\'), "{escaped}");
+            assert_eq!(unescape_bytes(&escaped), name, "{escaped}");
+        }
+        assert_eq!(escape_component(OsStr::new("100%.txt")), "100%.txt");
+        assert_eq!(escape_component(OsStr::new("%41%5C")), "%41%5C");
+        assert_eq!(escape_component(OsStr::new("a\\b")), "\\a%5Cb");
+        // Names from senders that did not escape are taken literally
+        assert_eq!(unescape_bytes("50%25 off %C3%A9"), &b"50%25 off %C3%A9"[..]);
+        // Escapes no sender writes are taken literally
+        assert_eq!(unescape_bytes("\\%2F%00%e9"), &b"%2F%00%e9"[..]);
+    }
+
+    #[test]
+    fn export_paths_map_or_reject_unportable_names() {
+        let root = Path::new("/out");
+        let names = [
+            "dir/aux.txt",
+            "dir/Readme.md",
+            "dir/README.md",
+            "caf\u{e9}",
+            "cafe\u{301}",
+        ];
+        let paths = export_paths(root, names, FilenamePolicy::Map).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                root.join("dir").join("_aux.txt"),
+                root.join("dir").join("Readme.md"),
+                root.join("dir").join("README (2).md"),
+                root.join("caf\u{e9}"),
+                root.join("cafe\u{301} (2)"),
+            ]
+        );
+
+        let err = export_paths(root, ["dir/aux.txt"], FilenamePolicy::Reject).unwrap_err();
+        assert_eq!(err.code(), "path_rejected");
+        let err = export_paths(root, ["a.txt", "A.TXT"], FilenamePolicy::Reject).unwrap_err();
+        assert!(err.to_string().contains("only in case"), "{err}");
+        assert!(export_paths(root, ["../etc/passwd"], FilenamePolicy::Map).is_err());
+    }
+}
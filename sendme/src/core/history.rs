@@ -0,0 +1,369 @@
+use crate::core::contacts::now_secs;
+use crate::core::error::Result;
+use iroh::EndpointId;
+use iroh_blobs::provider::TransferStats;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// One finished transfer, as kept in the transfer history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub direction: TransferDirection,
+    /// Seconds since the unix epoch at which the transfer started.
+    pub started_at: u64,
+    pub duration_ms: u64,
+    /// The other side, if it is known. A receive that failed before connecting has none.
+    pub peer: Option<EndpointId>,
+    /// Hex collection hash, if the ticket could be read.
+    pub hash: Option<String>,
+    pub files: Vec<String>,
+    /// Bytes that went over the wire, including the collection's metadata.
+    pub size: u64,
+    pub outcome: TransferOutcome,
+    /// Error code and message of a failed transfer.
+    pub error: Option<String>,
+}
+
+const CSV_HEADER: &str = "direction,started_at,duration_ms,peer,hash,files,size,outcome,error";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl TransferRecord {
+    fn to_csv_row(&self) -> String {
+        let direction = match self.direction {
+            TransferDirection::Sent => "sent",
+            TransferDirection::Received => "received",
+        };
+        let outcome = match self.outcome {
+            TransferOutcome::Completed => "completed",
+            TransferOutcome::Failed => "failed",
+            TransferOutcome::Cancelled => "cancelled",
+        };
+        [
+            direction.to_string(),
+            self.started_at.to_string(),
+            self.duration_ms.to_string(),
+            self.peer.map(|peer| peer.to_string()).unwrap_or_default(),
+            self.hash.clone().unwrap_or_default(),
+            self.files.join(";"),
+            self.size.to_string(),
+            outcome.to_string(),
+            self.error.clone().unwrap_or_default(),
+        ]
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// Export format for [`TransferHistory::export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for HistoryFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => anyhow::bail!("unknown history format {other:?}, expected json or csv"),
+        }
+    }
+}
+
+/// The transfer history, persisted as one JSON record per line.
+#[derive(Debug, Clone)]
+pub struct TransferHistory {
+    path: PathBuf,
+}
+
+impl TransferHistory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &TransferRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// All records, oldest first. A missing file is an empty history.
+    pub fn list(&self) -> Result<Vec<TransferRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = std::fs::read_to_string(&self.path)?;
+        let mut records = Vec::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(e) => tracing::warn!("skipping malformed history line: {}", e),
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the whole history to `dest`.
+    pub fn export(&self, dest: &Path, format: HistoryFormat) -> Result<()> {
+        let records = self.list()?;
+        let contents = match format {
+            HistoryFormat::Json => serde_json::to_string_pretty(&records)?,
+            HistoryFormat::Csv => {
+                let mut csv = String::from(CSV_HEADER);
+                csv.push('\n');
+                for record in &records {
+                    csv.push_str(&record.to_csv_row());
+                    csv.push('\n');
+                }
+                csv
+            }
+        };
+        std::fs::write(dest, contents)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct PeerTransfer {
+    peer: Option<EndpointId>,
+    started_at: u64,
+    started: Instant,
+    size: u64,
+    requests: usize,
+    aborted: bool,
+}
+
+/// Tracks what a share sends over each connection and records it once the connection closes.
+#[derive(Debug)]
+pub(crate) struct ShareLog {
+    history: TransferHistory,
+    /// Hash and file names of the share; a streaming share only knows them once hashed.
+    collection: Mutex<(String, Vec<String>)>,
+    connections: Mutex<HashMap<u64, PeerTransfer>>,
+}
+
+impl ShareLog {
+    pub(crate) fn new(history: TransferHistory, hash: String, files: Vec<String>) -> Self {
+        Self {
+            history,
+            collection: Mutex::new((hash, files)),
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn set_collection(&self, hash: String, files: Vec<String>) {
+        *self.collection.lock().expect("share log poisoned") = (hash, files);
+    }
+
+    pub(crate) fn connected(&self, connection_id: u64, peer: Option<EndpointId>) {
+        self.connections.lock().expect("share log poisoned").insert(
+            connection_id,
+            PeerTransfer {
+                peer,
+                started_at: now_secs(),
+                started: Instant::now(),
+                size: 0,
+                requests: 0,
+                aborted: false,
+            },
+        );
+    }
+
+    pub(crate) fn request_finished(&self, connection_id: u64, stats: &TransferStats, ok: bool) {
+        let mut connections = self.connections.lock().expect("share log poisoned");
+        if let Some(transfer) = connections.get_mut(&connection_id) {
+            transfer.size += stats.payload_bytes_sent;
+            transfer.requests += 1;
+            transfer.aborted |= !ok;
+        }
+    }
+
+    /// Records the connection, unless it never asked for anything (e.g. it was refused).
+    pub(crate) fn closed(&self, connection_id: u64) {
+        let transfer = self
+            .connections
+            .lock()
+            .expect("share log poisoned")
+            .remove(&connection_id);
+        let Some(transfer) = transfer.filter(|t| t.requests > 0) else {
+            return;
+        };
+        let (hash, files) = self.collection.lock().expect("share log poisoned").clone();
+        let record = TransferRecord {
+            direction: TransferDirection::Sent,
+            started_at: transfer.started_at,
+            duration_ms: transfer.started.elapsed().as_millis() as u64,
+            peer: transfer.peer,
+            hash: Some(hash),
+            files,
+            size: transfer.size,
+            outcome: if transfer.aborted {
+                TransferOutcome::Failed
+            } else {
+                TransferOutcome::Completed
+            },
+            error: transfer
+                .aborted
+                .then(|| "the receiver aborted the transfer".to_string()),
+        };
+        if let Err(e) = self.history.append(&record) {
+            tracing::warn!("Failed to record transfer in history: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::receive::download;
+    use crate::core::send::start_share;
+    use crate::core::types::{ReceiveOptions, SendOptions};
+    use std::time::Duration;
+
+    #[test]
+    fn history_roundtrip_and_export() {
+        let td = tempfile::TempDir::new().unwrap();
+        let history = TransferHistory::new(td.path().join("history.jsonl"));
+        assert!(history.list().unwrap().is_empty());
+
+        let record = TransferRecord {
+            direction: TransferDirection::Received,
+            started_at: 1,
+            duration_ms: 20,
+            peer: Some(iroh::SecretKey::generate(&mut rand::rng()).public()),
+            hash: Some("abc".to_string()),
+            files: vec!["a.txt".to_string(), "b, \"c\".txt".to_string()],
+            size: 42,
+            outcome: TransferOutcome::Completed,
+            error: None,
+        };
+        history.append(&record).unwrap();
+        history.append(&record).unwrap();
+        assert_eq!(history.list().unwrap(), vec![record.clone(), record]);
+
+        let csv = td.path().join("history.csv");
+        history.export(&csv, HistoryFormat::Csv).unwrap();
+        let csv = std::fs::read_to_string(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with("received,1,20,"));
+        assert!(lines[1].contains(",\"a.txt;b, \"\"c\"\".txt\",42,completed,"));
+
+        history.clear().unwrap();
+        assert!(history.list().unwrap().is_empty());
+        history.clear().unwrap();
+    }
+
+    #[tokio::test]
+    async fn transfers_are_recorded_in_history() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("report.txt");
+        std::fs::write(&src, "quarterly numbers").unwrap();
+        let sent = TransferHistory::new(td.path().join("sent.jsonl"));
+        let received = TransferHistory::new(td.path().join("received.jsonl"));
+
+        let share = start_share(
+            src,
+            SendOptions {
+                history: Some(sent.path().to_path_buf()),
+                ..SendOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let receiver = iroh::SecretKey::generate(&mut rand::rng());
+        let result = download(
+            share.ticket.clone(),
+            ReceiveOptions {
+                output_dir: Some(td.path().join("out")),
+                secret_key: Some(receiver.clone()),
+                history: Some(received.path().to_path_buf()),
+                ..ReceiveOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.files, vec!["report.txt"]);
+
+        let records = received.list().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].direction, TransferDirection::Received);
+        assert_eq!(records[0].outcome, TransferOutcome::Completed);
+        assert_eq!(records[0].peer, Some(share.router.endpoint().id()));
+        assert_eq!(records[0].hash.as_deref(), Some(share.hash.as_str()));
+
+        // The sender records a receiver once its connection closes
+        share.router.shutdown().await.unwrap();
+        let mut records = Vec::new();
+        for _ in 0..50 {
+            records = sent.list().unwrap();
+            if !records.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].direction, TransferDirection::Sent);
+        assert_eq!(records[0].outcome, TransferOutcome::Completed);
+        assert_eq!(records[0].peer, Some(receiver.public()));
+        assert_eq!(records[0].files, vec!["report.txt"]);
+        // The collection's metadata blobs are sent too
+        assert!(records[0].size >= "quarterly numbers".len() as u64);
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+    }
+}
@@ -0,0 +1,276 @@
+use iroh_blobs::{api::Store, format::collection::Collection, Hash, HashAndFormat};
+use n0_future::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const INDEX_FILE: &str = "import-cache.bin";
+
+/// Prefix of the tags that keep the recent shares of a persistent store.
+const SHARE_TAG_PREFIX: &str = "sendme-share-";
+
+/// How many recent shares a persistent store keeps. Blobs that only older shares used
+/// are garbage collected, and their files forgotten.
+const KEPT_SHARES: usize = 16;
+
+/// The coarsest mtime resolution of common filesystems (FAT). A file modified this
+/// close to its import may have changed again without its mtime moving.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+fn now_ns() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// What a file looked like when it was hashed. If any of it changed, the file is hashed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FileKey {
+    pub(crate) size: u64,
+    mtime_ns: u128,
+    inode: u64,
+}
+
+impl FileKey {
+    pub(crate) fn new(metadata: &Metadata) -> Option<Self> {
+        let mtime_ns = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Self {
+            size: metadata.len(),
+            mtime_ns,
+            inode,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFile {
+    key: FileKey,
+    hash: Hash,
+    /// When the file was hashed, in nanoseconds since the unix epoch.
+    imported_ns: u128,
+}
+
+/// Hashes of the files earlier shares imported into a persistent store, so that
+/// sharing them again does not hash them again.
+///
+/// Entries are keyed by the file's absolute path; the path's raw bytes are used so
+/// that names which are not UTF-8 can be cached too.
+#[derive(Debug, Default)]
+pub(crate) struct ImportCache {
+    index: PathBuf,
+    files: HashMap<Vec<u8>, CachedFile>,
+}
+
+impl ImportCache {
+    /// Loads the index kept in the store directory `dir`. A missing or unreadable
+    /// index is an empty cache.
+    pub(crate) fn load(dir: &Path) -> Self {
+        let index = dir.join(INDEX_FILE);
+        let files = match std::fs::read(&index) {
+            Ok(bytes) => postcard::from_bytes(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("discarding unreadable import cache: {}", e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self { index, files }
+    }
+
+    /// The hash of `path`, if it was imported before and has not changed since.
+    ///
+    /// A file whose mtime was within [`MTIME_GRANULARITY`] of its import is hashed
+    /// again, since a later write in the same tick leaves the mtime as it was.
+    pub(crate) fn get(&self, path: &Path, key: FileKey) -> Option<Hash> {
+        self.files
+            .get(path.as_os_str().as_encoded_bytes())
+            .filter(|cached| cached.key == key)
+            .filter(|cached| key.mtime_ns + MTIME_GRANULARITY.as_nanos() < cached.imported_ns)
+            .map(|cached| cached.hash)
+    }
+
+    pub(crate) fn insert(&mut self, path: &Path, key: FileKey, hash: Hash) {
+        self.files.insert(
+            path.as_os_str().as_encoded_bytes().to_vec(),
+            CachedFile {
+                key,
+                hash,
+                imported_ns: now_ns(),
+            },
+        );
+    }
+
+    /// Tags the collection `hash` as the newest share of the store in `db`, untags all
+    /// but the newest [`KEPT_SHARES`], forgets the files only untagged shares had, and
+    /// saves the index.
+    ///
+    /// The store's garbage collection then drops the blobs of the untagged shares.
+    pub(crate) async fn keep_share(&mut self, db: &Store, hash: Hash) -> anyhow::Result<()> {
+        let tags = db.tags();
+        let mut shares = tags
+            .list_prefix(SHARE_TAG_PREFIX)
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        for share in &shares {
+            let share = share.as_ref().map_err(|e| anyhow::anyhow!("{e}"))?;
+            if share.hash == hash {
+                tags.delete(&share.name).await?;
+            }
+        }
+        // Zero padded, so the tags list oldest first
+        let name = format!("{SHARE_TAG_PREFIX}{:039}-{}", now_ns(), hash.to_hex());
+        tags.set(&name, HashAndFormat::hash_seq(hash)).await?;
+
+        shares = tags
+            .list_prefix(SHARE_TAG_PREFIX)
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        let shares = shares
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        let stale = shares.len().saturating_sub(KEPT_SHARES);
+        for share in &shares[..stale] {
+            tags.delete(&share.name).await?;
+        }
+        let mut kept = HashSet::new();
+        for share in &shares[stale..] {
+            match Collection::load(share.hash, db).await {
+                Ok(collection) => kept.extend(collection.iter().map(|(_, hash)| *hash)),
+                Err(e) => tracing::warn!("Failed to read kept share {}: {}", share.hash, e),
+            }
+        }
+        self.retain(|hash| kept.contains(hash));
+        self.save()
+    }
+
+    /// Forgets the files whose hash `keep` rejects.
+    pub(crate) fn retain(&mut self, keep: impl Fn(&Hash) -> bool) {
+        self.files.retain(|_, cached| keep(&cached.hash));
+    }
+
+    /// Writes the index back, replacing the old one in a single step.
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let tmp = self.index.with_extension("tmp");
+        std::fs::write(&tmp, postcard::to_stdvec(&self.files)?)?;
+        std::fs::rename(&tmp, &self.index)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_cache_detects_changed_files() {
+        let td = tempfile::TempDir::new().unwrap();
+        let file = td.path().join("data.bin");
+        std::fs::write(&file, "v1").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        let key = FileKey::new(&std::fs::metadata(&file).unwrap()).unwrap();
+        let hash = Hash::new(b"v1");
+
+        let mut cache = ImportCache::load(td.path());
+        assert_eq!(cache.get(&file, key), None);
+        cache.insert(&file, key, hash);
+        cache.save().unwrap();
+
+        let cache = ImportCache::load(td.path());
+        assert_eq!(cache.get(&file, key), Some(hash));
+        let grown = FileKey {
+            size: key.size + 1,
+            ..key
+        };
+        assert_eq!(cache.get(&file, grown), None);
+        let touched = FileKey {
+            mtime_ns: key.mtime_ns + 1,
+            ..key
+        };
+        assert_eq!(cache.get(&file, touched), None);
+    }
+
+    #[test]
+    fn import_cache_rehashes_files_modified_around_import() {
+        let td = tempfile::TempDir::new().unwrap();
+        let file = td.path().join("data.bin");
+        std::fs::write(&file, "v1").unwrap();
+        let key = FileKey::new(&std::fs::metadata(&file).unwrap()).unwrap();
+
+        // A write later in the same mtime tick would go unnoticed
+        let mut cache = ImportCache::load(td.path());
+        cache.insert(&file, key, Hash::new(b"v1"));
+        assert_eq!(cache.get(&file, key), None);
+    }
+
+    #[tokio::test]
+    async fn keep_share_forgets_files_of_dropped_shares() {
+        let td = tempfile::TempDir::new().unwrap();
+        let store = iroh_blobs::store::mem::MemStore::new();
+        let key = FileKey {
+            size: 2,
+            mtime_ns: 0,
+            inode: 0,
+        };
+        let mut cache = ImportCache::load(td.path());
+        let mut shares = Vec::new();
+        for i in 0..=KEPT_SHARES {
+            let name = format!("{i}.bin");
+            let blob = store.add_bytes(name.clone().into_bytes()).await.unwrap();
+            cache.insert(&td.path().join(&name), key, blob.hash);
+            let collection = Collection::from_iter([(name, blob.hash)]);
+            let tag = collection.store(&store).await.unwrap();
+            cache.keep_share(&store, tag.hash()).await.unwrap();
+            shares.push(tag);
+        }
+
+        let tags = store
+            .tags()
+            .list_prefix(SHARE_TAG_PREFIX)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(tags.len(), KEPT_SHARES);
+        assert!(tags
+            .iter()
+            .all(|tag| tag.as_ref().unwrap().hash != shares[0].hash()));
+        assert_eq!(cache.files.len(), KEPT_SHARES);
+        assert!(!cache
+            .files
+            .contains_key(td.path().join("0.bin").as_os_str().as_encoded_bytes()));
+
+        // Keeping a share again moves it to the front instead of tagging it twice
+        cache.keep_share(&store, shares[1].hash()).await.unwrap();
+        let tags = store
+            .tags()
+            .list_prefix(SHARE_TAG_PREFIX)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(tags.len(), KEPT_SHARES);
+        let newest = tags.last().unwrap().as_ref().unwrap();
+        assert_eq!(newest.hash, shares[1].hash());
+    }
+}
@@ -0,0 +1,779 @@
+use crate::core::completion::CollectionBlobs;
+use crate::core::contacts::{now_secs, ContactBook};
+use crate::core::error::{Error, Result};
+use crate::core::filename::FilenamePolicy;
+use crate::core::history::{
+    ShareLog, TransferDirection, TransferHistory, TransferOutcome, TransferRecord,
+};
+use crate::core::preflight::Preflight;
+use crate::core::progress::ProgressTracker;
+use crate::core::provenance::Provenance;
+use crate::core::proxy::apply_proxy;
+use crate::core::receive::{export, fetch_missing};
+use crate::core::receivers::Receivers;
+use crate::core::send::{
+    emit_share_warnings, import, show_provide_progress_with_logging, ConnectionGate,
+};
+use crate::core::store::load_collected;
+use crate::core::types::{
+    apply_options, endpoint_builder, get_or_create_secret, AppHandle, InboxOptions, InboxResult,
+    InboxTransferRecord, PushResult, SendOptions,
+};
+use data_encoding::HEXLOWER;
+use iroh::{
+    endpoint::{ApplicationClose, Connection, ConnectionError, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+    EndpointAddr, EndpointId, RelayMode, Watcher,
+};
+use iroh_blobs::{
+    api::Store,
+    format::collection::Collection,
+    provider::events::{ConnectMode, EventMask, EventSender, RequestMode},
+    store::fs::FsStore,
+    Hash, HashAndFormat,
+};
+use iroh_tickets::{ParseError, Ticket};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+/// ALPN for pushing a collection into an inbox.
+///
+/// The sender opens the connection and offers a collection hash; the inbox then
+/// fetches it over that same connection with ordinary blob get requests, so pushed
+/// data is verified exactly like a regular download.
+pub const PUSH_ALPN: &[u8] = b"/sendme/push/0";
+
+/// Close code the inbox uses once a pushed collection has been stored and exported.
+const PUSH_DONE: VarInt = VarInt::from_u32(0);
+/// Close code the inbox uses when it could not take the pushed collection.
+const PUSH_FAILED: VarInt = VarInt::from_u32(1);
+/// Close code the inbox uses for senders that are not on its allow list.
+const PUSH_REJECTED: VarInt = VarInt::from_u32(2);
+
+/// The offer a sender writes on a unidirectional stream right after connecting.
+#[derive(Debug, Serialize, Deserialize)]
+struct PushOffer {
+    hash: Hash,
+}
+
+/// A ticket pointing at a receiver's inbox. Unlike a blob ticket it carries no hash,
+/// since the sender picks what to push.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+#[display("{}", Ticket::serialize(self))]
+pub struct InboxTicket {
+    addr: EndpointAddr,
+}
+
+impl InboxTicket {
+    pub fn new(addr: EndpointAddr) -> Self {
+        Self { addr }
+    }
+
+    pub fn addr(&self) -> &EndpointAddr {
+        &self.addr
+    }
+}
+
+impl Ticket for InboxTicket {
+    const KIND: &'static str = "inbox";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(&self.addr).expect("postcard serialization failed")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let addr = postcard::from_bytes(bytes)?;
+        Ok(Self { addr })
+    }
+}
+
+impl FromStr for InboxTicket {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ticket::deserialize(s)
+    }
+}
+
+fn emit_event(app_handle: &AppHandle, event_name: &str) {
+    if let Some(handle) = app_handle {
+        if let Err(e) = handle.emit_event(event_name) {
+            tracing::warn!("Failed to emit event {}: {}", event_name, e);
+        }
+    }
+}
+
+fn emit_event_with_payload(app_handle: &AppHandle, event_name: &str, payload: &str) {
+    if let Some(handle) = app_handle {
+        if let Err(e) = handle.emit_event_with_payload(event_name, payload) {
+            tracing::warn!("Failed to emit event {} with payload: {}", event_name, e);
+        }
+    }
+}
+
+/// Waits until the endpoint is reachable: via its home relay, or, with relays
+/// disabled, once it knows at least one direct address.
+pub(crate) async fn wait_reachable(endpoint: &iroh::Endpoint, relay_mode: &RelayMode) {
+    if !matches!(relay_mode, RelayMode::Disabled) {
+        endpoint.online().await;
+        return;
+    }
+    let mut addrs = endpoint.watch_addr();
+    while addrs.get().ip_addrs().next().is_none() {
+        if addrs.updated().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Accepts pushes on [`PUSH_ALPN`] and stores them in the inbox.
+#[derive(Clone)]
+struct InboxProtocol {
+    store: Store,
+    store_dir: PathBuf,
+    output_dir: PathBuf,
+    allowed_peers: Option<Arc<HashSet<EndpointId>>>,
+    contacts: Option<PathBuf>,
+    transfer_log: Option<PathBuf>,
+    history: Option<TransferHistory>,
+    tag_origin: bool,
+    max_total_bytes: Option<u64>,
+    max_files: Option<u64>,
+    app_handle: AppHandle,
+}
+
+impl std::fmt::Debug for InboxProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InboxProtocol")
+            .field("output_dir", &self.output_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProtocolHandler for InboxProtocol {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let remote = connection.remote_id();
+        if !self.trusts(&remote) {
+            tracing::warn!("rejecting push from untrusted endpoint {}", remote);
+            emit_event_with_payload(&self.app_handle, "inbox-push-rejected", &remote.to_string());
+            connection.close(PUSH_REJECTED, b"not a trusted device");
+            return Ok(());
+        }
+        let started_at = now_secs();
+        let started = Instant::now();
+        let result = self.receive_push(&connection).await.map_err(Error::from);
+        if let Some(history) = &self.history {
+            let duration_ms = started.elapsed().as_millis() as u64;
+            let record = match &result {
+                Ok(received) => TransferRecord {
+                    direction: TransferDirection::Received,
+                    started_at,
+                    duration_ms,
+                    peer: Some(remote),
+                    hash: Some(received.hash.clone()),
+                    files: received.files.clone(),
+                    size: received.size,
+                    outcome: TransferOutcome::Completed,
+                    error: None,
+                },
+                Err(e) => TransferRecord {
+                    direction: TransferDirection::Received,
+                    started_at,
+                    duration_ms,
+                    peer: Some(remote),
+                    hash: None,
+                    files: Vec::new(),
+                    size: 0,
+                    outcome: TransferOutcome::Failed,
+                    error: Some(format!("{}: {}", e.code(), e)),
+                },
+            };
+            if let Err(e) = history.append(&record) {
+                tracing::warn!("Failed to record transfer in history: {}", e);
+            }
+        }
+        match result {
+            Ok(record) => {
+                connection.close(PUSH_DONE, b"done");
+                if let Some(path) = &self.transfer_log {
+                    if let Err(e) = append_transfer_record(path, &record).await {
+                        tracing::warn!("Failed to log transfer to {}: {}", path.display(), e);
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                tracing::error!("push from {} failed: {}", remote, e);
+                emit_event_with_payload(&self.app_handle, "receive-failed", &e.to_string());
+                let reason = e.to_string();
+                connection.close(PUSH_FAILED, &reason.as_bytes()[..reason.len().min(512)]);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl InboxProtocol {
+    /// Whether `remote` may push here: it is one of the allowed peers or, as the contact
+    /// book is right now, a contact. With neither set, anyone holding the ticket may.
+    fn trusts(&self, remote: &EndpointId) -> bool {
+        if self.allowed_peers.is_none() && self.contacts.is_none() {
+            return true;
+        }
+        if let Some(allowed_peers) = &self.allowed_peers {
+            if allowed_peers.contains(remote) {
+                return true;
+            }
+        }
+        match &self.contacts {
+            Some(path) => match ContactBook::load(path) {
+                Ok(contacts) => contacts.get(remote).is_some(),
+                Err(e) => {
+                    tracing::warn!("Failed to read contacts from {}: {}", path.display(), e);
+                    false
+                }
+            },
+            None => false,
+        }
+    }
+
+    async fn receive_push(&self, connection: &Connection) -> anyhow::Result<InboxTransferRecord> {
+        let mut recv = connection.accept_uni().await?;
+        let offer: PushOffer = postcard::from_bytes(&recv.read_to_end(1024).await?)?;
+        let hash_and_format = HashAndFormat::hash_seq(offer.hash);
+        tracing::info!("push of {} from {}", offer.hash, connection.remote_id());
+
+        emit_event(&self.app_handle, "receive-started");
+        // The store collects whatever no tag keeps, so the push is only kept until exported
+        let _keep = self.store.tags().temp_tag(hash_and_format).await?;
+        let local = self.store.remote().local(hash_and_format).await?;
+        if !local.is_complete() {
+            fetch_missing(
+                &self.store,
+                connection.clone(),
+                hash_and_format,
+                &local,
+                &Preflight {
+                    store_dir: self.store_dir.clone(),
+                    output_dir: self.output_dir.clone(),
+                    max_total_bytes: self.max_total_bytes,
+                    max_files: self.max_files,
+                },
+                &mut ProgressTracker::new(self.app_handle.clone(), "receive-progress", None),
+            )
+            .await?;
+        }
+
+        let collection = Collection::load(offer.hash, &self.store).await?;
+        let file_names = collection
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        let file_names_json =
+            serde_json::to_string(&file_names).unwrap_or_else(|_| "[]".to_string());
+        emit_event_with_payload(&self.app_handle, "receive-file-names", &file_names_json);
+
+        let size = export(
+            &self.store,
+            collection,
+            &self.output_dir,
+            FilenamePolicy::default(),
+            false,
+            self.tag_origin
+                .then(|| Provenance::new(offer.hash, connection.remote_id())),
+        )
+        .await?;
+        emit_event(&self.app_handle, "receive-completed");
+        Ok(InboxTransferRecord {
+            received_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            peer: connection.remote_id().to_string(),
+            hash: offer.hash.to_hex().to_string(),
+            files: file_names,
+            size,
+            output_dir: self.output_dir.clone(),
+        })
+    }
+}
+
+async fn append_transfer_record(path: &Path, record: &InboxTransferRecord) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Reads back the records an inbox appended to its transfer log, oldest first.
+pub fn read_transfer_log(path: &Path) -> Result<Vec<InboxTransferRecord>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = std::fs::read_to_string(path)?;
+    let mut records = Vec::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(e) => tracing::warn!("skipping malformed transfer log line: {}", e),
+        }
+    }
+    Ok(records)
+}
+
+/// Starts an inbox that accepts pushed collections and exports them to the output directory.
+pub async fn start_inbox(options: InboxOptions, app_handle: AppHandle) -> Result<InboxResult> {
+    bind_inbox(options, app_handle).await.map_err(Error::from)
+}
+
+async fn bind_inbox(options: InboxOptions, app_handle: AppHandle) -> anyhow::Result<InboxResult> {
+    let secret_key = match options.secret_key.clone() {
+        Some(secret_key) => secret_key,
+        None => get_or_create_secret()?,
+    };
+    let relay_mode: RelayMode = options.relay_mode.clone().into();
+
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
+        .alpns(vec![PUSH_ALPN.to_vec()])
+        .secret_key(secret_key);
+    if let Some(addr) = options.magic_ipv4_addr {
+        builder = builder.bind_addr_v4(addr);
+    }
+    if let Some(addr) = options.magic_ipv6_addr {
+        builder = builder.bind_addr_v6(addr);
+    }
+    let (builder, proxy_handle) = apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
+
+    let suffix = rand::rng().random::<[u8; 16]>();
+    let blobs_data_dir =
+        std::env::temp_dir().join(format!(".sendme-inbox-{}", HEXLOWER.encode(&suffix)));
+    tokio::fs::create_dir_all(&blobs_data_dir).await?;
+    let store = load_collected(&blobs_data_dir).await?;
+
+    let output_dir = options.output_dir.unwrap_or_else(|| {
+        dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap())
+    });
+
+    let endpoint = builder.bind().await?;
+    let router = iroh::protocol::Router::builder(endpoint)
+        .accept(
+            PUSH_ALPN,
+            InboxProtocol {
+                store: (*store).clone(),
+                store_dir: blobs_data_dir.clone(),
+                output_dir: output_dir.clone(),
+                allowed_peers: options.allowed_peers.map(Arc::new),
+                contacts: options.contacts,
+                transfer_log: options.transfer_log,
+                history: options.history.map(TransferHistory::new),
+                tag_origin: options.tag_origin,
+                max_total_bytes: options.max_total_bytes,
+                max_files: options.max_files,
+                app_handle,
+            },
+        )
+        .spawn();
+
+    let ep = router.endpoint();
+    tokio::time::timeout(Duration::from_secs(30), wait_reachable(ep, &relay_mode)).await?;
+
+    let mut addr = ep.addr();
+    apply_options(&mut addr, options.ticket_type);
+    let ticket = InboxTicket::new(addr);
+
+    Ok(InboxResult {
+        ticket: ticket.to_string(),
+        output_dir,
+        router,
+        blobs_data_dir,
+        _store: store,
+        _proxy_handle: proxy_handle,
+    })
+}
+
+/// Imports `path` and pushes it into the inbox behind `ticket_str`.
+///
+/// Returns once the inbox has confirmed that it stored the collection.
+pub async fn push(
+    path: PathBuf,
+    ticket_str: String,
+    options: SendOptions,
+    app_handle: AppHandle,
+) -> Result<PushResult> {
+    push_collection(path, ticket_str, options, app_handle)
+        .await
+        .map_err(Error::from)
+}
+
+async fn push_collection(
+    path: PathBuf,
+    ticket_str: String,
+    options: SendOptions,
+    app_handle: AppHandle,
+) -> anyhow::Result<PushResult> {
+    let ticket = InboxTicket::from_str(ticket_str.trim())
+        .map_err(|e| Error::InvalidTicket(e.to_string()))?;
+    let secret_key = match options.secret_key.clone() {
+        Some(secret_key) => secret_key,
+        None => get_or_create_secret()?,
+    };
+
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
+        .alpns(vec![])
+        .secret_key(secret_key);
+    if let Some(addr) = options.magic_ipv4_addr {
+        builder = builder.bind_addr_v4(addr);
+    }
+    if let Some(addr) = options.magic_ipv6_addr {
+        builder = builder.bind_addr_v6(addr);
+    }
+    let (builder, _proxy_handle) =
+        apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
+
+    let history = options.history.clone().map(TransferHistory::new);
+    let suffix = rand::rng().random::<[u8; 16]>();
+    let blobs_data_dir =
+        std::env::temp_dir().join(format!(".sendme-send-{}", HEXLOWER.encode(&suffix)));
+    tokio::fs::create_dir_all(&blobs_data_dir).await?;
+    let store = FsStore::load(&blobs_data_dir).await?;
+
+    let result =
+        async {
+            let (temp_tag, size, collection, skipped) =
+                import(path, &store, options.strict, None, None).await?;
+            emit_share_warnings(&app_handle, &skipped);
+            let hash = temp_tag.hash();
+            let share_log = history.map(|history| {
+                let files = collection.iter().map(|(name, _)| name.clone()).collect();
+                Arc::new(ShareLog::new(history, hash.to_hex().to_string(), files))
+            });
+
+            let endpoint = builder.bind().await?;
+            let connection = endpoint
+                .connect(ticket.addr().clone(), PUSH_ALPN)
+                .await
+                .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+
+            let mut send = connection.open_uni().await?;
+            send.write_all(&postcard::to_stdvec(&PushOffer { hash })?)
+                .await?;
+            send.finish()?;
+
+            // Serve the inbox's get requests on this connection until it hangs up.
+            let collection_blobs = CollectionBlobs::default();
+            collection_blobs.set(&store, hash).await?;
+            let (progress_tx, progress_rx) = mpsc::channel(32);
+            let progress = n0_future::task::spawn(show_provide_progress_with_logging(
+                progress_rx,
+                app_handle.clone(),
+                size,
+                collection_blobs,
+                ConnectionGate::default(),
+                share_log,
+                Receivers::new(app_handle.clone(), options.progress_interval),
+            ));
+            iroh_blobs::provider::handle_connection(
+                connection.clone(),
+                (*store).clone(),
+                EventSender::new(
+                    progress_tx,
+                    EventMask {
+                        connected: ConnectMode::Notify,
+                        get: RequestMode::NotifyLog,
+                        ..EventMask::DEFAULT
+                    },
+                ),
+            )
+            .await;
+            // Read the inbox's verdict before closing the endpoint overwrites it.
+            let close_reason = connection.close_reason();
+            progress.await??;
+            endpoint.close().await;
+
+            match close_reason {
+                Some(ConnectionError::ApplicationClosed(ApplicationClose {
+                    error_code, ..
+                })) if error_code == PUSH_REJECTED => Err(Error::Unauthorized(
+                    "this device is not trusted by the receiver".to_string(),
+                )
+                .into()),
+                Some(ConnectionError::ApplicationClosed(ApplicationClose {
+                    error_code, ..
+                })) if error_code == PUSH_DONE => anyhow::Ok(PushResult {
+                    hash: hash.to_hex().to_string(),
+                    size,
+                    skipped,
+                }),
+                Some(ConnectionError::ApplicationClosed(ApplicationClose { reason, .. })) => {
+                    Err(Error::PeerAborted(format!(
+                        "inbox rejected push: {}",
+                        String::from_utf8_lossy(&reason)
+                    ))
+                    .into())
+                }
+                other => Err(Error::PeerAborted(format!("push interrupted: {:?}", other)).into()),
+            }
+        }
+        .await;
+
+    drop(store);
+    tokio::fs::remove_dir_all(&blobs_data_dir).await.ok();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::contacts::Contact;
+    use crate::core::types::{DiscoveryOption, ProxyOption, RelayModeOption};
+    use crate::AddrInfoOptions;
+
+    #[test]
+    fn inbox_ticket_roundtrip() {
+        let key = iroh::SecretKey::generate(&mut rand::rng());
+        let addr = EndpointAddr::new(key.public()).with_ip_addr("127.0.0.1:4433".parse().unwrap());
+        let ticket = InboxTicket::new(addr);
+        let s = ticket.to_string();
+        assert!(s.starts_with("inbox"));
+        assert_eq!(InboxTicket::from_str(&s).unwrap(), ticket);
+        assert!(iroh_blobs::ticket::BlobTicket::from_str(&s).is_err());
+    }
+
+    #[tokio::test]
+    async fn push_into_inbox_exports_files() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("project");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("a.txt"), "alpha").unwrap();
+        std::fs::write(src.join("sub/b.txt"), "beta").unwrap();
+        let out = td.path().join("out");
+        std::fs::create_dir_all(&out).unwrap();
+
+        let inbox = start_inbox(
+            InboxOptions {
+                output_dir: Some(out.clone()),
+                relay_mode: RelayModeOption::Disabled,
+                ticket_type: AddrInfoOptions::Addresses,
+                discovery: DiscoveryOption::Disabled,
+                proxy: ProxyOption::Disabled,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        let result = push(src, inbox.ticket.clone(), SendOptions::local(), None)
+            .await
+            .unwrap();
+        assert_eq!(result.size, 9);
+
+        // push only returns once the inbox has exported everything
+        assert_eq!(
+            std::fs::read_to_string(out.join("project/sub/b.txt")).unwrap(),
+            "beta"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("project/a.txt")).unwrap(),
+            "alpha"
+        );
+
+        inbox.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&inbox.blobs_data_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn inbox_applies_limits_and_drops_exported_blobs() {
+        let td = tempfile::TempDir::new().unwrap();
+        let small = td.path().join("small.txt");
+        std::fs::write(&small, "fits").unwrap();
+        let big = td.path().join("big.bin");
+        std::fs::write(&big, vec![7u8; 10_000]).unwrap();
+        let out = td.path().join("out");
+        std::fs::create_dir_all(&out).unwrap();
+
+        let inbox = start_inbox(
+            InboxOptions {
+                output_dir: Some(out.clone()),
+                max_total_bytes: Some(1_000),
+                relay_mode: RelayModeOption::Disabled,
+                ticket_type: AddrInfoOptions::Addresses,
+                discovery: DiscoveryOption::Disabled,
+                proxy: ProxyOption::Disabled,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        let err = push(big, inbox.ticket.clone(), SendOptions::local(), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("limits"), "{err}");
+        assert!(!out.join("big.bin").exists());
+
+        push(small, inbox.ticket.clone(), SendOptions::local(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("small.txt")).unwrap(),
+            "fits"
+        );
+        // Nothing keeps the exported push, so the next garbage collection drops it
+        let store = inbox._store.clone();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !store.blobs().list().hashes().await.unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("the pushed blobs were not collected");
+
+        inbox.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&inbox.blobs_data_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn inbox_only_accepts_trusted_devices() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("drop.txt");
+        std::fs::write(&src, "payload").unwrap();
+        let out = td.path().join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        let log = td.path().join("log.jsonl");
+        let history = td.path().join("history.jsonl");
+
+        let trusted = iroh::SecretKey::generate(&mut rand::rng());
+        let stranger = iroh::SecretKey::generate(&mut rand::rng());
+        let inbox = start_inbox(
+            InboxOptions {
+                output_dir: Some(out.clone()),
+                allowed_peers: Some([trusted.public()].into_iter().collect()),
+                transfer_log: Some(log.clone()),
+                history: Some(history.clone()),
+                relay_mode: RelayModeOption::Disabled,
+                ticket_type: AddrInfoOptions::Addresses,
+                discovery: DiscoveryOption::Disabled,
+                proxy: ProxyOption::Disabled,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let send_options = |key: &iroh::SecretKey| SendOptions {
+            secret_key: Some(key.clone()),
+            ..SendOptions::local()
+        };
+
+        let err = push(
+            src.clone(),
+            inbox.ticket.clone(),
+            send_options(&stranger),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), "unauthorized");
+        assert!(err.to_string().contains("not trusted"), "{err}");
+        assert!(!out.join("drop.txt").exists());
+        assert!(read_transfer_log(&log).unwrap().is_empty());
+
+        push(src, inbox.ticket.clone(), send_options(&trusted), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("drop.txt")).unwrap(),
+            "payload"
+        );
+        let records = read_transfer_log(&log).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].peer, trusted.public().to_string());
+        assert_eq!(records[0].files, vec!["drop.txt".to_string()]);
+        assert_eq!(records[0].size, 7);
+        // Only the trusted push made it into the history
+        let records = TransferHistory::new(&history).list().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].direction, TransferDirection::Received);
+        assert_eq!(records[0].outcome, TransferOutcome::Completed);
+        assert_eq!(records[0].peer, Some(trusted.public()));
+        assert_eq!(records[0].files, vec!["drop.txt".to_string()]);
+
+        inbox.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&inbox.blobs_data_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn inbox_accepts_devices_paired_after_it_started() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("drop.txt");
+        std::fs::write(&src, "payload").unwrap();
+        let out = td.path().join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        let contacts = td.path().join("contacts.json");
+
+        let device = iroh::SecretKey::generate(&mut rand::rng());
+        let inbox = start_inbox(
+            InboxOptions {
+                output_dir: Some(out.clone()),
+                allowed_peers: Some(HashSet::new()),
+                contacts: Some(contacts.clone()),
+                relay_mode: RelayModeOption::Disabled,
+                ticket_type: AddrInfoOptions::Addresses,
+                discovery: DiscoveryOption::Disabled,
+                proxy: ProxyOption::Disabled,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let send_options = || SendOptions {
+            secret_key: Some(device.clone()),
+            ..SendOptions::local()
+        };
+
+        let err = push(src.clone(), inbox.ticket.clone(), send_options(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "unauthorized");
+
+        let mut book = ContactBook::load(&contacts).unwrap();
+        book.upsert(Contact {
+            nickname: "laptop".to_string(),
+            endpoint_id: device.public(),
+            last_seen: 0,
+        });
+        book.save().unwrap();
+        push(src, inbox.ticket.clone(), send_options(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("drop.txt")).unwrap(),
+            "payload"
+        );
+
+        inbox.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&inbox.blobs_data_dir).ok();
+    }
+}
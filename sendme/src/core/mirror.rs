@@ -0,0 +1,1035 @@
+use crate::core::completion::CollectionBlobs;
+use crate::core::error::{Error, Result};
+use crate::core::filename::{export_paths, FilenamePolicy};
+use crate::core::history::{ShareLog, TransferHistory};
+use crate::core::import_cache::{FileKey, ImportCache};
+use crate::core::inbox::wait_reachable;
+use crate::core::preflight::Preflight;
+use crate::core::progress::ProgressTracker;
+use crate::core::proxy::apply_proxy;
+use crate::core::receive::{export_blob, fetch_missing};
+use crate::core::receivers::Receivers;
+use crate::core::send::{
+    emit_share_warnings, import, show_provide_progress_with_logging, ConnectionGate,
+};
+use crate::core::store::load_collected;
+use crate::core::streaming::{read_message, write_message};
+use crate::core::types::{
+    apply_options, endpoint_builder, get_or_create_secret, AppHandle, MirrorOptions, MirrorResult,
+    MirrorSubscription, ReceiveOptions, SendOptions,
+};
+use crate::core::verify::hash_file;
+use data_encoding::HEXLOWER;
+use iroh::{
+    endpoint::{Connection, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointAddr, EndpointId, RelayMode,
+};
+use iroh_blobs::{
+    api::{Store, TempTag},
+    format::collection::Collection,
+    provider::events::{ConnectMode, EventMask, EventSender, RequestMode},
+    store::fs::FsStore,
+    BlobsProtocol, Hash, HashAndFormat,
+};
+use iroh_tickets::{ParseError, Ticket};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{mpsc, watch};
+use walkdir::WalkDir;
+
+/// ALPN on which a mirror announces each new version of its folder.
+///
+/// Subscribers get the current version right after connecting and every later one as
+/// it is published; they fetch the versions themselves over the blobs ALPN.
+pub const MIRROR_ALPN: &[u8] = b"/sendme/mirror/0";
+
+/// Close code for subscribers the mirror does not serve.
+const MIRROR_REJECTED: VarInt = VarInt::from_u32(2);
+
+/// How often a mirror checks its folder when [`MirrorOptions::poll_interval`] is unset.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long a subscriber waits before reconnecting to a mirror it lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// What a subscriber last applied, kept in the target directory.
+const STATE_FILE: &str = ".sendme-mirror.json";
+/// Conflicts a subscriber ran into, one JSON record per line, kept in the target directory.
+const CONFLICTS_FILE: &str = ".sendme-mirror-conflicts.jsonl";
+
+/// A version of the mirrored folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct MirrorVersion {
+    /// Counts up from 1 with every change the mirror publishes.
+    version: u64,
+    hash: Hash,
+}
+
+/// A ticket for subscribing to a mirror. It carries no hash, since the mirrored folder
+/// changes over time.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+#[display("{}", Ticket::serialize(self))]
+pub struct MirrorTicket {
+    addr: EndpointAddr,
+}
+
+impl MirrorTicket {
+    pub fn new(addr: EndpointAddr) -> Self {
+        Self { addr }
+    }
+
+    pub fn addr(&self) -> &EndpointAddr {
+        &self.addr
+    }
+}
+
+impl Ticket for MirrorTicket {
+    const KIND: &'static str = "mirror";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(&self.addr).expect("postcard serialization failed")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, ParseError> {
+        let addr = postcard::from_bytes(bytes)?;
+        Ok(Self { addr })
+    }
+}
+
+impl FromStr for MirrorTicket {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ticket::deserialize(s)
+    }
+}
+
+/// A file the subscriber left alone because changing it would lose local changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorConflict {
+    /// The version whose changes could not be applied.
+    pub version: u64,
+    /// Name of the file in the collection.
+    pub name: String,
+    pub reason: String,
+}
+
+/// What applying a version of the mirror changes in the target directory.
+///
+/// Names are collection names, relative to the target directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorPlan {
+    pub version: u64,
+    /// Hex collection hash of the version.
+    pub hash: String,
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    /// Files that moved, as `(from, to)`. They are moved rather than fetched again.
+    pub renamed: Vec<(String, String)>,
+    pub deleted: Vec<String>,
+    pub conflicts: Vec<MirrorConflict>,
+    /// The plan was only worked out, not applied.
+    pub dry_run: bool,
+}
+
+/// Reads back the conflicts a subscriber recorded in `target`, oldest first.
+pub fn mirror_conflicts(target: &Path) -> Result<Vec<MirrorConflict>> {
+    let path = target.join(CONFLICTS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut conflicts = Vec::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(conflict) => conflicts.push(conflict),
+            Err(e) => tracing::warn!("skipping malformed conflict record: {}", e),
+        }
+    }
+    Ok(conflicts)
+}
+
+fn emit_event_with_payload(app_handle: &AppHandle, event_name: &str, payload: &str) {
+    if let Some(handle) = app_handle {
+        if let Err(e) = handle.emit_event_with_payload(event_name, payload) {
+            tracing::warn!("Failed to emit event {}: {}", event_name, e);
+        }
+    }
+}
+
+fn emit_plan(app_handle: &AppHandle, event_name: &str, plan: &MirrorPlan) {
+    match serde_json::to_string(plan) {
+        Ok(payload) => emit_event_with_payload(app_handle, event_name, &payload),
+        Err(e) => tracing::warn!("Failed to serialize mirror plan: {}", e),
+    }
+}
+
+/// Serves [`MIRROR_ALPN`]: tells each subscriber about every version as it is published.
+#[derive(Debug, Clone)]
+struct MirrorProtocol {
+    versions: watch::Sender<Option<MirrorVersion>>,
+    gate: ConnectionGate,
+}
+
+impl MirrorProtocol {
+    async fn announce(&self, connection: &Connection) -> anyhow::Result<()> {
+        let mut send = connection.open_uni().await?;
+        let mut versions = self.versions.subscribe();
+        loop {
+            let version = *versions.borrow_and_update();
+            if let Some(version) = version {
+                write_message(&mut send, &version).await?;
+            }
+            if versions.changed().await.is_err() {
+                // The mirror stopped
+                send.finish()?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl ProtocolHandler for MirrorProtocol {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        if !self.gate.allows(Some(connection.remote_id())) {
+            connection.close(MIRROR_REJECTED, b"not allowed");
+            return Ok(());
+        }
+        if let Err(e) = self.announce(&connection).await {
+            tracing::debug!("subscriber {} went away: {}", connection.remote_id(), e);
+        }
+        Ok(())
+    }
+}
+
+/// Size, modification time and inode of every file under `path`. Any change in it
+/// means the folder has to be imported again.
+fn folder_key(path: &Path) -> Vec<(PathBuf, Option<FileKey>)> {
+    let mut key = WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_type().is_dir())
+        .map(|entry| {
+            let file_key = entry.metadata().ok().and_then(|m| FileKey::new(&m));
+            (entry.into_path(), file_key)
+        })
+        .collect::<Vec<_>>();
+    key.sort_by(|(a, _), (b, _)| a.cmp(b));
+    key
+}
+
+/// Publishes a new version of a mirrored folder whenever its contents change.
+struct FolderWatch {
+    path: PathBuf,
+    store: Store,
+    strict: bool,
+    cache: Option<ImportCache>,
+    versions: watch::Sender<Option<MirrorVersion>>,
+    /// The blobs of the published version, which subscribers complete on.
+    collection_blobs: CollectionBlobs,
+    share_log: Option<Arc<ShareLog>>,
+    app_handle: AppHandle,
+    /// The published version and the one before it, which subscribers may still be fetching.
+    tags: (Option<TempTag>, Option<TempTag>),
+    key: Vec<(PathBuf, Option<FileKey>)>,
+}
+
+impl FolderWatch {
+    /// Imports the folder and publishes it if it is not the current version.
+    /// Returns the payload size.
+    async fn publish(&mut self) -> anyhow::Result<u64> {
+        let key = folder_key(&self.path);
+        let (temp_tag, size, collection, skipped) = import(
+            self.path.clone(),
+            &self.store,
+            self.strict,
+            self.cache.as_mut(),
+            None,
+        )
+        .await?;
+        self.key = key;
+        emit_share_warnings(&self.app_handle, &skipped);
+        let hash = temp_tag.hash();
+        if self.tags.0.as_ref().map(TempTag::hash) == Some(hash) {
+            return Ok(size);
+        }
+
+        let version = self.versions.borrow().map_or(0, |v| v.version) + 1;
+        if let Some(share_log) = &self.share_log {
+            let files = collection.iter().map(|(name, _)| name.clone()).collect();
+            share_log.set_collection(hash.to_hex().to_string(), files);
+        }
+        self.collection_blobs.set(&self.store, hash).await?;
+        self.tags = (Some(temp_tag), self.tags.0.take());
+        self.versions
+            .send_replace(Some(MirrorVersion { version, hash }));
+        tracing::info!("published version {} of {}", version, self.path.display());
+        let payload = serde_json::json!({
+            "version": version,
+            "hash": hash.to_hex().to_string(),
+            "files": collection.len(),
+            "size": size,
+        });
+        emit_event_with_payload(&self.app_handle, "mirror-published", &payload.to_string());
+        Ok(size)
+    }
+
+    async fn run(mut self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if folder_key(&self.path) == self.key {
+                continue;
+            }
+            if let Err(e) = self.publish().await {
+                // The folder is checked again on the next tick
+                tracing::warn!("Failed to publish {}: {}", self.path.display(), e);
+                emit_event_with_payload(&self.app_handle, "mirror-error", &e.to_string());
+            }
+        }
+    }
+}
+
+/// Mirrors the folder at `path`: publishes a new version whenever its contents change,
+/// for subscribers to apply with [`subscribe_mirror`].
+///
+/// With [`SendOptions::store_dir`], files that did not change are not hashed again,
+/// not even across restarts. Passphrases and streaming are not supported and ignored.
+pub async fn start_mirror(
+    path: PathBuf,
+    options: SendOptions,
+    mirror: MirrorOptions,
+    app_handle: AppHandle,
+) -> Result<MirrorResult> {
+    serve_mirror(path, options, mirror, app_handle)
+        .await
+        .map_err(Error::from)
+}
+
+async fn serve_mirror(
+    path: PathBuf,
+    options: SendOptions,
+    mirror: MirrorOptions,
+    app_handle: AppHandle,
+) -> anyhow::Result<MirrorResult> {
+    if !path.is_dir() {
+        return Err(Error::path_rejected(path.display().to_string(), "not a directory").into());
+    }
+    let secret_key = match options.secret_key.clone() {
+        Some(secret_key) => secret_key,
+        None => get_or_create_secret()?,
+    };
+    let relay_mode: RelayMode = options.relay_mode.clone().into();
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
+        .alpns(vec![
+            iroh_blobs::protocol::ALPN.to_vec(),
+            MIRROR_ALPN.to_vec(),
+        ])
+        .secret_key(secret_key);
+    if let Some(addr) = options.magic_ipv4_addr {
+        builder = builder.bind_addr_v4(addr);
+    }
+    if let Some(addr) = options.magic_ipv6_addr {
+        builder = builder.bind_addr_v6(addr);
+    }
+    let (builder, proxy_handle) = apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
+
+    let persistent_store = options.store_dir.is_some();
+    let blobs_data_dir = match &options.store_dir {
+        Some(store_dir) => store_dir.clone(),
+        None => {
+            let suffix = rand::rng().random::<[u8; 16]>();
+            std::env::temp_dir().join(format!(".sendme-mirror-{}", HEXLOWER.encode(&suffix)))
+        }
+    };
+    tokio::fs::create_dir_all(&blobs_data_dir).await?;
+    // A persistent store drops the versions it no longer keeps
+    let store = if persistent_store {
+        load_collected(&blobs_data_dir).await?
+    } else {
+        FsStore::load(&blobs_data_dir).await?
+    };
+
+    let gate = ConnectionGate::new(options.allowed_peers.clone());
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let blobs = BlobsProtocol::new(
+        &store,
+        Some(EventSender::new(
+            progress_tx,
+            EventMask {
+                connected: if gate.is_restricted() {
+                    ConnectMode::Intercept
+                } else {
+                    ConnectMode::Notify
+                },
+                get: RequestMode::NotifyLog,
+                ..EventMask::DEFAULT
+            },
+        )),
+    );
+
+    let share_log = options.history.clone().map(|history| {
+        Arc::new(ShareLog::new(
+            TransferHistory::new(history),
+            String::new(),
+            Vec::new(),
+        ))
+    });
+    let versions = watch::Sender::new(None);
+    let collection_blobs = CollectionBlobs::default();
+    let mut watch = FolderWatch {
+        path: path.canonicalize()?,
+        store: blobs.store().clone(),
+        strict: options.strict,
+        cache: persistent_store.then(|| ImportCache::load(&blobs_data_dir)),
+        versions: versions.clone(),
+        collection_blobs: collection_blobs.clone(),
+        share_log: share_log.clone(),
+        app_handle: app_handle.clone(),
+        tags: (None, None),
+        key: Vec::new(),
+    };
+    // The first version is published before the ticket is handed out
+    let size = watch.publish().await?;
+
+    let progress_handle = n0_future::task::spawn(show_provide_progress_with_logging(
+        progress_rx,
+        app_handle.clone(),
+        size,
+        collection_blobs,
+        gate.clone(),
+        share_log,
+        Receivers::new(app_handle.clone(), options.progress_interval),
+    ));
+    let router = iroh::protocol::Router::builder(builder.bind().await?)
+        .accept(iroh_blobs::ALPN, blobs)
+        .accept(MIRROR_ALPN, MirrorProtocol { versions, gate })
+        .spawn();
+    let ep = router.endpoint();
+    tokio::time::timeout(Duration::from_secs(30), wait_reachable(ep, &relay_mode)).await?;
+    let mut addr = ep.addr();
+    apply_options(&mut addr, options.ticket_type);
+
+    let interval = mirror.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+    let watch_handle = n0_future::task::spawn(watch.run(interval));
+
+    Ok(MirrorResult {
+        ticket: MirrorTicket::new(addr).to_string(),
+        router,
+        blobs_data_dir,
+        persistent_store,
+        _watch_handle: n0_future::task::AbortOnDropHandle::new(watch_handle),
+        _progress_handle: n0_future::task::AbortOnDropHandle::new(progress_handle),
+        _store: store,
+        _proxy_handle: proxy_handle,
+    })
+}
+
+/// What a subscriber last applied to its target directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MirrorState {
+    source: Option<EndpointId>,
+    version: u64,
+    /// The files of the applied version by collection name.
+    files: BTreeMap<String, Hash>,
+}
+
+impl MirrorState {
+    /// The state of `target` if it mirrors `source`. A target that mirrored something
+    /// else starts over: all of its files count as unknown.
+    fn load(target: &Path, source: EndpointId) -> Self {
+        let state = std::fs::read(target.join(STATE_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<MirrorState>(&bytes).ok());
+        match state {
+            Some(state) if state.source == Some(source) => state,
+            _ => MirrorState {
+                source: Some(source),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn save(&self, target: &Path) -> anyhow::Result<()> {
+        let path = target.join(STATE_FILE);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+/// What is at a path in the target directory.
+#[derive(Debug, PartialEq, Eq)]
+enum OnDisk {
+    Missing,
+    File(Hash),
+    /// A directory or anything else that is not a regular file.
+    Other,
+}
+
+/// What is at `path`. Files are only hashed again if they changed since `cache` saw them.
+async fn on_disk(path: &Path, cache: &mut ImportCache) -> anyhow::Result<OnDisk> {
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.is_file() => {
+            let key = FileKey::new(&metadata);
+            if let Some(hash) = key.and_then(|key| cache.get(path, key)) {
+                return Ok(OnDisk::File(hash));
+            }
+            let hash = hash_file(path.to_path_buf()).await?;
+            if let Some(key) = key {
+                cache.insert(path, key, hash);
+            }
+            Ok(OnDisk::File(hash))
+        }
+        Ok(_) => Ok(OnDisk::Other),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(OnDisk::Missing),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A change to the target directory.
+#[derive(Debug)]
+enum Step {
+    /// Export a new or changed file, replacing the old version if `replace` is set.
+    Write {
+        name: String,
+        hash: Hash,
+        target: PathBuf,
+        replace: bool,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Delete {
+        target: PathBuf,
+    },
+}
+
+/// Works out how to turn the target directory from `applied` into `collection`.
+///
+/// Files are only changed or removed if they still are what the mirror last put
+/// there, and only created where nothing is in the way. Everything else is a conflict
+/// and left alone. Files deleted here stay deleted until the source changes them.
+async fn plan_sync(
+    target: &Path,
+    applied: &BTreeMap<String, Hash>,
+    collection: &Collection,
+    policy: FilenamePolicy,
+    version: MirrorVersion,
+    cache: &mut ImportCache,
+) -> anyhow::Result<(MirrorPlan, Vec<Step>)> {
+    let mut plan = MirrorPlan {
+        version: version.version,
+        hash: version.hash.to_hex().to_string(),
+        ..Default::default()
+    };
+    let mut steps = Vec::new();
+    let conflict = |plan: &mut MirrorPlan, name: &str, reason: &str| {
+        plan.conflicts.push(MirrorConflict {
+            version: version.version,
+            name: name.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+
+    // The same safety checks and renames as a regular export
+    let new_targets = export_paths(
+        target,
+        collection.iter().map(|(name, _)| name.as_str()),
+        policy,
+    )?;
+    let old_targets = export_paths(target, applied.keys().map(String::as_str), policy)?;
+    let new_names = collection
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<HashSet<_>>();
+    // Files that are gone from the source, by hash so that renames can be spotted
+    let mut gone: HashMap<Hash, Vec<(&str, PathBuf)>> = HashMap::new();
+    for ((name, hash), path) in applied.iter().zip(old_targets) {
+        if !new_names.contains(name.as_str()) {
+            gone.entry(*hash).or_default().push((name, path));
+        }
+    }
+
+    for ((name, hash), path) in collection.iter().zip(new_targets) {
+        if path.parent() == Some(target)
+            && [STATE_FILE, CONFLICTS_FILE]
+                .iter()
+                .any(|reserved| path.file_name() == Some(reserved.as_ref()))
+        {
+            conflict(
+                &mut plan,
+                name,
+                "the name is reserved for the mirror's own state",
+            );
+            continue;
+        }
+        let write = |replace| Step::Write {
+            name: name.clone(),
+            hash: *hash,
+            target: path.clone(),
+            replace,
+        };
+        match (applied.get(name), on_disk(&path, cache).await?) {
+            // Already up to date, whatever put it there
+            (_, OnDisk::File(current)) if current == *hash => {}
+            // Changed or deleted here but not at the source: the local change stays
+            (Some(old), _) if old == hash => {}
+            // Deleted here, but the source has a newer version
+            (Some(_), OnDisk::Missing) => {
+                plan.added.push(name.clone());
+                steps.push(write(false));
+            }
+            (Some(old), OnDisk::File(current)) if current == *old => {
+                plan.updated.push(name.clone());
+                steps.push(write(true));
+            }
+            (Some(_), _) => conflict(&mut plan, name, "changed both here and at the source"),
+            (None, OnDisk::Missing) => {
+                let moved = gone.get_mut(hash).and_then(Vec::pop);
+                match moved {
+                    Some((from_name, from))
+                        if on_disk(&from, cache).await? == OnDisk::File(*hash) =>
+                    {
+                        plan.renamed.push((from_name.to_string(), name.clone()));
+                        steps.push(Step::Rename {
+                            from,
+                            to: path.clone(),
+                        });
+                    }
+                    moved => {
+                        // A moved file that was changed here is handled like any other deletion
+                        if let Some(moved) = moved {
+                            gone.entry(*hash).or_default().push(moved);
+                        }
+                        plan.added.push(name.clone());
+                        steps.push(write(false));
+                    }
+                }
+            }
+            (None, _) => conflict(
+                &mut plan,
+                name,
+                "a file that is not from the mirror is in the way",
+            ),
+        }
+    }
+
+    for (hash, files) in gone {
+        for (name, path) in files {
+            match on_disk(&path, cache).await? {
+                OnDisk::Missing => {}
+                OnDisk::File(current) if current == hash => {
+                    plan.deleted.push(name.to_string());
+                    steps.push(Step::Delete { target: path });
+                }
+                _ => conflict(&mut plan, name, "changed here but deleted at the source"),
+            }
+        }
+    }
+    plan.deleted.sort();
+    Ok((plan, steps))
+}
+
+/// Removes the directories above `path` that are left empty, up to `root`.
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir.filter(|dir| *dir != root && dir.starts_with(root)) {
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Applies `steps`: moves first, while the moved files are still where the plan saw
+/// them, then deletions, then new and changed files.
+async fn apply_steps(db: &Store, target: &Path, mut steps: Vec<Step>) -> anyhow::Result<()> {
+    steps.sort_by_key(|step| match step {
+        Step::Rename { .. } => 0,
+        Step::Delete { .. } => 1,
+        Step::Write { .. } => 2,
+    });
+    for step in steps {
+        match step {
+            Step::Rename { from, to } => {
+                if let Some(parent) = to.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::rename(&from, &to).await?;
+                remove_empty_parents(&from, target);
+            }
+            Step::Delete { target: path } => {
+                tokio::fs::remove_file(&path).await?;
+                remove_empty_parents(&path, target);
+            }
+            Step::Write {
+                name,
+                hash,
+                target: path,
+                replace,
+            } => {
+                export_blob(db, &name, hash, &path, replace).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn record_conflicts(target: &Path, conflicts: &[MirrorConflict]) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for conflict in conflicts {
+        lines.push_str(&serde_json::to_string(conflict)?);
+        lines.push('\n');
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(target.join(CONFLICTS_FILE))
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    // Tokio files write in the background; flush so the record is there once reported
+    file.flush().await?;
+    Ok(())
+}
+
+/// Applies the versions a mirror announces to the target directory.
+struct Follower {
+    endpoint: Endpoint,
+    addr: EndpointAddr,
+    store: Store,
+    target: PathBuf,
+    preflight: Preflight,
+    policy: FilenamePolicy,
+    dry_run: bool,
+    progress_interval: Option<Duration>,
+    state: MirrorState,
+    /// Hashes of the files in the target directory, so unchanged ones are not read again.
+    cache: ImportCache,
+    plans: watch::Sender<Option<MirrorPlan>>,
+    app_handle: AppHandle,
+}
+
+impl Follower {
+    async fn run(mut self) {
+        loop {
+            if let Err(e) = self.follow().await {
+                tracing::warn!("lost the mirror {}: {}", self.addr.id, e);
+                emit_event_with_payload(&self.app_handle, "mirror-disconnected", &e.to_string());
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn follow(&mut self) -> anyhow::Result<()> {
+        let announcements = self
+            .endpoint
+            .connect(self.addr.clone(), MIRROR_ALPN)
+            .await
+            .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+        let blobs = self
+            .endpoint
+            .connect(self.addr.clone(), iroh_blobs::protocol::ALPN)
+            .await
+            .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+        let mut recv = announcements.accept_uni().await?;
+        loop {
+            let version: MirrorVersion = read_message(&mut recv).await?;
+            let plan = self.sync(&blobs, version).await?;
+            self.plans.send_replace(Some(plan));
+        }
+    }
+
+    async fn sync(
+        &mut self,
+        connection: &Connection,
+        version: MirrorVersion,
+    ) -> anyhow::Result<MirrorPlan> {
+        let hash_and_format = HashAndFormat::hash_seq(version.hash);
+        let local = self.store.remote().local(hash_and_format).await?;
+        if !local.is_complete() {
+            fetch_missing(
+                &self.store,
+                connection.clone(),
+                hash_and_format,
+                &local,
+                &self.preflight,
+                &mut ProgressTracker::new(
+                    self.app_handle.clone(),
+                    "receive-progress",
+                    self.progress_interval,
+                ),
+            )
+            .await?;
+        }
+        let collection = Collection::load(version.hash, &self.store).await?;
+        let (mut plan, steps) = plan_sync(
+            &self.target,
+            &self.state.files,
+            &collection,
+            self.policy,
+            version,
+            &mut self.cache,
+        )
+        .await?;
+        if self.dry_run {
+            plan.dry_run = true;
+            emit_plan(&self.app_handle, "mirror-plan", &plan);
+            return Ok(plan);
+        }
+
+        apply_steps(&self.store, &self.target, steps).await?;
+        self.state.version = version.version;
+        self.state.files = collection
+            .iter()
+            .map(|(name, hash)| (name.clone(), *hash))
+            .collect();
+        self.state.save(&self.target)?;
+        let applied = self.state.files.values().collect::<HashSet<_>>();
+        self.cache.retain(|hash| applied.contains(hash));
+        if let Err(e) = self.cache.save() {
+            tracing::warn!("Failed to save the hashes of the target directory: {}", e);
+        }
+        record_conflicts(&self.target, &plan.conflicts).await?;
+        tracing::info!(
+            "applied version {}: {} added, {} updated, {} renamed, {} deleted, {} conflicts",
+            plan.version,
+            plan.added.len(),
+            plan.updated.len(),
+            plan.renamed.len(),
+            plan.deleted.len(),
+            plan.conflicts.len()
+        );
+        emit_plan(&self.app_handle, "mirror-applied", &plan);
+        Ok(plan)
+    }
+}
+
+/// Subscribes to the mirror behind `ticket_str` and keeps `options.output_dir` in step
+/// with it, including deletions and renames, until the subscription is dropped.
+///
+/// Files changed locally are never overwritten or deleted; they are recorded as
+/// conflicts, see [`mirror_conflicts`]. With [`MirrorOptions::dry_run`], each version is
+/// only planned and reported. Lost connections are retried.
+pub async fn subscribe_mirror(
+    ticket_str: String,
+    options: ReceiveOptions,
+    mirror: MirrorOptions,
+    app_handle: AppHandle,
+) -> Result<MirrorSubscription> {
+    subscribe(ticket_str, options, mirror, app_handle)
+        .await
+        .map_err(Error::from)
+}
+
+async fn subscribe(
+    ticket_str: String,
+    options: ReceiveOptions,
+    mirror: MirrorOptions,
+    app_handle: AppHandle,
+) -> anyhow::Result<MirrorSubscription> {
+    let ticket = MirrorTicket::from_str(ticket_str.trim())
+        .map_err(|e| Error::InvalidTicket(e.to_string()))?;
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
+        .alpns(vec![])
+        .secret_key(options.secret_key_for(&ticket.addr().id)?);
+    if let Some(addr) = options.magic_ipv4_addr {
+        builder = builder.bind_addr_v4(addr);
+    }
+    if let Some(addr) = options.magic_ipv6_addr {
+        builder = builder.bind_addr_v6(addr);
+    }
+    let (builder, proxy_handle) = apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
+    let endpoint = builder.bind().await?;
+
+    let persistent_store = options.store_dir.is_some();
+    let blobs_data_dir = match &options.store_dir {
+        Some(store_dir) => store_dir.clone(),
+        None => {
+            let suffix = rand::rng().random::<[u8; 16]>();
+            std::env::temp_dir().join(format!(".sendme-mirror-recv-{}", HEXLOWER.encode(&suffix)))
+        }
+    };
+    tokio::fs::create_dir_all(&blobs_data_dir).await?;
+    let store = FsStore::load(&blobs_data_dir).await?;
+
+    let target = options.output_dir.clone().unwrap_or_else(|| {
+        dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap())
+    });
+    tokio::fs::create_dir_all(&target).await?;
+    let (plans, plans_rx) = watch::channel(None);
+    let follower = Follower {
+        endpoint,
+        addr: ticket.addr().clone(),
+        store: (*store).clone(),
+        target: target.clone(),
+        preflight: Preflight {
+            store_dir: blobs_data_dir.clone(),
+            output_dir: target.clone(),
+            max_total_bytes: options.max_total_bytes,
+            max_files: options.max_files,
+        },
+        policy: options.filename_policy,
+        dry_run: mirror.dry_run,
+        progress_interval: options.progress_interval,
+        state: MirrorState::load(&target, ticket.addr().id),
+        cache: ImportCache::load(&blobs_data_dir),
+        plans,
+        app_handle,
+    };
+    let task = n0_future::task::spawn(follower.run());
+
+    Ok(MirrorSubscription {
+        target,
+        plans: plans_rx,
+        blobs_data_dir,
+        persistent_store,
+        _task: n0_future::task::AbortOnDropHandle::new(task),
+        _store: store,
+        _proxy_handle: proxy_handle,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirror_ticket_roundtrip() {
+        let key = iroh::SecretKey::generate(&mut rand::rng());
+        let addr = EndpointAddr::new(key.public()).with_ip_addr("127.0.0.1:4433".parse().unwrap());
+        let ticket = MirrorTicket::new(addr);
+        let s = ticket.to_string();
+        assert!(s.starts_with("mirror"));
+        assert_eq!(MirrorTicket::from_str(&s).unwrap(), ticket);
+    }
+
+    async fn next_plan(subscription: &mut MirrorSubscription) -> MirrorPlan {
+        let plans = &mut subscription.plans;
+        tokio::time::timeout(Duration::from_secs(20), plans.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        plans.borrow_and_update().clone().unwrap()
+    }
+
+    #[tokio::test]
+    async fn subscriber_follows_changes_and_keeps_local_edits() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("notes");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("a.txt"), "alpha").unwrap();
+        std::fs::write(src.join("b.txt"), "beta").unwrap();
+        std::fs::write(src.join("e.txt"), "epsilon").unwrap();
+        std::fs::write(src.join("f.txt"), "phi").unwrap();
+        std::fs::write(src.join("sub/c.txt"), "gamma").unwrap();
+
+        let mirror = start_mirror(
+            src.clone(),
+            SendOptions::local(),
+            MirrorOptions {
+                poll_interval: Some(Duration::from_millis(500)),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let subscribe_to = |out: PathBuf, dry_run| {
+            subscribe_mirror(
+                mirror.ticket.clone(),
+                ReceiveOptions {
+                    output_dir: Some(out),
+                    ..ReceiveOptions::local()
+                },
+                MirrorOptions {
+                    dry_run,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+        let out = td.path().join("out");
+        let mut subscription = subscribe_to(out.clone(), false).await.unwrap();
+        let first = next_plan(&mut subscription).await;
+        assert_eq!(first.version, 1);
+        assert_eq!(first.added.len(), 5);
+        assert_eq!(
+            std::fs::read_to_string(out.join("notes/sub/c.txt")).unwrap(),
+            "gamma"
+        );
+
+        // Edited here and at the source, deleted here only, then changes the subscriber
+        // can apply
+        std::fs::write(out.join("notes/a.txt"), "mine").unwrap();
+        std::fs::remove_file(out.join("notes/f.txt")).unwrap();
+        std::fs::write(src.join("a.txt"), "theirs").unwrap();
+        std::fs::write(src.join("e.txt"), "epsilon, revised").unwrap();
+        std::fs::create_dir(src.join("moved")).unwrap();
+        std::fs::rename(src.join("b.txt"), src.join("moved/b2.txt")).unwrap();
+        std::fs::remove_file(src.join("sub/c.txt")).unwrap();
+        std::fs::write(src.join("d.txt"), "delta").unwrap();
+
+        // The changes may be published across more than one version
+        let mut plans = Vec::new();
+        while !out.join("notes/d.txt").exists() || out.join("notes/sub/c.txt").exists() {
+            plans.push(next_plan(&mut subscription).await);
+        }
+        let all = |f: fn(&MirrorPlan) -> Vec<String>| plans.iter().flat_map(f).collect::<Vec<_>>();
+        assert!(all(|p| p.updated.clone()).contains(&"notes/e.txt".to_string()));
+        assert!(all(|p| p.added.clone()).contains(&"notes/d.txt".to_string()));
+        assert!(all(|p| p.deleted.clone()).contains(&"notes/sub/c.txt".to_string()));
+        assert!(plans.iter().any(|p| p
+            .renamed
+            .contains(&("notes/b.txt".to_string(), "notes/moved/b2.txt".to_string()))));
+        assert_eq!(
+            std::fs::read_to_string(out.join("notes/e.txt")).unwrap(),
+            "epsilon, revised"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("notes/moved/b2.txt")).unwrap(),
+            "beta"
+        );
+        assert!(!out.join("notes/b.txt").exists());
+        // The emptied directory goes with its last file
+        assert!(!out.join("notes/sub").exists());
+        // The local deletion sticks, and the local edit survives and is recorded
+        assert!(!out.join("notes/f.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.join("notes/a.txt")).unwrap(),
+            "mine"
+        );
+        let conflicts = mirror_conflicts(&out).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].name, "notes/a.txt");
+
+        // A dry run reports the current version without touching the target
+        let preview = td.path().join("preview");
+        let mut dry = subscribe_to(preview.clone(), true).await.unwrap();
+        let plan = next_plan(&mut dry).await;
+        assert!(plan.dry_run);
+        assert_eq!(plan.version, plans.last().unwrap().version);
+        assert_eq!(plan.added.len(), 5);
+        assert_eq!(std::fs::read_dir(&preview).unwrap().count(), 0);
+
+        drop((subscription, dry));
+        mirror.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&mirror.blobs_data_dir).ok();
+    }
+}
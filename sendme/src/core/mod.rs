@@ -1,3 +1,24 @@
+pub mod approval;
+pub mod completion;
+pub mod contacts;
+pub mod error;
+pub mod filename;
+pub mod history;
+pub mod import_cache;
+pub mod inbox;
+pub mod mirror;
+pub mod multisource;
+pub mod offer;
+pub mod pairing;
+pub mod passphrase;
+pub mod preflight;
+pub mod progress;
+pub mod provenance;
+pub mod proxy;
 pub mod receive;
+pub mod receivers;
 pub mod send;
+pub mod store;
+pub mod streaming;
 pub mod types;
+pub mod verify;
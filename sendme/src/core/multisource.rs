@@ -0,0 +1,378 @@
+use crate::core::error::Error;
+use crate::core::preflight::Preflight;
+use crate::core::progress::{file_at, ProgressTracker};
+use crate::core::types::{AppHandle, ProviderStats};
+use iroh::{endpoint::Connection, Endpoint, EndpointAddr, EndpointId};
+use iroh_blobs::{
+    api::{remote::LocalInfo, Store},
+    get::{request::get_hash_seq_and_sizes, Stats},
+    protocol::{ChunkRanges, ChunkRangesExt, GetRequest},
+    Hash, HashAndFormat,
+};
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+/// Blobs larger than this are split into pieces of this size, so that a single large
+/// file is still spread across the providers. A multiple of the 16 KiB chunk groups.
+const PIECE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A range of one blob that a single provider is asked for.
+#[derive(Debug, Clone)]
+struct Piece {
+    hash: Hash,
+    ranges: ChunkRanges,
+    size: u64,
+}
+
+/// Parses another provider of a collection: a ticket for it, or just an endpoint id
+/// that discovery can find. Tickets carry the hash, which must match the download's.
+pub(crate) fn parse_provider(provider: &str) -> anyhow::Result<(EndpointAddr, Option<Hash>)> {
+    let provider = provider.trim();
+    if let Ok(ticket) = iroh_blobs::ticket::BlobTicket::from_str(provider) {
+        return Ok((ticket.addr().clone(), Some(ticket.hash())));
+    }
+    match EndpointId::from_str(provider) {
+        Ok(id) => Ok((EndpointAddr::new(id), None)),
+        Err(_) => Err(Error::InvalidTicket(format!(
+            "provider {provider} is neither a ticket nor an endpoint id"
+        ))
+        .into()),
+    }
+}
+
+/// Shared state of the providers while they work through the pieces.
+struct Swarm<'a> {
+    db: &'a Store,
+    queue: Mutex<VecDeque<Piece>>,
+    providers: Mutex<Vec<ProviderStats>>,
+    fetched: AtomicU64,
+    /// Sizes of the collection metadata and the files, to tell which file the download is at.
+    sizes: Vec<u64>,
+    started: Instant,
+    progress: Mutex<&'a mut ProgressTracker>,
+}
+
+impl Swarm<'_> {
+    /// Fetches pieces from one provider until none are left or the provider fails.
+    /// A piece the provider failed on goes back to the queue for the others.
+    async fn work(&self, index: usize, connection: Connection) -> Option<Connection> {
+        loop {
+            let Some(piece) = self.queue.lock().expect("piece queue poisoned").pop_front() else {
+                return Some(connection);
+            };
+            let result = self.fetch(&connection, &piece).await;
+            match result {
+                Ok(bytes) => self.record(index, bytes, None),
+                Err(e) => {
+                    tracing::warn!("dropping provider {}: {}", connection.remote_id(), e);
+                    self.queue
+                        .lock()
+                        .expect("piece queue poisoned")
+                        .push_front(piece);
+                    self.record(index, 0, Some(e.to_string()));
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Fetches whatever part of `piece` is missing, returning the payload bytes read.
+    async fn fetch(&self, connection: &Connection, piece: &Piece) -> anyhow::Result<u64> {
+        let request = GetRequest::blob_ranges(piece.hash, piece.ranges.clone());
+        let local = self.db.remote().local_for_request(request).await?;
+        if local.is_complete() {
+            self.fetched.fetch_add(piece.size, Ordering::Relaxed);
+            return Ok(0);
+        }
+        let stats = self
+            .db
+            .remote()
+            .execute_get(connection.clone(), local.missing())
+            .await?;
+        self.fetched.fetch_add(piece.size, Ordering::Relaxed);
+        Ok(stats.payload_bytes_read)
+    }
+
+    /// Adds `bytes` to a provider's share and emits the progress of the whole download.
+    fn record(&self, index: usize, bytes: u64, error: Option<String>) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let providers = {
+            let mut providers = self.providers.lock().expect("provider stats poisoned");
+            let provider = &mut providers[index];
+            provider.bytes = provider.bytes.saturating_add(bytes);
+            if elapsed > 0.0 {
+                provider.speed_bps = provider.bytes as f64 / elapsed;
+            }
+            if error.is_some() {
+                provider.error = error;
+            }
+            providers.clone()
+        };
+        let fetched = self.fetched.load(Ordering::Relaxed);
+        let mut progress = self.progress.lock().expect("progress poisoned");
+        progress.set_file(file_at(&self.sizes, fetched).saturating_sub(1));
+        progress.update(fetched);
+        emit_provider_stats(progress.app_handle(), &providers);
+    }
+}
+
+/// Emits `receive-providers` with the JSON list of [`ProviderStats`].
+fn emit_provider_stats(app_handle: &AppHandle, providers: &[ProviderStats]) {
+    let Some(handle) = app_handle else {
+        return;
+    };
+    let payload = serde_json::to_string(providers).unwrap_or_else(|_| "[]".to_string());
+    if let Err(e) = handle.emit_event_with_payload("receive-providers", &payload) {
+        tracing::warn!("Failed to emit provider stats: {}", e);
+    }
+}
+
+/// Fetches whatever part of the collection is not yet in `db` from all of `providers`
+/// at once, emitting receive progress and the throughput of each provider.
+///
+/// The files are split into pieces that idle providers take in turn, so faster
+/// providers end up sending more. When a provider fails, the others take over its
+/// pieces; the download only fails once none are left.
+///
+/// Returns the combined transfer stats, the number of files, the payload size in
+/// bytes and what each provider contributed.
+pub(crate) async fn fetch_from_providers(
+    db: &Store,
+    endpoint: &Endpoint,
+    providers: Vec<EndpointAddr>,
+    hash_and_format: HashAndFormat,
+    local: &LocalInfo,
+    preflight: &Preflight,
+    progress: &mut ProgressTracker,
+) -> anyhow::Result<(Stats, u64, u64, Vec<ProviderStats>)> {
+    let hash = hash_and_format.hash;
+    let connections = n0_future::join_all(
+        providers
+            .iter()
+            .map(|addr| endpoint.connect(addr.clone(), iroh_blobs::protocol::ALPN)),
+    )
+    .await;
+    let mut stats = Vec::new();
+    let mut connections = providers
+        .iter()
+        .zip(connections)
+        .map(|(addr, connection)| {
+            let (connection, error) = match connection {
+                Ok(connection) => (Some(connection), None),
+                Err(e) => {
+                    tracing::warn!("could not reach provider {}: {}", addr.id, e);
+                    (None, Some(e.to_string()))
+                }
+            };
+            stats.push(ProviderStats {
+                endpoint_id: addr.id,
+                bytes: 0,
+                speed_bps: 0.0,
+                error,
+            });
+            connection
+        })
+        .collect::<Vec<_>>();
+
+    // The first provider that answers describes the collection and sends the hash sequence
+    let mut described = None;
+    for (index, slot) in connections.iter_mut().enumerate() {
+        let Some(connection) = slot.clone() else {
+            continue;
+        };
+        let result = async {
+            let (hash_seq, sizes) =
+                get_hash_seq_and_sizes(&connection, &hash, 1024 * 1024 * 32, None).await?;
+            db.remote()
+                .fetch(connection.clone(), HashAndFormat::raw(hash))
+                .complete()
+                .await?;
+            anyhow::Ok((hash_seq, sizes))
+        }
+        .await;
+        match result {
+            Ok(x) => {
+                described = Some(x);
+                break;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "provider {} could not describe {}: {}",
+                    stats[index].endpoint_id,
+                    hash,
+                    e
+                );
+                stats[index].error = Some(e.to_string());
+                *slot = None;
+            }
+        }
+    }
+    let Some((hash_seq, sizes)) = described else {
+        let reason = stats
+            .iter()
+            .rev()
+            .find_map(|provider| provider.error.clone())
+            .unwrap_or_else(|| "no providers".to_string());
+        return Err(Error::ConnectFailed(reason).into());
+    };
+
+    let total_size = sizes
+        .iter()
+        .fold(0u64, |sum, size| sum.saturating_add(*size));
+    // The first size is the collection metadata, the rest are the files
+    let payload_size = sizes
+        .iter()
+        .skip(1)
+        .fold(0u64, |sum, size| sum.saturating_add(*size));
+    let total_files = (sizes.len().saturating_sub(1)) as u64;
+
+    let missing_bytes = total_size.saturating_sub(local.local_bytes());
+    preflight.check(total_files, payload_size, missing_bytes)?;
+    progress.start(payload_size, total_files);
+
+    let mut pieces = VecDeque::new();
+    for (hash, size) in hash_seq.iter().zip(sizes.iter().copied()) {
+        let mut start = 0;
+        loop {
+            let end = start + PIECE_SIZE;
+            if end >= size {
+                // Open ended, so the last chunk and its size proof are always included
+                pieces.push_back(Piece {
+                    hash,
+                    ranges: ChunkRanges::bytes(start..),
+                    size: size - start.min(size),
+                });
+                break;
+            }
+            pieces.push_back(Piece {
+                hash,
+                ranges: ChunkRanges::bytes(start..end),
+                size: PIECE_SIZE,
+            });
+            start = end;
+        }
+    }
+
+    let swarm = Swarm {
+        db,
+        queue: Mutex::new(pieces),
+        providers: Mutex::new(stats),
+        fetched: AtomicU64::new(0),
+        sizes: sizes.to_vec(),
+        started: Instant::now(),
+        progress: Mutex::new(progress),
+    };
+    // A provider can fail after the others ran out of work, so go again until every
+    // piece is fetched or no provider is left
+    while !swarm.queue.lock().expect("piece queue poisoned").is_empty() {
+        let working = connections
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot.take()?)))
+            .collect::<Vec<_>>();
+        if working.is_empty() {
+            let reason = swarm
+                .providers
+                .lock()
+                .expect("provider stats poisoned")
+                .iter()
+                .rev()
+                .find_map(|provider| provider.error.clone())
+                .unwrap_or_default();
+            return Err(Error::PeerAborted(format!("every provider failed: {reason}")).into());
+        }
+        let done = n0_future::join_all(working.into_iter().map(|(index, connection)| {
+            let swarm = &swarm;
+            async move { (index, swarm.work(index, connection).await) }
+        }))
+        .await;
+        for (index, connection) in done {
+            connections[index] = connection;
+        }
+    }
+
+    let providers = swarm
+        .providers
+        .into_inner()
+        .expect("provider stats poisoned");
+    let elapsed = swarm.started.elapsed();
+    let progress = swarm.progress.into_inner().expect("progress poisoned");
+    progress.finish();
+    emit_provider_stats(progress.app_handle(), &providers);
+    let mut stats = Stats {
+        elapsed,
+        ..Default::default()
+    };
+    stats.payload_bytes_read = providers.iter().map(|provider| provider.bytes).sum();
+    Ok((stats, total_files, payload_size, providers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::receive::download;
+    use crate::core::send::start_share;
+    use crate::core::types::{ReceiveOptions, SendOptions};
+
+    #[tokio::test]
+    async fn download_splits_across_providers_and_skips_dead_ones() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("dataset");
+        std::fs::create_dir(&dir).unwrap();
+        let big = (0..PIECE_SIZE * 2 + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::write(dir.join("weights.bin"), &big).unwrap();
+        std::fs::write(dir.join("labels.csv"), "cat,dog").unwrap();
+
+        let first = start_share(dir.clone(), SendOptions::local(), None)
+            .await
+            .unwrap();
+        let second = start_share(dir.clone(), SendOptions::local(), None)
+            .await
+            .unwrap();
+        assert_eq!(first.hash, second.hash);
+        let gone = iroh::SecretKey::generate(&mut rand::rng()).public();
+
+        let out = td.path().join("out");
+        let result = download(
+            first.ticket.clone(),
+            ReceiveOptions {
+                output_dir: Some(out.clone()),
+                providers: vec![second.ticket.clone(), gone.to_string()],
+                ..ReceiveOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(out.join("dataset/weights.bin")).unwrap(), big);
+        assert_eq!(result.providers.len(), 3);
+        assert_eq!(result.providers[2].endpoint_id, gone);
+        assert!(result.providers[2].error.is_some());
+        let sent = result.providers.iter().map(|p| p.bytes).sum::<u64>();
+        assert!(sent >= big.len() as u64);
+
+        for share in [first, second] {
+            share.router.shutdown().await.unwrap();
+            std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+        }
+    }
+
+    #[test]
+    fn provider_tickets_and_ids_parse() {
+        let id = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let (addr, hash) = parse_provider(&id.to_string()).unwrap();
+        assert_eq!(addr.id, id);
+        assert_eq!(hash, None);
+        let err = parse_provider("not a provider").unwrap_err();
+        assert_eq!(Error::from(err).code(), "invalid_ticket");
+    }
+}
@@ -0,0 +1,382 @@
+use crate::core::error::{Error, Result};
+use crate::core::receive::fetch_sizes;
+use crate::core::types::AppHandle;
+use iroh::{endpoint::Connection, EndpointId};
+use iroh_blobs::{
+    api::Store,
+    format::collection::Collection,
+    hashseq::HashSeq,
+    protocol::{ChunkRanges, GetRequest},
+    Hash, HashAndFormat,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long a download waits for its offer to be answered when the prompt leaves it unset.
+const DEFAULT_OFFER_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A file of a [`ReceiveOffer`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OfferedFile {
+    pub name: String,
+    pub size: u64,
+}
+
+/// What a sender offers, as sent in `receive-offer` events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiveOffer {
+    pub hash: String,
+    pub sender: EndpointId,
+    pub files: Vec<OfferedFile>,
+    /// The size of all files together.
+    pub size: u64,
+}
+
+/// How the user answered a [`ReceiveOffer`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferAnswer {
+    Accept,
+    /// Receive only the files with these names.
+    AcceptSome(Vec<String>),
+    Decline,
+}
+
+/// Where a download waits for the user to answer its [`ReceiveOffer`].
+///
+/// Pass a clone in [`crate::ReceiveOptions::confirm`] and answer from elsewhere once the
+/// `receive-offer` event arrives. The connection to the sender stays open meanwhile.
+/// Declining fails the download with [`Error::Cancelled`], and so does leaving the
+/// offer unanswered for longer than the prompt's timeout.
+#[derive(Clone)]
+pub struct OfferPrompt {
+    waiting: Arc<Mutex<Option<Waiting>>>,
+    timeout: Duration,
+}
+
+struct Waiting {
+    offer: ReceiveOffer,
+    answer: oneshot::Sender<OfferAnswer>,
+}
+
+impl fmt::Debug for OfferPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OfferPrompt").field(&self.offer()).finish()
+    }
+}
+
+impl Default for OfferPrompt {
+    fn default() -> Self {
+        Self::with_timeout(DEFAULT_OFFER_TIMEOUT)
+    }
+}
+
+impl OfferPrompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A prompt that declines once nobody answered for `timeout`.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            waiting: Arc::default(),
+            timeout,
+        }
+    }
+
+    /// The offer waiting for an answer, if any.
+    pub fn offer(&self) -> Option<ReceiveOffer> {
+        self.lock().as_ref().map(|waiting| waiting.offer.clone())
+    }
+
+    pub fn answer(&self, answer: OfferAnswer) -> Result<()> {
+        let waiting = self
+            .lock()
+            .take()
+            .ok_or_else(|| Error::other("no offer is waiting for an answer"))?;
+        waiting
+            .answer
+            .send(answer)
+            .map_err(|_| Error::other("the download is no longer waiting"))
+    }
+
+    /// Offers the collection `hash`, whose metadata and hash sequence are in `db`, and
+    /// waits for the answer. `sizes` are those of the metadata and the files, in order.
+    pub(crate) async fn ask(
+        &self,
+        app_handle: &AppHandle,
+        db: &Store,
+        hash: Hash,
+        sender: EndpointId,
+        sizes: Vec<u64>,
+    ) -> anyhow::Result<Selection> {
+        let collection = Collection::load(hash, db).await?;
+        let files: Vec<_> = collection
+            .iter()
+            .zip(sizes.iter().skip(1))
+            .map(|((name, _), size)| OfferedFile {
+                name: name.clone(),
+                size: *size,
+            })
+            .collect();
+        let offer = ReceiveOffer {
+            hash: hash.to_hex().to_string(),
+            sender,
+            size: files.iter().map(|file| file.size).sum(),
+            files,
+        };
+
+        let (tx, rx) = oneshot::channel();
+        *self.lock() = Some(Waiting {
+            offer: offer.clone(),
+            answer: tx,
+        });
+        if let Some(handle) = app_handle {
+            match serde_json::to_string(&offer) {
+                Ok(payload) => {
+                    if let Err(e) = handle.emit_event_with_payload("receive-offer", &payload) {
+                        tracing::warn!("Failed to emit receive offer: {}", e);
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to serialize receive offer: {}", e),
+            }
+        }
+        // A prompt that is dropped, asked again or left unanswered declines
+        let answer = match tokio::time::timeout(self.timeout, rx).await {
+            Ok(answer) => answer.unwrap_or(OfferAnswer::Decline),
+            Err(_) => {
+                tracing::info!("nobody answered the offer of {}, declining", offer.hash);
+                self.lock().take();
+                OfferAnswer::Decline
+            }
+        };
+
+        let names = match answer {
+            OfferAnswer::Accept => None,
+            OfferAnswer::Decline => return Err(Error::Cancelled.into()),
+            OfferAnswer::AcceptSome(names) => {
+                let names: HashSet<String> = names.into_iter().collect();
+                if let Some(name) = names
+                    .iter()
+                    .find(|name| !offer.files.iter().any(|file| &file.name == *name))
+                {
+                    return Err(Error::other(format!("{name:?} was not offered")).into());
+                }
+                if names.is_empty() {
+                    return Err(Error::Cancelled.into());
+                }
+                Some(names)
+            }
+        };
+        Ok(Selection {
+            names,
+            collection,
+            sizes,
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Waiting>> {
+        self.waiting.lock().expect("offer prompt poisoned")
+    }
+}
+
+/// The part of an offered collection that the user accepted.
+pub(crate) struct Selection {
+    /// The names of the accepted files, or `None` if all of them were accepted.
+    names: Option<HashSet<String>>,
+    collection: Collection,
+    /// The sizes of the metadata and of every file of the collection.
+    sizes: Vec<u64>,
+}
+
+impl Selection {
+    pub(crate) fn is_subset(&self) -> bool {
+        self.names.is_some()
+    }
+
+    fn accepted(&self) -> impl Iterator<Item = (usize, &(String, Hash))> {
+        self.collection.iter().enumerate().filter(|(_, (name, _))| {
+            self.names
+                .as_ref()
+                .map_or(true, |names| names.contains(name))
+        })
+    }
+
+    /// Requests the hash sequence, the metadata and the accepted files.
+    pub(crate) fn request(&self, hash: Hash) -> GetRequest {
+        if !self.is_subset() {
+            return HashAndFormat::hash_seq(hash).into();
+        }
+        let builder = GetRequest::builder()
+            .root(ChunkRanges::all())
+            .child(0, ChunkRanges::all());
+        self.accepted()
+            .fold(builder, |builder, (index, _)| {
+                builder.child(index as u64 + 1, ChunkRanges::all())
+            })
+            .build(hash)
+    }
+
+    /// The index in the hash sequence and the size of the metadata and the accepted
+    /// files, in the order they are sent.
+    pub(crate) fn sizes(&self) -> Vec<(u64, u64)> {
+        let files = self
+            .accepted()
+            .map(|(index, _)| (index as u64 + 1, self.sizes[index + 1]));
+        let meta = self.sizes.first().copied().unwrap_or_default();
+        std::iter::once((0, meta)).chain(files).collect()
+    }
+
+    /// The accepted files of `collection`.
+    pub(crate) fn filter(&self, collection: Collection) -> Collection {
+        match &self.names {
+            Some(names) => collection
+                .into_iter()
+                .filter(|(name, _)| names.contains(name))
+                .collect(),
+            None => collection,
+        }
+    }
+}
+
+/// Fetches what an offer shows, the hash sequence and the metadata of the collection,
+/// and returns the sizes of the metadata and the files. No file data is requested.
+pub(crate) async fn preview(
+    db: &Store,
+    connection: &Connection,
+    hash: Hash,
+) -> anyhow::Result<Vec<u64>> {
+    let sizes = fetch_sizes(connection, hash).await?;
+    if !db.has(hash).await? {
+        fetch_blob(db, connection, hash).await?;
+    }
+    let meta = HashSeq::try_from(db.get_bytes(hash).await?)?
+        .get(0)
+        .ok_or_else(|| Error::PeerAborted("the collection has no metadata".to_string()))?;
+    if !db.has(meta).await? {
+        fetch_blob(db, connection, meta).await?;
+    }
+    Ok(sizes)
+}
+
+/// The sizes of the metadata and the files of a collection that is complete in `db`.
+pub(crate) async fn stored_sizes(db: &Store, hash: Hash) -> anyhow::Result<Vec<u64>> {
+    let mut sizes = Vec::new();
+    for child in HashSeq::try_from(db.get_bytes(hash).await?)? {
+        sizes.push(db.observe(child).await?.size());
+    }
+    Ok(sizes)
+}
+
+pub(crate) async fn fetch_blob(
+    db: &Store,
+    connection: &Connection,
+    hash: Hash,
+) -> anyhow::Result<()> {
+    db.remote()
+        .fetch(connection.clone(), HashAndFormat::raw(hash))
+        .complete()
+        .await
+        .map_err(|e| Error::PeerAborted(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::receive::download;
+    use crate::core::send::start_share;
+    use crate::core::types::{ReceiveOptions, SendOptions};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// Waits for the download to make its offer, then answers it.
+    async fn answer_when_offered(prompt: OfferPrompt, answer: OfferAnswer) -> ReceiveOffer {
+        loop {
+            if let Some(offer) = prompt.offer() {
+                prompt.answer(answer).unwrap();
+                return offer;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn receiver_picks_files_from_the_offer() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("album");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("cover.png"), vec![1u8; 2_000]).unwrap();
+        std::fs::write(dir.join("track1.flac"), vec![2u8; 300_000]).unwrap();
+        std::fs::write(dir.join("track2.flac"), vec![3u8; 400_000]).unwrap();
+
+        let share = start_share(dir, SendOptions::local(), None).await.unwrap();
+        let receive_options = |out: PathBuf, prompt: &OfferPrompt| ReceiveOptions {
+            output_dir: Some(out),
+            confirm: Some(prompt.clone()),
+            ..ReceiveOptions::local()
+        };
+
+        let prompt = OfferPrompt::new();
+        let out = td.path().join("some");
+        let answering = tokio::spawn(answer_when_offered(
+            prompt.clone(),
+            OfferAnswer::AcceptSome(vec!["album/track2.flac".to_string()]),
+        ));
+        let received = download(
+            share.ticket.clone(),
+            ReceiveOptions {
+                reseed: Some(SendOptions::local()),
+                ..receive_options(out.clone(), &prompt)
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let offer = answering.await.unwrap();
+        // Part of the collection is not shared on
+        assert!(received.reseed.is_none());
+        assert_eq!(received.hash, offer.hash);
+        assert_eq!(offer.files.len(), 3);
+        assert_eq!(offer.size, 702_000);
+        assert_eq!(received.files, vec!["album/track2.flac".to_string()]);
+        // Only the accepted file was requested
+        assert_eq!(received.size, 400_000);
+        assert!(out.join("album/track2.flac").exists());
+        assert!(!out.join("album/track1.flac").exists());
+        assert!(!out.join("album/cover.png").exists());
+
+        let prompt = OfferPrompt::new();
+        let out = td.path().join("none");
+        let answering = tokio::spawn(answer_when_offered(prompt.clone(), OfferAnswer::Decline));
+        let result = download(
+            share.ticket.clone(),
+            receive_options(out.clone(), &prompt),
+            None,
+        )
+        .await;
+        answering.await.unwrap();
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(!out.join("album").exists());
+
+        // Nobody answers, so the download gives up once the time runs out
+        let prompt = OfferPrompt::with_timeout(Duration::from_millis(200));
+        let out = td.path().join("unanswered");
+        let result = download(
+            share.ticket.clone(),
+            receive_options(out.clone(), &prompt),
+            None,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(prompt.offer().is_none());
+        assert!(!out.join("album").exists());
+
+        share.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+    }
+}
@@ -0,0 +1,403 @@
+use crate::core::contacts::{now_secs, Contact};
+use crate::core::error::{Error, Result};
+use crate::core::inbox::wait_reachable;
+use crate::core::proxy::apply_proxy;
+use crate::core::types::{apply_options, endpoint_builder, get_or_create_secret, PairingOptions};
+use anyhow::Context;
+use iroh::{
+    endpoint::{Connection, RecvStream, SendStream},
+    Endpoint, EndpointAddr, EndpointId, RelayMode,
+};
+use iroh_blobs::Hash;
+use iroh_tickets::{ParseError, Ticket};
+use n0_future::task::AbortOnDropHandle;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{str::FromStr, time::Duration};
+
+/// ALPN for pairing two devices.
+///
+/// The joining side commits to a random nonce before it learns the host's nonce,
+/// then reveals it. Both sides derive a short authentication string from the two
+/// endpoint ids and both nonces, so a device in the middle cannot steer the two
+/// strings into matching.
+pub const PAIR_ALPN: &[u8] = b"/sendme/pair/0";
+
+/// Upper bound for a single pairing message; they only carry names and nonces.
+const MAX_MESSAGE_SIZE: usize = 4096;
+/// Longest nickname a peer may announce.
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JoinHello {
+    name: String,
+    commitment: Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HostHello {
+    name: String,
+    nonce: [u8; 32],
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Reveal {
+    nonce: [u8; 32],
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Decision {
+    accept: bool,
+}
+
+/// A ticket for joining a pairing session.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+#[display("{}", Ticket::serialize(self))]
+pub struct PairingTicket {
+    addr: EndpointAddr,
+}
+
+impl PairingTicket {
+    pub fn new(addr: EndpointAddr) -> Self {
+        Self { addr }
+    }
+
+    pub fn addr(&self) -> &EndpointAddr {
+        &self.addr
+    }
+}
+
+impl Ticket for PairingTicket {
+    const KIND: &'static str = "pair";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(&self.addr).expect("postcard serialization failed")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, ParseError> {
+        let addr = postcard::from_bytes(bytes)?;
+        Ok(Self { addr })
+    }
+}
+
+impl FromStr for PairingTicket {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ticket::deserialize(s)
+    }
+}
+
+/// Formats the short authentication string both devices show for confirmation.
+fn short_auth_string(
+    host: &EndpointId,
+    joiner: &EndpointId,
+    host_nonce: &[u8; 32],
+    join_nonce: &[u8; 32],
+) -> String {
+    let mut data = Vec::with_capacity(b"sendme-pair-sas".len() + 4 * 32);
+    data.extend_from_slice(b"sendme-pair-sas");
+    data.extend_from_slice(host.as_bytes());
+    data.extend_from_slice(joiner.as_bytes());
+    data.extend_from_slice(host_nonce);
+    data.extend_from_slice(join_nonce);
+    let digest = Hash::new(&data);
+    let bytes = digest.as_bytes();
+    let code = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) % 1_000_000;
+    format!("{:03} {:03}", code / 1000, code % 1000)
+}
+
+async fn write_message(send: &mut SendStream, message: &impl Serialize) -> anyhow::Result<()> {
+    let bytes = postcard::to_stdvec(message)?;
+    send.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
+    send.write_all(&bytes).await?;
+    Ok(())
+}
+
+async fn read_message<T: DeserializeOwned>(recv: &mut RecvStream) -> anyhow::Result<T> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await?;
+    let len = u32::from_le_bytes(len) as usize;
+    anyhow::ensure!(len <= MAX_MESSAGE_SIZE, "pairing message too large");
+    let mut bytes = vec![0u8; len];
+    recv.read_exact(&mut bytes).await?;
+    Ok(postcard::from_bytes(&bytes)?)
+}
+
+fn check_name(name: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !name.trim().is_empty() && name.chars().count() <= MAX_NAME_LEN,
+        "device name must be between 1 and {} characters",
+        MAX_NAME_LEN
+    );
+    Ok(())
+}
+
+async fn bind(
+    options: &PairingOptions,
+) -> anyhow::Result<(Endpoint, Option<AbortOnDropHandle<()>>)> {
+    check_name(&options.name)?;
+    let secret_key = match options.secret_key.clone() {
+        Some(secret_key) => secret_key,
+        None => get_or_create_secret()?,
+    };
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
+        .alpns(vec![PAIR_ALPN.to_vec()])
+        .secret_key(secret_key);
+    if let Some(addr) = options.magic_ipv4_addr {
+        builder = builder.bind_addr_v4(addr);
+    }
+    if let Some(addr) = options.magic_ipv6_addr {
+        builder = builder.bind_addr_v6(addr);
+    }
+    let (builder, proxy_handle) = apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
+    Ok((builder.bind().await?, proxy_handle))
+}
+
+/// A pairing session waiting for the other device to join.
+pub struct PairingHost {
+    pub ticket: String,
+    name: String,
+    endpoint: Endpoint,
+    _proxy_handle: Option<AbortOnDropHandle<()>>,
+}
+
+impl PairingHost {
+    /// Waits for a device to join and runs the handshake with it.
+    pub async fn accept(&self) -> Result<PendingPairing> {
+        self.handshake().await.map_err(Error::from)
+    }
+
+    async fn handshake(&self) -> anyhow::Result<PendingPairing> {
+        let incoming = self
+            .endpoint
+            .accept()
+            .await
+            .context("pairing session closed")?;
+        let connection = incoming.await?;
+        let (mut send, mut recv) = connection.accept_bi().await?;
+
+        let hello: JoinHello = read_message(&mut recv).await?;
+        check_name(&hello.name)?;
+        let nonce = rand::rng().random::<[u8; 32]>();
+        write_message(
+            &mut send,
+            &HostHello {
+                name: self.name.clone(),
+                nonce,
+            },
+        )
+        .await?;
+        let reveal: Reveal = read_message(&mut recv).await?;
+        anyhow::ensure!(
+            Hash::new(reveal.nonce) == hello.commitment,
+            "joining device did not keep its commitment"
+        );
+
+        let peer = connection.remote_id();
+        let sas = short_auth_string(&self.endpoint.id(), &peer, &nonce, &reveal.nonce);
+        Ok(PendingPairing {
+            peer,
+            peer_name: hello.name,
+            sas,
+            connection,
+            send,
+            recv,
+            _endpoint: None,
+            _proxy_handle: None,
+        })
+    }
+
+    /// Stops waiting for a device to join.
+    pub async fn close(&self) {
+        self.endpoint.close().await;
+    }
+}
+
+/// Opens a pairing session and returns the ticket for the other device to join.
+pub async fn host_pairing(options: PairingOptions) -> Result<PairingHost> {
+    host(options).await.map_err(Error::from)
+}
+
+async fn host(options: PairingOptions) -> anyhow::Result<PairingHost> {
+    let (endpoint, proxy_handle) = bind(&options).await?;
+    let relay_mode: RelayMode = options.relay_mode.clone().into();
+    tokio::time::timeout(
+        Duration::from_secs(30),
+        wait_reachable(&endpoint, &relay_mode),
+    )
+    .await?;
+
+    let mut addr = endpoint.addr();
+    apply_options(&mut addr, options.ticket_type);
+    Ok(PairingHost {
+        ticket: PairingTicket::new(addr).to_string(),
+        name: options.name,
+        endpoint,
+        _proxy_handle: proxy_handle,
+    })
+}
+
+/// Joins the pairing session behind `ticket_str` and runs the handshake with it.
+pub async fn join_pairing(ticket_str: String, options: PairingOptions) -> Result<PendingPairing> {
+    join(ticket_str, options).await.map_err(Error::from)
+}
+
+async fn join(ticket_str: String, options: PairingOptions) -> anyhow::Result<PendingPairing> {
+    let ticket = PairingTicket::from_str(ticket_str.trim())
+        .map_err(|e| Error::InvalidTicket(e.to_string()))?;
+    let (endpoint, proxy_handle) = bind(&options).await?;
+    let connection = endpoint
+        .connect(ticket.addr().clone(), PAIR_ALPN)
+        .await
+        .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+
+    let nonce = rand::rng().random::<[u8; 32]>();
+    write_message(
+        &mut send,
+        &JoinHello {
+            name: options.name.clone(),
+            commitment: Hash::new(nonce),
+        },
+    )
+    .await?;
+    let hello: HostHello = read_message(&mut recv).await?;
+    check_name(&hello.name)?;
+    write_message(&mut send, &Reveal { nonce }).await?;
+
+    let peer = connection.remote_id();
+    let sas = short_auth_string(&peer, &endpoint.id(), &hello.nonce, &nonce);
+    Ok(PendingPairing {
+        peer,
+        peer_name: hello.name,
+        sas,
+        connection,
+        send,
+        recv,
+        _endpoint: Some(endpoint),
+        _proxy_handle: proxy_handle,
+    })
+}
+
+/// A completed handshake waiting for the user to compare the short authentication strings.
+pub struct PendingPairing {
+    pub peer: EndpointId,
+    pub peer_name: String,
+    /// Six digits that must match on both screens.
+    pub sas: String,
+    connection: Connection,
+    send: SendStream,
+    recv: RecvStream,
+    _endpoint: Option<Endpoint>,
+    _proxy_handle: Option<AbortOnDropHandle<()>>,
+}
+
+impl std::fmt::Debug for PendingPairing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingPairing")
+            .field("peer", &self.peer)
+            .field("peer_name", &self.peer_name)
+            .field("sas", &self.sas)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PendingPairing {
+    /// Sends this side's decision and waits for the other side's.
+    ///
+    /// Returns the new contact only if both users confirmed that the strings match.
+    pub async fn confirm(self, accept: bool) -> Result<Option<Contact>> {
+        self.decide(accept).await.map_err(Error::from)
+    }
+
+    async fn decide(mut self, accept: bool) -> anyhow::Result<Option<Contact>> {
+        write_message(&mut self.send, &Decision { accept }).await?;
+        self.send.finish()?;
+        let theirs: Decision = read_message(&mut self.recv).await?;
+        // Drain to the end and wait until our decision was read too, so closing
+        // the connection cannot discard it in flight.
+        self.recv.read_to_end(MAX_MESSAGE_SIZE).await?;
+        self.send.stopped().await.ok();
+        self.connection.close(0u32.into(), b"done");
+        if let Some(endpoint) = &self._endpoint {
+            endpoint.close().await;
+        }
+        if !(accept && theirs.accept) {
+            return Ok(None);
+        }
+        Ok(Some(Contact {
+            nickname: self.peer_name,
+            endpoint_id: self.peer,
+            last_seen: now_secs(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{DiscoveryOption, ProxyOption, RelayModeOption};
+    use crate::AddrInfoOptions;
+
+    fn options(name: &str) -> PairingOptions {
+        PairingOptions {
+            name: name.to_string(),
+            relay_mode: RelayModeOption::Disabled,
+            ticket_type: AddrInfoOptions::Addresses,
+            discovery: DiscoveryOption::Disabled,
+            proxy: ProxyOption::Disabled,
+            ..Default::default()
+        }
+    }
+
+    async fn pair(host_accepts: bool) -> (Option<Contact>, Option<Contact>, EndpointId) {
+        let host_key = iroh::SecretKey::generate(&mut rand::rng());
+        let host = host_pairing(PairingOptions {
+            secret_key: Some(host_key.clone()),
+            ..options("desktop")
+        })
+        .await
+        .unwrap();
+        let join = join_pairing(host.ticket.clone(), options("phone"));
+        let (hosted, joined) = tokio::join!(host.accept(), join);
+        let (hosted, joined) = (hosted.unwrap(), joined.unwrap());
+
+        assert_eq!(hosted.sas, joined.sas);
+        assert_eq!(hosted.sas.len(), 7);
+        assert_eq!(hosted.peer_name, "phone");
+        assert_eq!(joined.peer_name, "desktop");
+        assert_eq!(joined.peer, host_key.public());
+
+        let (a, b) = tokio::join!(hosted.confirm(host_accepts), joined.confirm(true));
+        host.close().await;
+        (a.unwrap(), b.unwrap(), host_key.public())
+    }
+
+    #[tokio::test]
+    async fn pairing_adds_contacts_when_both_confirm() {
+        let (on_host, on_joiner, host_id) = pair(true).await;
+        let on_host = on_host.unwrap();
+        let on_joiner = on_joiner.unwrap();
+        assert_eq!(on_host.nickname, "phone");
+        assert_eq!(on_joiner.nickname, "desktop");
+        assert_eq!(on_joiner.endpoint_id, host_id);
+    }
+
+    #[tokio::test]
+    async fn pairing_is_abandoned_when_one_side_declines() {
+        let (on_host, on_joiner, _) = pair(false).await;
+        assert!(on_host.is_none());
+        assert!(on_joiner.is_none());
+    }
+
+    #[test]
+    fn sas_depends_on_both_nonces() {
+        let a = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let b = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let sas = short_auth_string(&a, &b, &[1; 32], &[2; 32]);
+        assert_eq!(sas, short_auth_string(&a, &b, &[1; 32], &[2; 32]));
+        assert_ne!(sas, short_auth_string(&b, &a, &[1; 32], &[2; 32]));
+        assert_ne!(sas, short_auth_string(&a, &b, &[1; 32], &[3; 32]));
+    }
+}
@@ -0,0 +1,302 @@
+use crate::core::error::Error;
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointAddr, EndpointId,
+};
+use iroh_blobs::ticket::BlobTicket;
+use iroh_tickets::{ParseError, Ticket};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// ALPN on which a passphrase-protected share challenges receivers.
+///
+/// The receiver proves it knows the passphrase by answering a random challenge
+/// with a MAC keyed by the share's access key. Only endpoints that passed the
+/// challenge are then served over the blobs ALPN.
+pub const AUTH_ALPN: &[u8] = b"/sendme/auth/0";
+
+/// Argon2id cost for stretching the passphrase, to slow down guessing against a leaked
+/// ticket: 19 MiB of memory and two passes, as OWASP recommends.
+const STRETCH_MEMORY_KIB: u32 = 19 * 1024;
+const STRETCH_PASSES: u32 = 2;
+
+/// How long the provider waits for a receiver to acknowledge the challenge's verdict.
+const VERDICT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CIPHER_CONTEXT: &str = "sendme locked ticket v0 cipher";
+const MAC_CONTEXT: &str = "sendme locked ticket v0 mac";
+const ACCESS_CONTEXT: &str = "sendme locked ticket v0 access";
+
+/// Key a receiver uses to answer the provider's challenge.
+#[derive(Clone)]
+pub(crate) struct AccessKey([u8; 32]);
+
+impl std::fmt::Debug for AccessKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AccessKey(..)")
+    }
+}
+
+impl AccessKey {
+    fn proof(
+        &self,
+        challenge: &[u8; 32],
+        client: &EndpointId,
+        server: &EndpointId,
+    ) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&self.0);
+        hasher.update(challenge);
+        hasher.update(client.as_bytes());
+        hasher.update(server.as_bytes());
+        hasher.finalize()
+    }
+}
+
+/// Keys derived from a passphrase and a per-ticket salt.
+struct Keys {
+    cipher: [u8; 32],
+    mac: [u8; 32],
+    access: AccessKey,
+}
+
+impl Keys {
+    fn derive(passphrase: &str, salt: &[u8; 16]) -> Self {
+        let params = argon2::Params::new(STRETCH_MEMORY_KIB, STRETCH_PASSES, 1, Some(32))
+            .expect("valid argon2 parameters");
+        let mut key = [0u8; 32];
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .expect("a 16 byte salt and 32 byte output are within argon2 limits");
+        Self {
+            cipher: blake3::derive_key(CIPHER_CONTEXT, &key),
+            mac: blake3::derive_key(MAC_CONTEXT, &key),
+            access: AccessKey(blake3::derive_key(ACCESS_CONTEXT, &key)),
+        }
+    }
+
+    /// XORs `data` with the keystream; encryption and decryption are the same operation.
+    fn apply_keystream(&self, data: &mut [u8]) {
+        let mut keystream = vec![0u8; data.len()];
+        blake3::Hasher::new_keyed(&self.cipher)
+            .finalize_xof()
+            .fill(&mut keystream);
+        for (byte, key) in data.iter_mut().zip(keystream) {
+            *byte ^= key;
+        }
+    }
+
+    fn tag(&self, salt: &[u8; 16], ciphertext: &[u8]) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&self.mac);
+        hasher.update(salt);
+        hasher.update(ciphertext);
+        hasher.finalize()
+    }
+}
+
+/// A blob ticket encrypted with a passphrase.
+///
+/// Every ticket gets a fresh salt, so the derived keys are never reused.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, derive_more::Display)]
+#[display("{}", Ticket::serialize(self))]
+pub struct LockedTicket {
+    salt: [u8; 16],
+    ciphertext: Vec<u8>,
+    tag: [u8; 32],
+}
+
+impl Ticket for LockedTicket {
+    const KIND: &'static str = "locked";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).expect("postcard serialization failed")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+impl FromStr for LockedTicket {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ticket::deserialize(s)
+    }
+}
+
+/// Whether `ticket` is passphrase protected and needs `unlock_ticket` before use.
+pub fn is_locked_ticket(ticket: &str) -> bool {
+    LockedTicket::from_str(ticket.trim()).is_ok()
+}
+
+/// Passphrase-derived keys for a new share, created before its ticket exists so the
+/// challenge protocol can be registered on the router up front.
+pub(crate) struct TicketLock {
+    salt: [u8; 16],
+    keys: Keys,
+}
+
+impl TicketLock {
+    pub(crate) fn new(passphrase: &str) -> Self {
+        let salt = rand::rng().random::<[u8; 16]>();
+        let keys = Keys::derive(passphrase, &salt);
+        Self { salt, keys }
+    }
+
+    /// The key the provider checks receivers against.
+    pub(crate) fn access_key(&self) -> AccessKey {
+        self.keys.access.clone()
+    }
+
+    /// Encrypts `ticket` into an envelope that only the passphrase opens.
+    pub(crate) fn lock(&self, ticket: &BlobTicket) -> LockedTicket {
+        let mut ciphertext = ticket.to_string().into_bytes();
+        self.keys.apply_keystream(&mut ciphertext);
+        let tag = *self.keys.tag(&self.salt, &ciphertext).as_bytes();
+        LockedTicket {
+            salt: self.salt,
+            ciphertext,
+            tag,
+        }
+    }
+}
+
+/// Decrypts a locked ticket. Fails if the passphrase is wrong.
+pub(crate) fn unlock_ticket(
+    locked: &LockedTicket,
+    passphrase: &str,
+) -> anyhow::Result<(BlobTicket, AccessKey)> {
+    let keys = Keys::derive(passphrase, &locked.salt);
+    // blake3::Hash compares in constant time
+    if keys.tag(&locked.salt, &locked.ciphertext) != blake3::Hash::from_bytes(locked.tag) {
+        return Err(Error::Unauthorized("wrong passphrase".to_string()).into());
+    }
+    let mut plaintext = locked.ciphertext.clone();
+    keys.apply_keystream(&mut plaintext);
+    let ticket = BlobTicket::from_str(std::str::from_utf8(&plaintext)?)?;
+    Ok((ticket, keys.access))
+}
+
+/// Endpoints that proved knowledge of a share's passphrase.
+pub(crate) type Authorized = Arc<Mutex<HashSet<EndpointId>>>;
+
+/// Answers [`AUTH_ALPN`] connections with a challenge and records who passed it.
+#[derive(Debug, Clone)]
+pub(crate) struct AuthProtocol {
+    pub(crate) endpoint_id: EndpointId,
+    pub(crate) key: AccessKey,
+    pub(crate) authorized: Authorized,
+}
+
+impl AuthProtocol {
+    async fn challenge(&self, connection: &Connection) -> anyhow::Result<bool> {
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let mut hello = [0u8; 1];
+        recv.read_exact(&mut hello).await?;
+        let challenge = rand::rng().random::<[u8; 32]>();
+        send.write_all(&challenge).await?;
+        let mut proof = [0u8; 32];
+        recv.read_exact(&mut proof).await?;
+
+        let client = connection.remote_id();
+        let expected = self.key.proof(&challenge, &client, &self.endpoint_id);
+        let passed = expected == blake3::Hash::from_bytes(proof);
+        if passed {
+            self.authorized
+                .lock()
+                .expect("authorized set poisoned")
+                .insert(client);
+        }
+        send.write_all(&[passed as u8]).await?;
+        send.finish()?;
+        // Closing drops unacknowledged data, so wait until the verdict has arrived
+        tokio::time::timeout(VERDICT_TIMEOUT, send.stopped()).await??;
+        Ok(passed)
+    }
+}
+
+impl ProtocolHandler for AuthProtocol {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        match self.challenge(&connection).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::warn!("{} failed the passphrase challenge", connection.remote_id())
+            }
+            Err(e) => tracing::warn!("passphrase challenge aborted: {}", e),
+        }
+        connection.close(0u32.into(), b"done");
+        Ok(())
+    }
+}
+
+/// Proves to the provider at `addr` that this endpoint knows the passphrase.
+pub(crate) async fn authenticate(
+    endpoint: &Endpoint,
+    addr: EndpointAddr,
+    key: &AccessKey,
+) -> anyhow::Result<()> {
+    let server = addr.id;
+    let connection = endpoint
+        .connect(addr, AUTH_ALPN)
+        .await
+        .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    // The stream only reaches the provider once something is written on it
+    send.write_all(&[0]).await?;
+    let mut challenge = [0u8; 32];
+    recv.read_exact(&mut challenge).await?;
+    send.write_all(key.proof(&challenge, &endpoint.id(), &server).as_bytes())
+        .await?;
+    send.finish()?;
+    let mut verdict = [0u8; 1];
+    recv.read_exact(&mut verdict).await?;
+    connection.close(0u32.into(), b"done");
+    if verdict[0] != 1 {
+        return Err(Error::Unauthorized("the sender rejected the passphrase".to_string()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket() -> BlobTicket {
+        let key = iroh::SecretKey::generate(&mut rand::rng());
+        let addr = EndpointAddr::new(key.public()).with_ip_addr("127.0.0.1:4433".parse().unwrap());
+        BlobTicket::new(
+            addr,
+            iroh_blobs::Hash::new(b"hello"),
+            iroh_blobs::BlobFormat::HashSeq,
+        )
+    }
+
+    #[test]
+    fn locked_ticket_roundtrip() {
+        let ticket = ticket();
+        let lock = TicketLock::new("correct horse");
+        let locked = lock.lock(&ticket).to_string();
+        assert!(is_locked_ticket(&locked));
+        assert!(!is_locked_ticket(&ticket.to_string()));
+        assert!(!locked.contains(&ticket.to_string()));
+
+        let parsed = LockedTicket::from_str(&locked).unwrap();
+        let (unlocked, access_key) = unlock_ticket(&parsed, "correct horse").unwrap();
+        assert_eq!(unlocked, ticket);
+        assert_eq!(access_key.0, lock.access_key().0);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let locked = TicketLock::new("correct horse").lock(&ticket());
+        let err = unlock_ticket(&locked, "battery staple").unwrap_err();
+        assert!(err.to_string().contains("wrong passphrase"));
+    }
+}
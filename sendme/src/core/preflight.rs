@@ -0,0 +1,169 @@
+use crate::core::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Checks a collection against the receiver's limits and free disk space before any
+/// of its data is fetched.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Preflight {
+    /// Where the blob store keeps the downloaded data until it is exported.
+    pub(crate) store_dir: PathBuf,
+    pub(crate) output_dir: PathBuf,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) max_files: Option<u64>,
+}
+
+impl Preflight {
+    /// `missing_bytes` still have to land in the store; `payload_size` is then copied out
+    /// to the output directory.
+    pub(crate) fn check(
+        &self,
+        total_files: u64,
+        payload_size: u64,
+        missing_bytes: u64,
+    ) -> Result<(), Error> {
+        if let Some(max_files) = self.max_files {
+            if total_files > max_files {
+                return Err(Error::LimitExceeded(format!(
+                    "{} files offered, at most {} allowed",
+                    total_files, max_files
+                )));
+            }
+        }
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            if payload_size > max_total_bytes {
+                return Err(Error::LimitExceeded(format!(
+                    "{} bytes offered, at most {} allowed",
+                    payload_size, max_total_bytes
+                )));
+            }
+        }
+
+        let store = existing_ancestor(&self.store_dir);
+        let output = existing_ancestor(&self.output_dir);
+        if same_filesystem(&store, &output) {
+            ensure_space(&output, missing_bytes.saturating_add(payload_size))
+        } else {
+            ensure_space(&store, missing_bytes)?;
+            ensure_space(&output, payload_size)
+        }
+    }
+}
+
+fn ensure_space(path: &Path, needed: u64) -> Result<(), Error> {
+    match available_space(path) {
+        Ok(available) if available < needed => Err(Error::InsufficientSpace {
+            path: path.to_path_buf(),
+            needed,
+            available,
+        }),
+        Ok(_) => Ok(()),
+        // Not knowing is no reason to refuse; running out mid-transfer still fails cleanly
+        Err(e) => {
+            tracing::warn!(
+                "could not determine free space on {}: {}",
+                path.display(),
+                e
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The output directory usually does not exist yet; its free space is that of its parent.
+fn existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|p| p.exists())
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    // Compares drive letters or UNC shares
+    a.components().next() == b.components().next()
+}
+
+/// Bytes available to this process on the filesystem holding `path`.
+#[cfg(unix)]
+pub(crate) fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL terminated and `stat` is only read after statvfs filled it in
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes available to this process on the volume holding `path`.
+#[cfg(windows)]
+pub(crate) fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide` is NUL terminated and the other out pointers may be null
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn available_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preflight_enforces_limits_and_free_space() {
+        let td = tempfile::TempDir::new().unwrap();
+        let preflight = Preflight {
+            store_dir: td.path().join("store"),
+            output_dir: td.path().join("out/nested"),
+            max_total_bytes: Some(1000),
+            max_files: Some(3),
+        };
+        assert!(preflight.check(3, 1000, 1000).is_ok());
+        assert_eq!(
+            preflight.check(4, 10, 10).unwrap_err().code(),
+            "limit_exceeded"
+        );
+        assert_eq!(
+            preflight.check(1, 1001, 10).unwrap_err().code(),
+            "limit_exceeded"
+        );
+
+        let unlimited = Preflight {
+            max_total_bytes: None,
+            max_files: None,
+            ..preflight
+        };
+        let available = available_space(td.path()).unwrap();
+        let err = unlimited.check(1, available, available).unwrap_err();
+        assert_eq!(err.code(), "insufficient_space");
+    }
+}
@@ -0,0 +1,276 @@
+use crate::core::types::AppHandle;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How far back the speed is averaged over.
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+/// How often progress is emitted when the options leave it unset.
+pub(crate) const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Bytes per second over the last few seconds.
+#[derive(Debug, Default)]
+pub(crate) struct SpeedWindow {
+    /// Recent `(time, bytes)` samples, oldest first, covering at least the speed window.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl SpeedWindow {
+    pub(crate) fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Records that `bytes` were done in total at `now`.
+    pub(crate) fn record(&mut self, now: Instant, bytes: u64) {
+        self.samples.push_back((now, bytes));
+        // Keep one sample from before the window as the baseline for the speed
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|(time, _)| now.duration_since(*time) >= SPEED_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn speed_bps(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first_time, first)), Some((last_time, last))) => {
+                let elapsed = last_time.duration_since(*first_time).as_secs_f64();
+                if elapsed > 0.0 {
+                    last.saturating_sub(*first) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Tracks the progress of one transfer and emits it at most once per interval.
+///
+/// Progress events carry a colon separated payload:
+/// `bytes:total:speed:eta:file:files`, where `speed` is the bytes per second over the
+/// last few seconds times 1000, `eta` the seconds left or -1 while unknown, and `file`
+/// the 1-based position of the file in transfer out of `files`, which is 0 while unknown.
+pub(crate) struct ProgressTracker {
+    app_handle: AppHandle,
+    event_name: &'static str,
+    total: u64,
+    interval: Duration,
+    speed: SpeedWindow,
+    last_emit: Option<Instant>,
+    file: u64,
+    files: u64,
+}
+
+/// A point-in-time view of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Progress {
+    pub(crate) bytes: u64,
+    pub(crate) total: u64,
+    pub(crate) speed_bps: f64,
+    pub(crate) eta: Option<Duration>,
+    pub(crate) file: u64,
+    pub(crate) files: u64,
+}
+
+impl Progress {
+    fn payload(&self) -> String {
+        // Speed keeps 3 decimal places as an integer
+        let speed_int = (self.speed_bps * 1000.0) as i64;
+        let eta = self.eta.map_or(-1, |eta| eta.as_secs() as i64);
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            self.bytes, self.total, speed_int, eta, self.file, self.files
+        )
+    }
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(
+        app_handle: AppHandle,
+        event_name: &'static str,
+        interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            app_handle,
+            event_name,
+            total: 0,
+            interval: interval.unwrap_or(DEFAULT_PROGRESS_INTERVAL),
+            speed: SpeedWindow::default(),
+            last_emit: None,
+            file: 0,
+            files: 0,
+        }
+    }
+
+    pub(crate) fn app_handle(&self) -> &AppHandle {
+        &self.app_handle
+    }
+
+    /// Sets which file is being transferred, counting from 1.
+    pub(crate) fn set_file(&mut self, file: u64) {
+        self.file = file;
+    }
+
+    /// Starts a transfer of `total` bytes in `files` files, emitting it right away so
+    /// that the total shows up immediately.
+    pub(crate) fn start(&mut self, total: u64, files: u64) {
+        let now = Instant::now();
+        self.total = total;
+        self.files = files;
+        self.speed.clear();
+        self.speed.record(now, 0);
+        self.emit(now, 0);
+    }
+
+    /// Records that `bytes` of the total are done, emitting if the interval has passed.
+    pub(crate) fn update(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.record(now, bytes);
+        if self
+            .last_emit
+            .map_or(true, |last| now.duration_since(last) >= self.interval)
+        {
+            self.emit(now, bytes);
+        }
+    }
+
+    /// Records and emits that `bytes` are done, however recently progress was emitted.
+    pub(crate) fn report(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.record(now, bytes);
+        self.emit(now, bytes);
+    }
+
+    /// Emits the completed transfer, however recently progress was emitted.
+    pub(crate) fn finish(&mut self) {
+        self.file = self.files;
+        self.report(self.total);
+    }
+
+    fn record(&mut self, now: Instant, bytes: u64) {
+        self.speed.record(now, bytes.min(self.total));
+    }
+
+    fn snapshot(&self, bytes: u64) -> Progress {
+        let bytes = bytes.min(self.total);
+        let speed_bps = self.speed.speed_bps();
+        let remaining = self.total - bytes;
+        let eta = if remaining == 0 {
+            Some(Duration::ZERO)
+        } else if speed_bps > 0.0 {
+            Some(Duration::from_secs_f64(remaining as f64 / speed_bps))
+        } else {
+            None
+        };
+        Progress {
+            bytes,
+            total: self.total,
+            speed_bps,
+            eta,
+            file: self.file,
+            files: self.files,
+        }
+    }
+
+    fn emit(&mut self, now: Instant, bytes: u64) {
+        self.last_emit = Some(now);
+        let Some(handle) = &self.app_handle else {
+            return;
+        };
+        let payload = self.snapshot(bytes).payload();
+        if let Err(e) = handle.emit_event_with_payload(self.event_name, &payload) {
+            tracing::warn!("Failed to emit progress event: {}", e);
+        }
+    }
+}
+
+/// The 1-based position of the file that byte `offset` of the concatenated `sizes` falls in.
+pub(crate) fn file_at(sizes: &[u64], offset: u64) -> u64 {
+    let mut end = 0u64;
+    for (index, size) in sizes.iter().enumerate() {
+        end = end.saturating_add(*size);
+        if offset < end {
+            return index as u64 + 1;
+        }
+    }
+    sizes.len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::EventEmitter;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl EventEmitter for Recorder {
+        fn emit_event(&self, _event_name: &str) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn emit_event_with_payload(&self, _event_name: &str, payload: &str) -> Result<(), String> {
+            self.0.lock().unwrap().push(payload.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn speed_only_counts_the_recent_window() {
+        let mut tracker = ProgressTracker::new(None, "receive-progress", None);
+        tracker.total = 1000;
+        let start = Instant::now();
+        // A fast start followed by a slow stretch
+        tracker.record(start, 0);
+        tracker.record(start + Duration::from_secs(1), 500);
+        for second in 2..=10 {
+            tracker.record(start + Duration::from_secs(second), 500 + (second - 1) * 10);
+        }
+        let progress = tracker.snapshot(590);
+        // The cumulative average would be 59 B/s
+        assert!((progress.speed_bps - 10.0).abs() < 0.5, "{progress:?}");
+        assert_eq!(progress.eta.unwrap().as_secs(), 41);
+    }
+
+    #[test]
+    fn eta_is_unknown_until_bytes_move() {
+        let mut tracker = ProgressTracker::new(None, "receive-progress", None);
+        tracker.total = 1000;
+        let progress = tracker.snapshot(0);
+        assert_eq!(progress.eta, None);
+        assert_eq!(progress.payload(), "0:1000:0:-1:0:0");
+    }
+
+    #[test]
+    fn updates_are_throttled_but_the_end_is_not() {
+        let recorder = Arc::new(Recorder::default());
+        let handle: AppHandle = Some(recorder.clone());
+        let mut tracker =
+            ProgressTracker::new(handle, "transfer-progress", Some(Duration::from_secs(60)));
+        tracker.start(100, 2);
+        tracker.set_file(1);
+        for bytes in 1..100 {
+            tracker.update(bytes);
+        }
+        tracker.finish();
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].starts_with("0:100:"));
+        assert!(events[1].starts_with("100:100:"));
+        assert!(events[1].ends_with(":0:2:2"));
+    }
+
+    #[test]
+    fn file_position_follows_the_offset() {
+        let sizes = [10, 0, 5];
+        assert_eq!(file_at(&sizes, 0), 1);
+        assert_eq!(file_at(&sizes, 9), 1);
+        assert_eq!(file_at(&sizes, 10), 3);
+        assert_eq!(file_at(&sizes, 15), 3);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::core::error::{Error, Result};
+use iroh::EndpointId;
+use iroh_blobs::Hash;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Extended attribute that desktop tools read the origin of a downloaded file from.
+pub const ORIGIN_URL_ATTR: &str = "user.xdg.origin.url";
+/// Extended attribute holding the endpoint id of the device that sent the file.
+pub const SENDER_ATTR: &str = "user.sendme.sender";
+
+const ORIGIN_URL_SCHEME: &str = "sendme:";
+
+/// Where a received file came from, as recorded in its extended attributes.
+///
+/// Both fields are `None` for files that were not tagged. Browsers set
+/// `user.xdg.origin.url` too, so a file may have an origin URL but no sender.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// `sendme:<hash>` for files received with sendme.
+    pub origin_url: Option<String>,
+    pub sender: Option<EndpointId>,
+}
+
+impl Provenance {
+    pub(crate) fn new(hash: Hash, sender: EndpointId) -> Self {
+        Self {
+            origin_url: Some(format!("{ORIGIN_URL_SCHEME}{}", hash.to_hex())),
+            sender: Some(sender),
+        }
+    }
+
+    /// The collection the file was received in, if it was received with sendme.
+    pub fn hash(&self) -> Option<Hash> {
+        self.origin_url
+            .as_deref()?
+            .strip_prefix(ORIGIN_URL_SCHEME)?
+            .parse()
+            .ok()
+    }
+
+    /// Tags `path` with this provenance. Only Linux is supported.
+    pub(crate) fn write(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(origin_url) = &self.origin_url {
+            set_xattr(path, ORIGIN_URL_ATTR, origin_url.as_bytes())?;
+        }
+        if let Some(sender) = &self.sender {
+            set_xattr(path, SENDER_ATTR, sender.to_string().as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads back where a received file came from.
+///
+/// Files that were received without origin tagging, live on a filesystem without
+/// extended attributes or on a platform other than Linux have an empty provenance.
+pub fn provenance(path: &Path) -> Result<Provenance> {
+    read_provenance(path).map_err(Error::from)
+}
+
+fn read_provenance(path: &Path) -> anyhow::Result<Provenance> {
+    let origin_url = get_xattr(path, ORIGIN_URL_ATTR)?
+        .map(String::from_utf8)
+        .transpose()?;
+    let sender = match get_xattr(path, SENDER_ATTR)? {
+        Some(sender) => Some(std::str::from_utf8(&sender)?.parse()?),
+        None => None,
+    };
+    Ok(Provenance { origin_url, sender })
+}
+
+#[cfg(target_os = "linux")]
+fn c_strings(path: &Path, name: &str) -> std::io::Result<(std::ffi::CString, std::ffi::CString)> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok((
+        std::ffi::CString::new(path.as_os_str().as_bytes())?,
+        std::ffi::CString::new(name)?,
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> std::io::Result<()> {
+    let (path, name) = c_strings(path, name)?;
+    // SAFETY: both strings are NUL terminated and `value` is valid for `value.len()` bytes
+    let result = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn get_xattr(path: &Path, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+    let (path, name) = c_strings(path, name)?;
+    loop {
+        // SAFETY: both strings are NUL terminated; a null buffer of size 0 asks for the size
+        let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return missing_xattr(std::io::Error::last_os_error());
+        }
+        let mut value = vec![0u8; size as usize];
+        // SAFETY: `value` is valid for writes of `value.len()` bytes
+        let read = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if read < 0 {
+            let err = std::io::Error::last_os_error();
+            // The value grew between the two calls
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return missing_xattr(err);
+        }
+        value.truncate(read as usize);
+        return Ok(Some(value));
+    }
+}
+
+/// An attribute that is not set, or a filesystem that has none, is not an error.
+#[cfg(target_os = "linux")]
+fn missing_xattr(err: std::io::Error) -> std::io::Result<Option<Vec<u8>>> {
+    match err.raw_os_error() {
+        Some(libc::ENODATA) | Some(libc::ENOTSUP) => Ok(None),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_xattr(_path: &Path, _name: &str) -> std::io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provenance_roundtrip() {
+        let td = tempfile::TempDir::new().unwrap();
+        let path = td.path().join("received.txt");
+        std::fs::write(&path, "hello").unwrap();
+        assert_eq!(provenance(&path).unwrap(), Provenance::default());
+
+        let hash = Hash::new(b"collection");
+        let sender = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let tagged = Provenance::new(hash, sender);
+        match tagged.write(&path) {
+            Ok(()) => {}
+            // Some filesystems used for temporary directories do not take user attributes
+            Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return,
+            Err(e) => panic!("{e}"),
+        }
+        let read = provenance(&path).unwrap();
+        assert_eq!(read, tagged);
+        assert_eq!(read.hash(), Some(hash));
+    }
+}
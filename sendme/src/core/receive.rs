@@ -1,17 +1,40 @@
-use crate::core::types::{get_or_create_secret, AppHandle, ReceiveOptions, ReceiveResult};
-use iroh::{discovery::dns::DnsDiscovery, Endpoint};
+use crate::core::contacts::now_secs;
+use crate::core::error::{Error, Result};
+use crate::core::filename::{
+    export_paths, portable_component, unescape_component, FilenamePolicy, ESCAPE_MARKER,
+};
+use crate::core::history::{TransferDirection, TransferHistory, TransferOutcome, TransferRecord};
+use crate::core::multisource::{fetch_from_providers, parse_provider};
+use crate::core::offer::{preview, stored_sizes, Selection};
+use crate::core::passphrase::{
+    authenticate, is_locked_ticket, unlock_ticket, AccessKey, LockedTicket,
+};
+use crate::core::preflight::Preflight;
+use crate::core::progress::{file_at, ProgressTracker};
+use crate::core::provenance::Provenance;
+use crate::core::proxy::apply_proxy;
+use crate::core::send::{share_stored, StoredCollection};
+use crate::core::store::load_collected;
+use crate::core::streaming::{follow_stream, is_streaming_ticket, StreamingTicket};
+use crate::core::types::{endpoint_builder, AppHandle, ReceiveOptions, ReceiveResult};
+use crate::core::verify::{check_manifest, hash_file, Manifest};
+use data_encoding::HEXLOWER;
+use iroh::{endpoint::Connection, Endpoint, EndpointAddr};
 use iroh_blobs::{
     api::{
         blobs::{ExportMode, ExportOptions, ExportProgressItem},
-        remote::GetProgressItem,
+        remote::{GetProgressItem, LocalInfo},
         Store,
     },
     format::collection::Collection,
     get::{request::get_hash_seq_and_sizes, GetError, Stats},
     store::fs::FsStore,
     ticket::BlobTicket,
+    Hash, HashAndFormat,
 };
 use n0_future::StreamExt;
+use rand::Rng;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Instant;
@@ -26,29 +49,6 @@ fn emit_event(app_handle: &AppHandle, event_name: &str) {
     }
 }
 
-// Helper function to emit progress events with payload
-fn emit_progress_event(
-    app_handle: &AppHandle,
-    bytes_transferred: u64,
-    total_bytes: u64,
-    speed_bps: f64,
-) {
-    if let Some(handle) = app_handle {
-        let event_name = "receive-progress";
-
-        // Convert speed to integer (multiply by 1000 to preserve 3 decimal places)
-        let speed_int = (speed_bps * 1000.0) as i64;
-
-        // Create payload data as colon-separated string
-        let payload = format!("{}:{}:{}", bytes_transferred, total_bytes, speed_int);
-
-        // Emit the event with appropriate payload
-        if let Err(e) = handle.emit_event_with_payload(event_name, &payload) {
-            tracing::warn!("Failed to emit progress event: {}", e);
-        }
-    }
-}
-
 // Helper function to emit events with payload
 fn emit_event_with_payload(app_handle: &AppHandle, event_name: &str, payload: &str) {
     if let Some(handle) = app_handle {
@@ -62,137 +62,265 @@ pub async fn download(
     ticket_str: String,
     options: ReceiveOptions,
     app_handle: AppHandle,
-) -> anyhow::Result<ReceiveResult> {
-    let ticket = BlobTicket::from_str(&ticket_str)?;
+) -> Result<ReceiveResult> {
+    let history = options.history.clone().map(TransferHistory::new);
+    let started_at = now_secs();
+    let started = Instant::now();
+    let result = receive(ticket_str.clone(), options, app_handle)
+        .await
+        .map_err(Error::from);
+    if let Some(history) = history {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let record = match &result {
+            Ok(received) => TransferRecord {
+                direction: TransferDirection::Received,
+                started_at,
+                duration_ms,
+                peer: Some(received.peer),
+                hash: Some(received.hash.clone()),
+                files: received.files.clone(),
+                size: received.size,
+                outcome: TransferOutcome::Completed,
+                error: None,
+            },
+            Err(e) => {
+                // Locked tickets cannot be read without the passphrase, so they stay anonymous
+                let ticket = BlobTicket::from_str(ticket_str.trim()).ok();
+                TransferRecord {
+                    direction: TransferDirection::Received,
+                    started_at,
+                    duration_ms,
+                    peer: ticket.as_ref().map(|t| t.addr().id),
+                    hash: ticket.as_ref().map(|t| t.hash().to_hex().to_string()),
+                    files: Vec::new(),
+                    size: 0,
+                    outcome: match e {
+                        Error::Cancelled => TransferOutcome::Cancelled,
+                        _ => TransferOutcome::Failed,
+                    },
+                    error: Some(format!("{}: {}", e.code(), e)),
+                }
+            }
+        };
+        if let Err(e) = history.append(&record) {
+            tracing::warn!("Failed to record transfer in history: {}", e);
+        }
+    }
+    result
+}
 
-    let addr = ticket.addr().clone();
+async fn receive(
+    ticket_str: String,
+    mut options: ReceiveOptions,
+    app_handle: AppHandle,
+) -> anyhow::Result<ReceiveResult> {
+    let (addr, ticket_hash, access_key) = if is_locked_ticket(&ticket_str) {
+        let passphrase = options.passphrase.clone().ok_or_else(|| {
+            Error::Unauthorized("this ticket is protected by a passphrase".to_string())
+        })?;
+        let locked = LockedTicket::from_str(ticket_str.trim())
+            .map_err(|e| Error::InvalidTicket(e.to_string()))?;
+        // Stretching the passphrase keeps a thread busy for a while
+        let (ticket, access_key) =
+            tokio::task::spawn_blocking(move || unlock_ticket(&locked, &passphrase)).await??;
+        (ticket.addr().clone(), Some(ticket.hash()), Some(access_key))
+    } else if is_streaming_ticket(&ticket_str) {
+        // The share is still hashing; its hash is announced once it is done
+        let ticket = StreamingTicket::from_str(ticket_str.trim())
+            .map_err(|e| Error::InvalidTicket(e.to_string()))?;
+        (ticket.addr().clone(), None, None)
+    } else {
+        let ticket = BlobTicket::from_str(ticket_str.trim())
+            .map_err(|e| Error::InvalidTicket(e.to_string()))?;
+        (ticket.addr().clone(), Some(ticket.hash()), None)
+    };
 
-    let secret_key = get_or_create_secret()?;
+    let peer = addr.id;
+    let extra_providers = options
+        .providers
+        .iter()
+        .map(|provider| parse_provider(provider))
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    let mut builder = Endpoint::builder()
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
         .alpns(vec![])
-        .secret_key(secret_key)
-        .relay_mode(options.relay_mode.clone().into());
+        .secret_key(options.secret_key_for(&peer)?);
 
-    if ticket.addr().relay_urls().count() == 0 && ticket.addr().ip_addrs().count() == 0 {
-        builder = builder.discovery(DnsDiscovery::n0_dns());
-    }
     if let Some(addr) = options.magic_ipv4_addr {
         builder = builder.bind_addr_v4(addr);
     }
     if let Some(addr) = options.magic_ipv6_addr {
         builder = builder.bind_addr_v6(addr);
     }
+    // Keeps a SOCKS5 bridge (if any) running until the download is done
+    let (builder, _proxy_handle) =
+        apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
 
     let endpoint = builder.bind().await?;
 
     // Use system temp directory instead of current_dir for GUI app
     // This avoids polluting user directories and OS manages cleanup automatically
-    let dir_name = format!(".sendme-recv-{}", ticket.hash().to_hex());
-    let temp_base = std::env::temp_dir();
-    let iroh_data_dir = temp_base.join(&dir_name);
-    let db = FsStore::load(&iroh_data_dir).await?;
+    // A reseeded store outlives the download, so it must not be the one that the next
+    // download of the same collection opens
+    let dir_name = match ticket_hash {
+        _ if options.reseed.is_some() => {
+            let suffix = rand::rng().random::<[u8; 16]>();
+            format!(".sendme-seed-{}", HEXLOWER.encode(&suffix))
+        }
+        Some(hash) => format!(".sendme-recv-{}", hash.to_hex()),
+        None => format!(".sendme-recv-stream-{}", peer),
+    };
+    let persistent_store = options.store_dir.is_some();
+    let iroh_data_dir = match &options.store_dir {
+        Some(store_dir) => store_dir.clone(),
+        None => std::env::temp_dir().join(&dir_name),
+    };
+    // A persistent store drops the collections it no longer keeps a tag for
+    let db = if persistent_store {
+        tokio::fs::create_dir_all(&iroh_data_dir).await?;
+        load_collected(&iroh_data_dir).await?
+    } else {
+        FsStore::load(&iroh_data_dir).await?
+    };
     let db2 = db.clone();
 
-    let fut = async move {
-        let hash_and_format = ticket.hash_and_format();
-        let local = db.remote().local(hash_and_format).await?;
+    // Determine output directory
+    let output_dir = options.output_dir.clone().unwrap_or_else(|| {
+        dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap())
+    });
+    let preflight = Preflight {
+        store_dir: iroh_data_dir.clone(),
+        output_dir: output_dir.clone(),
+        max_total_bytes: options.max_total_bytes,
+        max_files: options.max_files,
+    };
+    let mut progress = ProgressTracker::new(
+        app_handle.clone(),
+        "receive-progress",
+        options.progress_interval,
+    );
 
-        let (stats, total_files, payload_size) = if !local.is_complete() {
-            // Emit receive-started event
-            emit_event(&app_handle, "receive-started");
+    let reseed = options.reseed.take();
+    let confirm = options.confirm.take();
+    let filename_policy = options.filename_policy;
+    let reseed_handle = app_handle.clone();
+    let fut = async move {
+        // Files fetched while following a stream are kept until the whole collection is
+        let (hash, _streamed) = match ticket_hash {
+            Some(hash) => (hash, Vec::new()),
+            None => {
+                emit_event(&app_handle, "receive-started");
+                // Files are only fetched while the sender hashes if nobody needs to be asked
+                let fetch = confirm.is_none();
+                follow_stream(
+                    &endpoint,
+                    addr.clone(),
+                    &db,
+                    &preflight,
+                    &mut progress,
+                    fetch,
+                )
+                .await?
+            }
+        };
+        if extra_providers
+            .iter()
+            .any(|(_, provider_hash)| provider_hash.is_some_and(|h| h != hash))
+        {
+            return Err(Error::InvalidTicket(
+                "a provider ticket is for a different collection".to_string(),
+            )
+            .into());
+        }
+        let hash_and_format = HashAndFormat::hash_seq(hash);
+        // Keeps what is fetched from being collected before it is tagged
+        let _fetching = db.tags().temp_tag(hash_and_format).await?;
+        let mut local = db.remote().local(hash_and_format).await?;
+        // The ticket's provider, once connected to
+        let mut connection = None;
 
-            let connection = match endpoint
-                .connect(addr.clone(), iroh_blobs::protocol::ALPN)
-                .await
-            {
-                Ok(conn) => conn,
-                Err(e) => {
-                    tracing::error!("Connection failed: {}", e);
-                    tracing::error!("Error details: {:?}", e);
-                    tracing::error!("Tried to connect to node: {}", addr.id);
-                    tracing::error!("With relay: {:?}", addr.relay_urls().collect::<Vec<_>>());
-                    tracing::error!(
-                        "With direct addrs: {:?}",
-                        addr.ip_addrs().collect::<Vec<_>>()
-                    );
-                    return Err(anyhow::anyhow!("Connection failed: {}", e));
-                }
-            };
+        // Only the collection's metadata is fetched before the user answers
+        let selection = match &confirm {
+            Some(prompt) => {
+                let sizes = if local.is_complete() {
+                    stored_sizes(&db, hash).await?
+                } else {
+                    let conn = connect(&endpoint, &addr, access_key.as_ref()).await?;
+                    let sizes = preview(&db, &conn, hash).await?;
+                    connection = Some(conn);
+                    sizes
+                };
+                let selection = prompt.ask(&app_handle, &db, hash, peer, sizes).await?;
+                local = db
+                    .remote()
+                    .local_for_request(selection.request(hash))
+                    .await?;
+                Some(selection)
+            }
+            None => None,
+        };
 
-            let sizes_result =
-                get_hash_seq_and_sizes(&connection, &hash_and_format.hash, 1024 * 1024 * 32, None)
-                    .await;
+        // Only part of the collection ends up here if the user left out some of its files
+        let subset = selection.as_ref().is_some_and(Selection::is_subset);
+        let (stats, total_files, payload_size, providers) = if !local.is_complete() {
+            // Emit receive-started event, unless following the stream already did
+            if ticket_hash.is_some() {
+                emit_event(&app_handle, "receive-started");
+            }
 
-            let (_hash_seq, sizes) = match sizes_result {
-                Ok((hash_seq, sizes)) => (hash_seq, sizes),
-                Err(e) => {
-                    tracing::error!("Failed to get sizes: {:?}", e);
-                    tracing::error!("Error type: {}", std::any::type_name_of_val(&e));
-                    return Err(show_get_error(e).into());
-                }
-            };
-            let _total_size = sizes.iter().copied().sum::<u64>();
-            // For payload size, we want the actual file data size
-            // The sizes array contains: [collection_size, file1_size, file2_size, ...]
-            // We skip the first element (collection metadata) but include all file sizes
-            let payload_size = sizes.iter().skip(1).copied().sum::<u64>();
-            let total_files = (sizes.len().saturating_sub(1)) as u64;
-
-            // Emit initial progress event (0%) so frontend can display total size immediately
-            emit_progress_event(&app_handle, 0, payload_size, 0.0);
-
-            let _local_size = local.local_bytes();
-            let get = db.remote().execute_get(connection, local.missing());
-            let mut stats = Stats::default();
-            let mut stream = get.stream();
-            let mut last_log_offset = 0u64;
-            let transfer_start_time = Instant::now();
-
-            while let Some(item) = stream.next().await {
-                match item {
-                    GetProgressItem::Progress(offset) => {
-                        // Emit progress events every 1MB
-                        if offset - last_log_offset > 1_000_000 {
-                            last_log_offset = offset;
-
-                            // Calculate speed and emit progress event
-                            let elapsed = transfer_start_time.elapsed().as_secs_f64();
-                            let speed_bps = if elapsed > 0.0 {
-                                offset as f64 / elapsed
-                            } else {
-                                0.0
-                            };
-
-                            emit_progress_event(
-                                &app_handle,
-                                offset.min(payload_size),
-                                payload_size,
-                                speed_bps,
-                            );
-                        }
+            // Some of the files are only fetched from the ticket's provider
+            if extra_providers.is_empty() || subset {
+                let connection = match connection.take() {
+                    Some(connection) => connection,
+                    None => connect(&endpoint, &addr, access_key.as_ref()).await?,
+                };
+                let (stats, total_files, payload_size) = match &selection {
+                    Some(selection) => {
+                        fetch_blobs(
+                            &db,
+                            connection,
+                            hash_and_format,
+                            &local,
+                            &selection.sizes(),
+                            &preflight,
+                            &mut progress,
+                        )
+                        .await?
                     }
-                    GetProgressItem::Done(value) => {
-                        stats = value;
-
-                        // Emit final progress event
-                        let elapsed = transfer_start_time.elapsed().as_secs_f64();
-                        let speed_bps = if elapsed > 0.0 {
-                            payload_size as f64 / elapsed
-                        } else {
-                            0.0
-                        };
-                        emit_progress_event(&app_handle, payload_size, payload_size, speed_bps);
-
-                        break;
+                    None => {
+                        fetch_missing(
+                            &db,
+                            connection,
+                            hash_and_format,
+                            &local,
+                            &preflight,
+                            &mut progress,
+                        )
+                        .await?
                     }
-                    GetProgressItem::Error(cause) => {
-                        tracing::error!("Download error: {:?}", cause);
-                        anyhow::bail!(show_get_error(cause));
+                };
+                (stats, total_files, payload_size, Vec::new())
+            } else {
+                if let (None, Some(access_key)) = (&connection, &access_key) {
+                    authenticate(&endpoint, addr.clone(), access_key).await?;
+                }
+                let mut providers = vec![addr.clone()];
+                for (provider, _) in &extra_providers {
+                    if providers.iter().all(|p| p.id != provider.id) {
+                        providers.push(provider.clone());
                     }
                 }
+                fetch_from_providers(
+                    &db,
+                    &endpoint,
+                    providers,
+                    hash_and_format,
+                    &local,
+                    &preflight,
+                    &mut progress,
+                )
+                .await?
             }
-            (stats, total_files, payload_size)
         } else {
             let total_files = local.children().unwrap() - 1;
             let payload_bytes = 0; // todo local.sizes().skip(2).map(Option::unwrap).sum::<u64>();
@@ -201,10 +329,13 @@ pub async fn download(
             emit_event(&app_handle, "receive-started");
             emit_event(&app_handle, "receive-completed");
 
-            (Stats::default(), total_files, payload_bytes)
+            (Stats::default(), total_files, payload_bytes, Vec::new())
         };
 
-        let collection = Collection::load(hash_and_format.hash, db.as_ref()).await?;
+        let mut collection = Collection::load(hash_and_format.hash, db.as_ref()).await?;
+        if let Some(selection) = &selection {
+            collection = selection.filter(collection);
+        }
 
         // Extract file names from collection and emit them BEFORE export
         // This allows the UI to show file names during the export phase
@@ -220,90 +351,519 @@ pub async fn download(
             emit_event_with_payload(&app_handle, "receive-file-names", &file_names_json);
         }
 
-        // Determine output directory
-        let output_dir = options.output_dir.unwrap_or_else(|| {
-            dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap())
-        });
+        let manifest = Manifest::from_collection(hash_and_format.hash, &collection);
+        let tag = format!("sendme-from-{peer}");
+        // What the sender sent last, if this download replaces all of it
+        let replaced = match db.tags().get(&tag).await? {
+            Some(previous)
+                if persistent_store && options.update && previous.hash != hash && !subset =>
+            {
+                Collection::load(previous.hash, db.as_ref()).await.ok()
+            }
+            _ => None,
+        };
+        // Worked out over the whole collection, so names renamed to " (n)" match the export
+        let exported = export_paths(
+            &output_dir,
+            collection.iter().map(|(name, _)| name.as_str()),
+            filename_policy,
+        )?
+        .into_iter()
+        .collect::<HashSet<_>>();
+        let origin = options
+            .tag_origin
+            .then(|| Provenance::new(hash_and_format.hash, peer));
+        export(
+            &db,
+            collection,
+            &output_dir,
+            options.filename_policy,
+            options.update,
+            origin,
+        )
+        .await?;
 
-        export(&db, collection, &output_dir).await?;
+        if persistent_store && !subset {
+            // Keeps the newest collection from each sender; the next one only fetches what
+            // changed, and the one it replaces is collected. Part of a collection is not
+            // worth keeping, it would be served and updated as if it were all there
+            db.tags().set(&tag, hash_and_format).await?;
+        }
+        if let Some(replaced) = replaced {
+            remove_dropped_files(&replaced, &exported, &output_dir, filename_policy).await?;
+        }
+
+        // Re-hash what landed on disk; other software may have altered it after export
+        if options.verify {
+            emit_event(&app_handle, "receive-verifying");
+            let report = check_manifest(&output_dir, &manifest).await?;
+            if !report.is_ok() {
+                let report_json = serde_json::to_string(&report).unwrap_or_default();
+                emit_event_with_payload(&app_handle, "receive-verify-failed", &report_json);
+                return Err(Error::VerificationFailed(format!(
+                    "{} altered, {} missing",
+                    report.mismatched.len(),
+                    report.missing.len()
+                ))
+                .into());
+            }
+            emit_event(&app_handle, "receive-verified");
+        }
+        let manifest_path = if options.save_manifest {
+            let path = output_dir.join(manifest_file_name(&hash_and_format.hash));
+            manifest.save(&path)?;
+            Some(path)
+        } else {
+            None
+        };
 
         // Emit completion event AFTER everything is done
         emit_event(&app_handle, "receive-completed");
 
-        anyhow::Ok((total_files, payload_size, stats, output_dir))
+        anyhow::Ok((
+            hash,
+            total_files,
+            payload_size,
+            stats,
+            output_dir,
+            file_names,
+            providers,
+            manifest_path,
+            subset,
+        ))
     };
 
-    let (total_files, payload_size, _stats, output_dir) = select! {
+    let (hash, total_files, payload_size, _stats, output_dir, files, providers, manifest, subset) = select! {
         x = fut => match x {
             Ok(x) => x,
             Err(e) => {
                 tracing::error!("Download operation failed: {}", e);
                 // make sure we shutdown the db before exiting
                 db2.shutdown().await?;
-                anyhow::bail!("error: {e}");
+                if !persistent_store {
+                    tokio::fs::remove_dir_all(&iroh_data_dir).await.ok();
+                }
+                return Err(e);
             }
         },
         _ = tokio::signal::ctrl_c() => {
             tracing::warn!("Operation cancelled by user");
             db2.shutdown().await?;
-            anyhow::bail!("Operation cancelled");
+            if !persistent_store {
+                tokio::fs::remove_dir_all(&iroh_data_dir).await.ok();
+            }
+            return Err(Error::Cancelled.into());
         }
     };
 
-    tokio::fs::remove_dir_all(&iroh_data_dir).await?;
+    let reseed = match reseed {
+        // Receivers of the reseed would get the files this download left out from nobody
+        Some(_) if subset => {
+            emit_event_with_payload(
+                &reseed_handle,
+                "receive-reseed-failed",
+                "only part of the collection was received",
+            );
+            if persistent_store {
+                db2.shutdown().await?;
+            } else {
+                tokio::fs::remove_dir_all(&iroh_data_dir).await?;
+            }
+            None
+        }
+        Some(send_options) => {
+            // A single file is shared as a file, anything else as the directory it went into
+            let exported = match files.as_slice() {
+                [name] if !name.contains('/') => {
+                    get_export_path(&output_dir, name, filename_policy)
+                        .unwrap_or_else(|_| output_dir.clone())
+                }
+                _ => output_dir.clone(),
+            };
+            let stored = StoredCollection {
+                store: db2,
+                dir: iroh_data_dir.clone(),
+                hash,
+                persistent: persistent_store,
+            };
+            match share_stored(exported, stored, send_options, reseed_handle.clone()).await {
+                Ok(share) => {
+                    emit_event_with_payload(&reseed_handle, "receive-reseeded", &share.ticket);
+                    Some(share)
+                }
+                Err(e) => {
+                    // The files are safely exported; only the share of them is lost
+                    tracing::warn!("Failed to share the received collection: {}", e);
+                    emit_event_with_payload(
+                        &reseed_handle,
+                        "receive-reseed-failed",
+                        &e.to_string(),
+                    );
+                    if !persistent_store {
+                        tokio::fs::remove_dir_all(&iroh_data_dir).await.ok();
+                    }
+                    None
+                }
+            }
+        }
+        None if persistent_store => {
+            db2.shutdown().await?;
+            None
+        }
+        None => {
+            tokio::fs::remove_dir_all(&iroh_data_dir).await?;
+            None
+        }
+    };
 
     Ok(ReceiveResult {
         message: format!("Downloaded {} files, {} bytes", total_files, payload_size),
         file_path: output_dir,
+        hash: hash.to_hex().to_string(),
+        files,
+        size: payload_size,
+        peer,
+        providers,
+        manifest,
+        reseed,
     })
 }
 
-async fn export(db: &Store, collection: Collection, output_dir: &Path) -> anyhow::Result<()> {
-    for (_i, (name, hash)) in collection.iter().enumerate() {
-        let target = get_export_path(output_dir, name)?;
-        if target.exists() {
-            anyhow::bail!("target {} already exists", target.display());
-        }
-        let mut stream = db
-            .export_with_opts(ExportOptions {
-                hash: *hash,
-                target,
-                mode: ExportMode::Copy,
-            })
-            .stream()
-            .await;
-
-        while let Some(item) = stream.next().await {
-            match item {
-                ExportProgressItem::Size(_size) => {
-                    // Skip progress updates for library version
-                }
-                ExportProgressItem::CopyProgress(_offset) => {
-                    // Skip progress updates for library version
-                }
-                ExportProgressItem::Done => {
-                    // Export completed
-                }
-                ExportProgressItem::Error(cause) => {
-                    anyhow::bail!("error exporting {}: {}", name, cause);
-                }
+/// Removes the files of `replaced` below `root` that are not among the `exported` files of
+/// the collection that replaced it. Files changed since they were exported are kept.
+async fn remove_dropped_files(
+    replaced: &Collection,
+    exported: &HashSet<PathBuf>,
+    root: &Path,
+    policy: FilenamePolicy,
+) -> anyhow::Result<()> {
+    // A collection whose names cannot be exported together never reached the disk
+    let Ok(paths) = export_paths(root, replaced.iter().map(|(name, _)| name.as_str()), policy)
+    else {
+        return Ok(());
+    };
+    for ((_, hash), path) in replaced.iter().zip(paths) {
+        if exported.contains(&path) || hash_file(path.clone()).await.ok() != Some(*hash) {
+            continue;
+        }
+        tokio::fs::remove_file(&path).await?;
+        // Directories the dropped file leaves empty go with it
+        for dir in path.ancestors().skip(1).take_while(|dir| *dir != root) {
+            if tokio::fs::remove_dir(dir).await.is_err() {
+                break;
             }
         }
     }
     Ok(())
 }
 
-fn get_export_path(root: &Path, name: &str) -> anyhow::Result<PathBuf> {
-    let parts = name.split('/');
+/// Name of the manifest saved by [`ReceiveOptions::save_manifest`].
+fn manifest_file_name(hash: &Hash) -> String {
+    format!(".sendme-{}.manifest.json", hash.to_hex())
+}
+
+/// Connects to the provider at `addr` for blobs, proving the passphrase first if given.
+async fn connect(
+    endpoint: &Endpoint,
+    addr: &EndpointAddr,
+    access_key: Option<&AccessKey>,
+) -> anyhow::Result<Connection> {
+    if let Some(access_key) = access_key {
+        authenticate(endpoint, addr.clone(), access_key).await?;
+    }
+    match endpoint
+        .connect(addr.clone(), iroh_blobs::protocol::ALPN)
+        .await
+    {
+        Ok(conn) => Ok(conn),
+        Err(e) => {
+            tracing::error!("Connection failed: {}", e);
+            tracing::error!("Error details: {:?}", e);
+            tracing::error!("Tried to connect to node: {}", addr.id);
+            tracing::error!("With relay: {:?}", addr.relay_urls().collect::<Vec<_>>());
+            tracing::error!(
+                "With direct addrs: {:?}",
+                addr.ip_addrs().collect::<Vec<_>>()
+            );
+            Err(Error::ConnectFailed(e.to_string()).into())
+        }
+    }
+}
+
+/// Fetches whatever part of the collection is not yet in `db`, reporting to `progress`.
+///
+/// Returns the transfer stats, the number of files and the payload size in bytes.
+pub(crate) async fn fetch_missing(
+    db: &Store,
+    connection: Connection,
+    hash_and_format: HashAndFormat,
+    local: &LocalInfo,
+    preflight: &Preflight,
+    progress: &mut ProgressTracker,
+) -> anyhow::Result<(Stats, u64, u64)> {
+    let sizes = fetch_sizes(&connection, hash_and_format.hash)
+        .await?
+        .into_iter()
+        .enumerate()
+        .map(|(index, size)| (index as u64, size))
+        .collect::<Vec<_>>();
+    fetch_blobs(
+        db,
+        connection,
+        hash_and_format,
+        local,
+        &sizes,
+        preflight,
+        progress,
+    )
+    .await
+}
+
+/// Asks the provider for the sizes of the collection's metadata and files, in order.
+pub(crate) async fn fetch_sizes(connection: &Connection, hash: Hash) -> anyhow::Result<Vec<u64>> {
+    match get_hash_seq_and_sizes(connection, &hash, 1024 * 1024 * 32, None).await {
+        Ok((_hash_seq, sizes)) => Ok(sizes.to_vec()),
+        Err(e) => {
+            tracing::error!("Failed to get sizes: {:?}", e);
+            tracing::error!("Error type: {}", std::any::type_name_of_val(&e));
+            Err(Error::PeerAborted(show_get_error(e).to_string()).into())
+        }
+    }
+}
+
+/// Fetches the blobs that `local` is missing, where `sizes` are the index in the hash
+/// sequence and the size of the collection's metadata and of the requested files, in the
+/// order they are sent.
+async fn fetch_blobs(
+    db: &Store,
+    connection: Connection,
+    hash_and_format: HashAndFormat,
+    local: &LocalInfo,
+    sizes: &[(u64, u64)],
+    preflight: &Preflight,
+    progress: &mut ProgressTracker,
+) -> anyhow::Result<(Stats, u64, u64)> {
+    let total_size = sizes
+        .iter()
+        .fold(0u64, |sum, (_, size)| sum.saturating_add(*size));
+    // For payload size, we want the actual file data size
+    // The sizes array contains: [collection_size, file1_size, file2_size, ...]
+    // We skip the first element (collection metadata) but include all file sizes
+    let payload_size = sizes
+        .iter()
+        .skip(1)
+        .fold(0u64, |sum, (_, size)| sum.saturating_add(*size));
+    let total_files = (sizes.len().saturating_sub(1)) as u64;
+
+    // Without the hash sequence the store cannot tell which files it already has, so
+    // fetch it first; after that only blobs missing from the store are requested
+    let refreshed;
+    let local = if db.has(hash_and_format.hash).await? {
+        local
+    } else {
+        db.remote()
+            .fetch(connection.clone(), HashAndFormat::raw(hash_and_format.hash))
+            .complete()
+            .await
+            .map_err(|e| Error::PeerAborted(e.to_string()))?;
+        refreshed = db.remote().local(hash_and_format).await?;
+        &refreshed
+    };
+
+    // Refuse before any data moves if the collection is too big for the limits or the disk
+    let missing_bytes = total_size.saturating_sub(local.local_bytes());
+    preflight.check(total_files, payload_size, missing_bytes)?;
+
+    // The offsets the download reports only count the blobs it requests, in order
+    let missing = local.missing();
+    let last = sizes.last().map_or(0, |(index, _)| *index);
+    let requested = missing
+        .ranges
+        .iter_non_empty_infinite()
+        .map(|(offset, _)| offset)
+        .take_while(|offset| *offset <= last + 1)
+        // Offset 0 is the hash sequence itself
+        .filter_map(|offset| offset.checked_sub(1))
+        .collect::<HashSet<_>>();
+    // The position among `sizes` and the size of each requested blob
+    let (positions, requested_sizes): (Vec<u64>, Vec<u64>) = sizes
+        .iter()
+        .enumerate()
+        .filter(|(_, (index, _))| requested.contains(index))
+        .map(|(position, (_, size))| (position as u64, *size))
+        .unzip();
+    // Files that are already stored count as done; the metadata is not payload
+    let stored = sizes
+        .iter()
+        .skip(1)
+        .filter(|(index, _)| !requested.contains(index))
+        .fold(0u64, |sum, (_, size)| sum.saturating_add(*size));
+    let metadata = match sizes.first() {
+        Some((index, size)) if requested.contains(index) => *size,
+        _ => 0,
+    };
+
+    // Emit initial progress event so frontend can display total size immediately
+    progress.start(payload_size, total_files);
+    if stored > 0 {
+        progress.report(stored);
+    }
+
+    let get = db.remote().execute_get(connection, missing);
+    let mut stats = Stats::default();
+    let mut stream = get.stream();
+
+    while let Some(item) = stream.next().await {
+        match item {
+            GetProgressItem::Progress(offset) => {
+                // Position 0 is the metadata, so the position of a file is its number
+                let blob = file_at(&requested_sizes, offset) as usize;
+                if let Some(position) = blob.checked_sub(1).and_then(|i| positions.get(i)) {
+                    progress.set_file(*position);
+                }
+                progress.update(stored.saturating_add(offset.saturating_sub(metadata)));
+            }
+            GetProgressItem::Done(value) => {
+                stats = value;
+                progress.finish();
+                break;
+            }
+            GetProgressItem::Error(cause) => {
+                tracing::error!("Download error: {:?}", cause);
+                return Err(Error::PeerAborted(show_get_error(cause).to_string()).into());
+            }
+        }
+    }
+    Ok((stats, total_files, payload_size))
+}
+
+/// Exports every file of the collection below `output_dir`, returning the bytes written.
+///
+/// Existing files are a conflict unless `update` is set. Then files that already have
+/// the right content are left alone and the others are replaced, each in a single step.
+pub(crate) async fn export(
+    db: &Store,
+    collection: Collection,
+    output_dir: &Path,
+    policy: FilenamePolicy,
+    update: bool,
+    mut origin: Option<Provenance>,
+) -> anyhow::Result<u64> {
+    let targets = export_paths(
+        output_dir,
+        collection.iter().map(|(name, _)| name.as_str()),
+        policy,
+    )?;
+    let mut total_size = 0;
+    for ((name, hash), target) in collection.iter().zip(targets) {
+        let replace = target.exists();
+        if replace && !(update && target.is_file()) {
+            return Err(Error::ExportConflict(target).into());
+        }
+        if replace && hash_file(target.clone()).await? == *hash {
+            tracing::debug!("{} is unchanged", name);
+            continue;
+        }
+        total_size += export_blob(db, name, *hash, &target, replace).await?;
+        if let Some(provenance) = &origin {
+            // The files are already in place; give up on tagging rather than on the transfer
+            if let Err(e) = provenance.write(&target) {
+                tracing::warn!("Not tagging received files with their origin: {}", e);
+                origin = None;
+            }
+        }
+    }
+    Ok(total_size)
+}
+
+/// Exports the blob `hash` of the file `name` to `target`, returning the bytes written.
+///
+/// With `replace`, the file is written next to `target` and renamed over it once
+/// complete, so the old version stays intact until the new one is whole.
+pub(crate) async fn export_blob(
+    db: &Store,
+    name: &str,
+    hash: Hash,
+    target: &Path,
+    replace: bool,
+) -> anyhow::Result<u64> {
+    let written = match replace {
+        true => update_path(target),
+        false => target.to_path_buf(),
+    };
+    let mut stream = db
+        .export_with_opts(ExportOptions {
+            hash,
+            target: written.clone(),
+            mode: ExportMode::Copy,
+        })
+        .stream()
+        .await;
+
+    let mut size = 0;
+    while let Some(item) = stream.next().await {
+        match item {
+            ExportProgressItem::Size(total) => {
+                size = total;
+            }
+            ExportProgressItem::CopyProgress(_offset) => {
+                // Skip progress updates for library version
+            }
+            ExportProgressItem::Done => {
+                // Export completed
+            }
+            ExportProgressItem::Error(cause) => {
+                anyhow::bail!("error exporting {}: {}", name, cause);
+            }
+        }
+    }
+    if replace {
+        tokio::fs::rename(&written, target).await?;
+    }
+    Ok(size)
+}
+
+/// Where the new version of `target` is written before it replaces the old one.
+fn update_path(target: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".sendme-update");
+    target.with_file_name(name)
+}
+
+/// Where `name` is exported below `root`.
+///
+/// Names that would escape `root` are always rejected. Names that some platform cannot
+/// store, like `aux.txt` on Windows, are renamed or rejected according to `policy`.
+pub(crate) fn get_export_path(root: &Path, name: &str, policy: FilenamePolicy) -> Result<PathBuf> {
     let mut path = root.to_path_buf();
-    for part in parts {
-        validate_path_component(part)?;
-        path.push(part);
+    for part in portable_name(name, policy)?.split('/') {
+        path.push(unescape_component(part));
     }
     Ok(path)
 }
 
+/// `name` with each component checked and made portable according to `policy`,
+/// still in the escaped form collections use.
+pub(crate) fn portable_name(name: &str, policy: FilenamePolicy) -> Result<String> {
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        validate_path_component(part).map_err(|e| Error::path_rejected(name, e.to_string()))?;
+        let portable = portable_component(part);
+        if policy == FilenamePolicy::Reject && portable != part {
+            return Err(Error::path_rejected(
+                name,
+                format!("{part:?} is not a valid file name on every platform"),
+            ));
+        }
+        parts.push(portable);
+    }
+    Ok(parts.join("/"))
+}
+
 fn validate_path_component(component: &str) -> anyhow::Result<()> {
+    // Escaped names are marked with a backslash; the rest cannot hold one
+    let component = component.strip_prefix(ESCAPE_MARKER).unwrap_or(component);
     anyhow::ensure!(!component.is_empty(), "empty path component");
     anyhow::ensure!(!component.contains('/'), "contains /");
     anyhow::ensure!(!component.contains('\\'), "contains \\");
@@ -314,9 +874,43 @@ fn validate_path_component(component: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn show_get_error(e: GetError) -> GetError {
+    match &e {
+        GetError::InitialNext { source, .. } => {
+            tracing::error!("initial connection error: {source}");
+        }
+        GetError::ConnectedNext { source, .. } => {
+            tracing::error!("connected error: {source}");
+        }
+        GetError::AtBlobHeaderNext { source, .. } => {
+            tracing::error!("reading blob header error: {source}");
+        }
+        GetError::Decode { source, .. } => {
+            tracing::error!("decoding error: {source}");
+        }
+        GetError::IrpcSend { source, .. } => {
+            tracing::error!("error sending over irpc: {source}");
+        }
+        GetError::AtClosingNext { source, .. } => {
+            tracing::error!("error at closing: {source}");
+        }
+        GetError::BadRequest { .. } => {
+            tracing::error!("bad request");
+        }
+        GetError::LocalFailure { source, .. } => {
+            tracing::error!("local failure {source:?}");
+        }
+    }
+    e
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::send::start_share;
+    use crate::core::types::{EventEmitter, Events, SendOptions};
+    use std::sync::Arc;
+    use std::time::Duration;
 
     #[test]
     fn validate_rejects_empty() {
@@ -362,54 +956,227 @@ mod tests {
     #[test]
     fn get_export_path_blocks_drive_prefix() {
         let root = Path::new("/tmp/test");
-        assert!(get_export_path(root, "C:foo").is_err());
+        assert!(get_export_path(root, "C:foo", FilenamePolicy::Map).is_err());
     }
 
     #[test]
     fn get_export_path_blocks_traversal() {
         let root = Path::new("/tmp/test");
-        assert!(get_export_path(root, "../etc/passwd").is_err());
-        assert!(get_export_path(root, "subdir/../../etc/passwd").is_err());
+        assert!(get_export_path(root, "../etc/passwd", FilenamePolicy::Map).is_err());
+        assert!(get_export_path(root, "subdir/../../etc/passwd", FilenamePolicy::Map).is_err());
     }
 
     #[test]
     fn get_export_path_blocks_backslash() {
-        assert!(get_export_path(Path::new("/tmp/test"), "file\\name").is_err());
+        assert!(
+            get_export_path(Path::new("/tmp/test"), "file\\name", FilenamePolicy::Map).is_err()
+        );
     }
 
     #[test]
     fn get_export_path_allows_normal() {
-        let p = get_export_path(Path::new("/tmp/test"), "subdir/file.txt").unwrap();
+        let p = get_export_path(
+            Path::new("/tmp/test"),
+            "subdir/file.txt",
+            FilenamePolicy::Map,
+        )
+        .unwrap();
         assert_eq!(p, PathBuf::from("/tmp/test/subdir/file.txt"));
     }
-}
 
-fn show_get_error(e: GetError) -> GetError {
-    match &e {
-        GetError::InitialNext { source, .. } => {
-            tracing::error!("initial connection error: {source}");
-        }
-        GetError::ConnectedNext { source, .. } => {
-            tracing::error!("connected error: {source}");
-        }
-        GetError::AtBlobHeaderNext { source, .. } => {
-            tracing::error!("reading blob header error: {source}");
-        }
-        GetError::Decode { source, .. } => {
-            tracing::error!("decoding error: {source}");
-        }
-        GetError::IrpcSend { source, .. } => {
-            tracing::error!("error sending over irpc: {source}");
-        }
-        GetError::AtClosingNext { source, .. } => {
-            tracing::error!("error at closing: {source}");
-        }
-        GetError::BadRequest { .. } => {
-            tracing::error!("bad request");
-        }
-        GetError::LocalFailure { source, .. } => {
-            tracing::error!("local failure {source:?}");
+    #[tokio::test]
+    async fn dropped_files_are_found_under_their_numbered_names() {
+        let td = tempfile::TempDir::new().unwrap();
+        let root = td.path();
+        std::fs::write(root.join("notes.txt"), "kept").unwrap();
+        std::fs::write(root.join("NOTES (2).txt"), "dropped").unwrap();
+        let replaced = Collection::from_iter([
+            ("notes.txt", Hash::new(b"kept")),
+            ("NOTES.txt", Hash::new(b"dropped")),
+        ]);
+        let exported = [root.join("notes.txt")].into_iter().collect();
+
+        remove_dropped_files(&replaced, &exported, root, FilenamePolicy::Map)
+            .await
+            .unwrap();
+        assert!(root.join("notes.txt").exists());
+        assert!(!root.join("NOTES (2).txt").exists());
+    }
+
+    #[tokio::test]
+    async fn receiver_reseeds_what_it_downloaded() {
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("slides.pdf");
+        std::fs::write(&src, "all hands").unwrap();
+        let receive_options = |out: PathBuf, reseed| ReceiveOptions {
+            output_dir: Some(out),
+            reseed,
+            ..ReceiveOptions::local()
+        };
+
+        let origin = start_share(src, SendOptions::local(), None).await.unwrap();
+        let first = download(
+            origin.ticket.clone(),
+            receive_options(td.path().join("first"), Some(SendOptions::local())),
+            None,
+        )
+        .await
+        .unwrap();
+        let reseed = first.reseed.expect("the receiver shares the collection");
+        assert_eq!(reseed.hash, origin.hash);
+        assert_eq!(reseed.entry_type, "file");
+        assert_ne!(reseed.ticket, origin.ticket);
+        assert!(reseed.blobs_data_dir.exists());
+
+        // The original sender is gone; the second receiver gets the file from the first
+        origin.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&origin.blobs_data_dir).ok();
+        let second = download(
+            reseed.ticket.clone(),
+            receive_options(td.path().join("second"), None),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(second.reseed.is_none());
+        assert_eq!(
+            std::fs::read_to_string(td.path().join("second/slides.pdf")).unwrap(),
+            "all hands"
+        );
+        reseed.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&reseed.blobs_data_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn persistent_receive_store_only_fetches_changes() {
+        let td = tempfile::TempDir::new().unwrap();
+        let project = td.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        let assets = vec![7u8; 300_000];
+        std::fs::write(project.join("assets.bin"), &assets).unwrap();
+        std::fs::write(project.join("notes.md"), "v1").unwrap();
+        let out = td.path().join("out");
+        let store_dir = td.path().join("receive-store");
+        // The store keeps the newest collection of each sender
+        let sender = iroh::SecretKey::generate(&mut rand::rng());
+
+        let mut sent = Vec::new();
+        let events = Arc::new(Events::default());
+        for version in ["v1", "v2"] {
+            std::fs::write(project.join("notes.md"), version).unwrap();
+            // Dropped from the folder before the second transfer
+            if version == "v1" {
+                std::fs::create_dir(project.join("drafts")).unwrap();
+                std::fs::write(project.join("drafts/old.md"), "draft").unwrap();
+            } else {
+                std::fs::remove_dir_all(project.join("drafts")).unwrap();
+            }
+            let history = TransferHistory::new(td.path().join(format!("sent-{version}.jsonl")));
+            let share = start_share(
+                project.clone(),
+                SendOptions {
+                    history: Some(history.path().to_path_buf()),
+                    secret_key: Some(sender.clone()),
+                    ..SendOptions::local()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+            download(
+                share.ticket.clone(),
+                ReceiveOptions {
+                    output_dir: Some(out.clone()),
+                    store_dir: Some(store_dir.clone()),
+                    update: true,
+                    ..ReceiveOptions::local()
+                },
+                (version == "v2").then(|| events.clone() as Arc<dyn EventEmitter>),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                std::fs::read_to_string(out.join("project/notes.md")).unwrap(),
+                version
+            );
+            share.router.shutdown().await.unwrap();
+            let mut records = Vec::new();
+            for _ in 0..50 {
+                records = history.list().unwrap();
+                if !records.is_empty() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            sent.push(records[0].size);
+            std::fs::remove_dir_all(&share.blobs_data_dir).ok();
         }
+        assert!(sent[0] >= assets.len() as u64);
+        // Only the changed note and the collection metadata cross the wire again
+        assert!(sent[1] < 10_000, "sent {} bytes for the update", sent[1]);
+        assert_eq!(
+            std::fs::read(out.join("project/assets.bin")).unwrap(),
+            assets
+        );
+        assert!(!out.join("project/drafts").exists());
+        // The stored assets count as done from the start, and progress never goes back
+        let progress = events
+            .named("receive-progress")
+            .iter()
+            .map(|payload| payload.split(':').next().unwrap().parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        assert!(progress[1] >= assets.len() as u64, "{progress:?}");
+        assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // The replaced collection is collected, the current one kept
+        let store = load_collected(&store_dir).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(!store.has(Hash::new(b"draft")).await.unwrap());
+        assert!(store.has(Hash::new(&assets)).await.unwrap());
+        store.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn download_refuses_collections_over_the_limits() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("photos");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.jpg"), vec![1u8; 4096]).unwrap();
+        std::fs::write(dir.join("b.jpg"), vec![2u8; 4096]).unwrap();
+
+        let share = start_share(dir, SendOptions::local(), None).await.unwrap();
+        let out = td.path().join("out");
+        let receive_options = |max_total_bytes, max_files| ReceiveOptions {
+            output_dir: Some(out.clone()),
+            max_total_bytes,
+            max_files,
+            ..ReceiveOptions::local()
+        };
+
+        let err = download(share.ticket.clone(), receive_options(None, Some(1)), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "limit_exceeded");
+        let err = download(
+            share.ticket.clone(),
+            receive_options(Some(8191), None),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), "limit_exceeded");
+        assert!(!out.join("photos").exists());
+
+        download(
+            share.ticket.clone(),
+            receive_options(Some(8192), Some(2)),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(out.join("photos/b.jpg").exists());
+
+        share.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
     }
-    e
 }
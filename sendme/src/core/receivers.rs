@@ -0,0 +1,219 @@
+use crate::core::progress::{SpeedWindow, DEFAULT_PROGRESS_INTERVAL};
+use crate::core::types::{AppHandle, ReceiverProgress, ReceiverState};
+use iroh::EndpointId;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The receivers connected to a share, with what each of them was sent.
+///
+/// Every change is emitted as a `receiver-progress` event carrying a JSON
+/// [`ReceiverProgress`]. Byte counts are throttled to one event per interval and
+/// connection; state changes always go out.
+#[derive(Clone)]
+pub struct Receivers(Arc<Mutex<Inner>>);
+
+struct Inner {
+    app_handle: AppHandle,
+    interval: Duration,
+    connections: BTreeMap<u64, Connection>,
+}
+
+struct Connection {
+    endpoint_id: Option<EndpointId>,
+    state: ReceiverState,
+    /// Bytes sent by the requests that are still going, by request id.
+    requests: HashMap<u64, u64>,
+    /// Bytes sent by the requests that ended.
+    finished: u64,
+    speed: SpeedWindow,
+    last_emit: Option<Instant>,
+}
+
+impl Connection {
+    fn bytes(&self) -> u64 {
+        self.finished + self.requests.values().sum::<u64>()
+    }
+
+    fn snapshot(&self, connection_id: u64) -> ReceiverProgress {
+        ReceiverProgress {
+            connection_id,
+            endpoint_id: self.endpoint_id,
+            bytes: self.bytes(),
+            speed_bps: self.speed.speed_bps(),
+            state: self.state,
+        }
+    }
+}
+
+impl fmt::Debug for Receivers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.list()).finish()
+    }
+}
+
+impl Receivers {
+    pub(crate) fn new(app_handle: AppHandle, interval: Option<Duration>) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            app_handle,
+            interval: interval.unwrap_or(DEFAULT_PROGRESS_INTERVAL),
+            connections: BTreeMap::new(),
+        })))
+    }
+
+    /// How often progress is emitted per connection.
+    pub(crate) fn interval(&self) -> Duration {
+        self.lock().interval
+    }
+
+    /// The receivers that are connected right now, in the order they connected.
+    pub fn list(&self) -> Vec<ReceiverProgress> {
+        let inner = self.lock();
+        inner
+            .connections
+            .iter()
+            .map(|(id, connection)| connection.snapshot(*id))
+            .collect()
+    }
+
+    pub(crate) fn connected(&self, connection_id: u64, endpoint_id: Option<EndpointId>) {
+        let mut inner = self.lock();
+        let connection = Connection {
+            endpoint_id,
+            state: ReceiverState::Connected,
+            requests: HashMap::new(),
+            finished: 0,
+            speed: SpeedWindow::default(),
+            last_emit: None,
+        };
+        inner.connections.insert(connection_id, connection);
+        inner.emit(connection_id, Instant::now());
+    }
+
+    /// Records that request `request_id` sent `bytes` so far on the connection, returning
+    /// what all of the connection's requests sent.
+    pub(crate) fn progress(&self, connection_id: u64, request_id: u64, bytes: u64) -> u64 {
+        let now = Instant::now();
+        let mut inner = self.lock();
+        let interval = inner.interval;
+        let Some(connection) = inner.connections.get_mut(&connection_id) else {
+            return bytes;
+        };
+        connection.requests.insert(request_id, bytes);
+        let total = connection.bytes();
+        connection.speed.record(now, total);
+        let changed = connection.state != ReceiverState::Transferring;
+        connection.state = ReceiverState::Transferring;
+        if changed
+            || connection
+                .last_emit
+                .map_or(true, |last| now.duration_since(last) >= interval)
+        {
+            inner.emit(connection_id, now);
+        }
+        total
+    }
+
+    /// Records that a request ended, `ok` telling whether it sent all it was asked for.
+    pub(crate) fn request_finished(&self, connection_id: u64, request_id: u64, ok: bool) {
+        let mut inner = self.lock();
+        let Some(connection) = inner.connections.get_mut(&connection_id) else {
+            return;
+        };
+        connection.finished += connection.requests.remove(&request_id).unwrap_or(0);
+        if !ok {
+            connection.state = ReceiverState::Failed;
+        } else if connection.requests.is_empty() && connection.state == ReceiverState::Transferring
+        {
+            connection.state = ReceiverState::Connected;
+        }
+        inner.emit(connection_id, Instant::now());
+    }
+
+    /// Records that the receiver on the connection has the whole collection.
+    pub(crate) fn completed(&self, connection_id: u64) {
+        let mut inner = self.lock();
+        let Some(connection) = inner.connections.get_mut(&connection_id) else {
+            return;
+        };
+        connection.state = ReceiverState::Completed;
+        inner.emit(connection_id, Instant::now());
+    }
+
+    /// Emits the connection as disconnected and forgets it.
+    pub(crate) fn closed(&self, connection_id: u64) {
+        let mut inner = self.lock();
+        let Some(mut connection) = inner.connections.remove(&connection_id) else {
+            return;
+        };
+        connection.state = ReceiverState::Disconnected;
+        inner.send(&connection.snapshot(connection_id));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.0.lock().expect("receivers poisoned")
+    }
+}
+
+impl Inner {
+    fn emit(&mut self, connection_id: u64, now: Instant) {
+        let Some(connection) = self.connections.get_mut(&connection_id) else {
+            return;
+        };
+        connection.last_emit = Some(now);
+        let progress = connection.snapshot(connection_id);
+        self.send(&progress);
+    }
+
+    fn send(&self, progress: &ReceiverProgress) {
+        let Some(handle) = &self.app_handle else {
+            return;
+        };
+        let payload = match serde_json::to_string(progress) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::warn!("Failed to serialize receiver progress: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = handle.emit_event_with_payload("receiver-progress", &payload) {
+            tracing::warn!("Failed to emit receiver progress: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_each_connection_on_its_own() {
+        let receivers = Receivers::new(None, Some(Duration::from_secs(60)));
+        let peer = iroh::SecretKey::generate(&mut rand::rng()).public();
+        receivers.connected(1, Some(peer));
+        receivers.connected(2, None);
+
+        receivers.progress(1, 0, 100);
+        receivers.progress(1, 1, 50);
+        receivers.progress(2, 0, 10);
+        receivers.request_finished(1, 0, true);
+        receivers.progress(1, 1, 80);
+        receivers.request_finished(2, 0, false);
+
+        let list = receivers.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].endpoint_id, Some(peer));
+        assert_eq!(list[0].bytes, 180);
+        assert_eq!(list[0].state, ReceiverState::Transferring);
+        assert_eq!(list[1].endpoint_id, None);
+        assert_eq!(list[1].bytes, 10);
+        assert_eq!(list[1].state, ReceiverState::Failed);
+
+        receivers.request_finished(1, 1, true);
+        receivers.completed(1);
+        assert_eq!(receivers.list()[0].state, ReceiverState::Completed);
+        receivers.closed(1);
+        assert_eq!(receivers.list().len(), 1);
+    }
+}
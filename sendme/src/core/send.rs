@@ -1,25 +1,44 @@
+use crate::core::approval::Approvals;
+use crate::core::completion::{CollectionBlobs, CompletionTracker, Receiver};
+use crate::core::error::{Error, Result};
+use crate::core::filename::escape_component;
+use crate::core::history::{ShareLog, TransferHistory};
+use crate::core::import_cache::{FileKey, ImportCache};
+use crate::core::passphrase::{AuthProtocol, Authorized, TicketLock, AUTH_ALPN};
+use crate::core::progress::ProgressTracker;
+use crate::core::proxy::apply_proxy;
+use crate::core::receivers::Receivers;
+use crate::core::store::load_collected;
+use crate::core::streaming::{ImportProgress, StreamProtocol, StreamingTicket, STREAM_ALPN};
 use crate::core::types::{
-    apply_options, get_or_create_secret, AddrInfoOptions, AppHandle, SendOptions, SendResult,
+    apply_options, endpoint_builder, get_or_create_secret, AppHandle, SendOptions, SendResult,
+    SkippedFile,
 };
 use anyhow::Context;
 use data_encoding::HEXLOWER;
-use iroh::{discovery::pkarr::PkarrPublisher, Endpoint, RelayMode};
+use iroh::{EndpointId, RelayMode};
 use iroh_blobs::{
     api::{
         blobs::{AddPathOptions, ImportMode},
         Store, TempTag,
     },
     format::collection::Collection,
-    provider::events::{ConnectMode, EventMask, EventSender, RequestMode},
+    protocol::{ChunkRanges, ChunkRangesExt},
+    provider::events::{
+        AbortReason, ConnectMode, EventMask, EventSender, ProviderMessage, RequestMode,
+        RequestUpdate,
+    },
     store::fs::FsStore,
     ticket::BlobTicket,
-    BlobFormat, BlobsProtocol,
+    BlobFormat, BlobsProtocol, Hash, HashAndFormat,
 };
 use n0_future::StreamExt;
 use n0_future::{task::AbortOnDropHandle, BufferedStreamExt};
 use rand::Rng;
 use std::{
+    collections::{HashMap, HashSet},
     path::{Component, Path, PathBuf},
+    sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::{select, sync::mpsc};
@@ -34,25 +53,25 @@ fn emit_event(app_handle: &AppHandle, event_name: &str) {
     }
 }
 
-fn emit_progress_event(
-    app_handle: &AppHandle,
-    bytes_transferred: u64,
-    total_bytes: u64,
-    speed_bps: f64,
-) {
+fn emit_event_with_payload(app_handle: &AppHandle, event_name: &str, payload: &str) {
     if let Some(handle) = app_handle {
-        let event_name = "transfer-progress";
-
-        let speed_int = (speed_bps * 1000.0) as i64;
-
-        let payload = format!("{}:{}:{}", bytes_transferred, total_bytes, speed_int);
-
-        if let Err(e) = handle.emit_event_with_payload(event_name, &payload) {
-            tracing::warn!("Failed to emit progress event: {}", e);
+        if let Err(e) = handle.emit_event_with_payload(event_name, payload) {
+            tracing::warn!("Failed to emit event {}: {}", event_name, e);
         }
     }
 }
 
+/// Tells the frontend which files were left out of a share, if any.
+pub(crate) fn emit_share_warnings(app_handle: &AppHandle, skipped: &[SkippedFile]) {
+    if skipped.is_empty() {
+        return;
+    }
+    match serde_json::to_string(skipped) {
+        Ok(payload) => emit_event_with_payload(app_handle, "share-warnings", &payload),
+        Err(e) => tracing::warn!("Failed to serialize skipped files: {}", e),
+    }
+}
+
 fn emit_active_connection_count(app_handle: &AppHandle, count: usize) {
     if let Some(handle) = app_handle {
         let event_name = "active-connection-count";
@@ -68,46 +87,120 @@ pub async fn start_share(
     path: PathBuf,
     options: SendOptions,
     app_handle: AppHandle,
+) -> Result<SendResult> {
+    share(path, None, options, app_handle)
+        .await
+        .map_err(Error::from)
+}
+
+/// A collection that is already in a store, e.g. because it was just downloaded.
+pub(crate) struct StoredCollection {
+    pub(crate) store: FsStore,
+    pub(crate) dir: PathBuf,
+    pub(crate) hash: Hash,
+    /// The store is kept when the share stops, like [`SendOptions::store_dir`].
+    pub(crate) persistent: bool,
+}
+
+/// Shares a collection that is already in `stored`, without importing anything.
+///
+/// `path` is where the files were exported to. Unless `stored` is persistent, the
+/// share takes over the store directory and removes it like any other when it stops.
+pub(crate) async fn share_stored(
+    path: PathBuf,
+    stored: StoredCollection,
+    options: SendOptions,
+    app_handle: AppHandle,
+) -> anyhow::Result<SendResult> {
+    share(path, Some(stored), options, app_handle).await
+}
+
+async fn share(
+    path: PathBuf,
+    stored: Option<StoredCollection>,
+    options: SendOptions,
+    app_handle: AppHandle,
 ) -> anyhow::Result<SendResult> {
-    let secret_key = get_or_create_secret()?;
+    let secret_key = match options.secret_key.clone() {
+        Some(secret_key) => secret_key,
+        None => get_or_create_secret()?,
+    };
 
     let relay_mode: RelayMode = options.relay_mode.clone().into();
 
-    let mut builder = Endpoint::builder()
+    let mut builder = endpoint_builder(&options.relay_mode, &options.discovery)
         .alpns(vec![iroh_blobs::protocol::ALPN.to_vec()])
-        .secret_key(secret_key)
-        .relay_mode(relay_mode.clone());
+        .secret_key(secret_key);
 
-    if options.ticket_type == AddrInfoOptions::Id {
-        builder = builder.discovery(PkarrPublisher::n0_dns());
-    }
     if let Some(addr) = options.magic_ipv4_addr {
         builder = builder.bind_addr_v4(addr);
     }
     if let Some(addr) = options.magic_ipv6_addr {
         builder = builder.bind_addr_v6(addr);
     }
+    let (builder, proxy_handle) = apply_proxy(builder, &options.proxy, &options.relay_mode).await?;
 
-    let suffix = rand::rng().random::<[u8; 16]>();
-    let temp_base = std::env::temp_dir();
-    let blobs_data_dir = temp_base.join(format!(".sendme-send-{}", HEXLOWER.encode(&suffix)));
-    if blobs_data_dir.exists() {
-        anyhow::bail!(
-            "can not share twice from the same directory: {}",
-            temp_base.display(),
-        );
-    }
+    let persistent_store = stored
+        .as_ref()
+        .map_or(options.store_dir.is_some(), |stored| stored.persistent);
+    let blobs_data_dir = match (&stored, &options.store_dir) {
+        (Some(stored), _) => stored.dir.clone(),
+        (None, Some(store_dir)) => store_dir.clone(),
+        (None, None) => {
+            let suffix = rand::rng().random::<[u8; 16]>();
+            let temp_base = std::env::temp_dir();
+            let blobs_data_dir =
+                temp_base.join(format!(".sendme-send-{}", HEXLOWER.encode(&suffix)));
+            if blobs_data_dir.exists() {
+                anyhow::bail!(
+                    "can not share twice from the same directory: {}",
+                    temp_base.display(),
+                );
+            }
+            blobs_data_dir
+        }
+    };
     let cwd = std::env::current_dir()?;
-    if cwd.join(&path) == cwd {
+    if stored.is_none() && cwd.join(&path) == cwd {
         anyhow::bail!("can not share from the current directory");
     }
+    let (stored_store, stored_hash) = match stored {
+        Some(stored) => (Some(stored.store), Some(stored.hash)),
+        None => (None, None),
+    };
 
     let path2 = path.clone();
     let blobs_data_dir2 = blobs_data_dir.clone();
     let (progress_tx, progress_rx) = mpsc::channel(32);
     let app_handle_clone = app_handle.clone();
     let entry_type = if path.is_file() { "file" } else { "directory" };
-    let entry_type_for_progress = entry_type.to_string();
+    // Derive the passphrase keys up front: the challenge protocol needs them before
+    // the ticket they encrypt exists. Stretching the passphrase keeps a thread busy for
+    // a while, so it stays off the async workers.
+    let ticket_lock = match options.passphrase.clone() {
+        Some(passphrase) => {
+            Some(tokio::task::spawn_blocking(move || TicketLock::new(&passphrase)).await?)
+        }
+        None => None,
+    };
+    let authorized = ticket_lock.as_ref().map(|_| Authorized::default());
+    let access_key = ticket_lock.as_ref().map(TicketLock::access_key);
+    let approvals = options
+        .approval
+        .as_ref()
+        .map(|approval| Approvals::new(app_handle.clone(), approval));
+    let gate = ConnectionGate {
+        allowed_peers: options.allowed_peers.clone(),
+        authorized: authorized.clone(),
+        approvals: approvals.clone(),
+    };
+    let history = options.history.clone().map(TransferHistory::new);
+    let strict = options.strict;
+    // A locked ticket must carry the hash, so passphrase shares always hash up front
+    let streaming = options.streaming && ticket_lock.is_none() && stored_hash.is_none();
+    let ticket_type = options.ticket_type;
+    let receivers = Receivers::new(app_handle.clone(), options.progress_interval);
+    let receivers2 = receivers.clone();
 
     let setup = async move {
         let t0 = Instant::now();
@@ -115,34 +208,99 @@ pub async fn start_share(
 
         let endpoint = builder.bind().await?;
 
-        let store = FsStore::load(&blobs_data_dir2).await?;
+        let store = match stored_store {
+            Some(store) => store,
+            // A persistent store drops the blobs of shares it no longer keeps
+            None if persistent_store => load_collected(&blobs_data_dir2).await?,
+            None => FsStore::load(&blobs_data_dir2).await?,
+        };
 
         let blobs = BlobsProtocol::new(
             &store,
             Some(EventSender::new(
                 progress_tx,
                 EventMask {
-                    connected: ConnectMode::Notify,
-                    get: RequestMode::NotifyLog,
+                    // Restricted shares vet every connecting endpoint and each of its
+                    // requests before serving them
+                    connected: if gate.is_restricted() {
+                        ConnectMode::Intercept
+                    } else {
+                        ConnectMode::Notify
+                    },
+                    get: if gate.is_restricted() {
+                        RequestMode::InterceptLog
+                    } else {
+                        RequestMode::NotifyLog
+                    },
                     ..EventMask::DEFAULT
                 },
             )),
         );
 
-        let import_result = import(path2, blobs.store()).await?;
+        let mut cache = persistent_store.then(|| ImportCache::load(&blobs_data_dir2));
+        // A streaming share hashes behind the ticket, announcing each file as it goes
+        let import_progress = streaming.then(|| ImportProgress::new(app_handle_clone.clone()));
+        let import_result = match (stored_hash, &import_progress) {
+            (Some(hash), _) => Some(load_stored(blobs.store(), hash).await?),
+            (None, Some(_)) => None,
+            (None, None) => {
+                Some(import(path2.clone(), blobs.store(), strict, cache.as_mut(), None).await?)
+            }
+        };
         let dt = t0.elapsed();
+        // Tells when a receiver has every blob; a streaming share knows them once hashed
+        let collection_blobs = CollectionBlobs::default();
+        if let Some((temp_tag, _, _, _)) = &import_result {
+            collection_blobs.set(blobs.store(), temp_tag.hash()).await?;
+        }
 
-        let (ref _temp_tag, size, ref _collection) = import_result;
+        let share_log = history.map(|history| {
+            let (hash, files) = match &import_result {
+                Some((temp_tag, _, collection, _)) => (
+                    temp_tag.hash().to_hex().to_string(),
+                    collection.iter().map(|(name, _)| name.clone()).collect(),
+                ),
+                None => (String::new(), Vec::new()),
+            };
+            Arc::new(ShareLog::new(history, hash, files))
+        });
+        let size = match &import_result {
+            Some((_, size, _, _)) => *size,
+            None => walk_size(&path2),
+        };
         let progress_handle = n0_future::task::spawn(show_provide_progress_with_logging(
             progress_rx,
-            app_handle_clone,
+            app_handle_clone.clone(),
             size,
-            entry_type_for_progress,
+            collection_blobs.clone(),
+            gate.clone(),
+            share_log.clone(),
+            receivers2,
         ));
 
-        let router = iroh::protocol::Router::builder(endpoint)
-            .accept(iroh_blobs::ALPN, blobs.clone())
-            .spawn();
+        let endpoint_id = endpoint.id();
+        let mut router =
+            iroh::protocol::Router::builder(endpoint).accept(iroh_blobs::ALPN, blobs.clone());
+        if let (Some(key), Some(authorized)) = (access_key, authorized) {
+            router = router.accept(
+                AUTH_ALPN,
+                AuthProtocol {
+                    endpoint_id,
+                    key,
+                    authorized,
+                },
+            );
+        }
+        if let Some(progress) = &import_progress {
+            router = router.accept(
+                STREAM_ALPN,
+                StreamProtocol {
+                    progress: progress.clone(),
+                    gate,
+                },
+            );
+        }
+        let router = router.spawn();
 
         let ep = router.endpoint();
         tokio::time::timeout(Duration::from_secs(30), async move {
@@ -152,9 +310,46 @@ pub async fn start_share(
         })
         .await?;
 
+        let import_handle = import_progress.map(|progress| {
+            let mut addr = router.endpoint().addr();
+            apply_options(&mut addr, ticket_type);
+            let store = blobs.store().clone();
+            n0_future::task::spawn(async move {
+                let result = import(path2, &store, strict, cache.as_mut(), Some(&progress)).await;
+                match result {
+                    Ok((temp_tag, _, collection, skipped)) => {
+                        let hash = temp_tag.hash();
+                        if let Err(e) = collection_blobs.set(&store, hash).await {
+                            tracing::warn!("Failed to read back the collection: {}", e);
+                        }
+                        progress.finished(Ok(hash));
+                        if let Some(share_log) = share_log {
+                            let files = collection.iter().map(|(name, _)| name.clone()).collect();
+                            share_log.set_collection(hash.to_hex().to_string(), files);
+                        }
+                        emit_share_warnings(&app_handle_clone, &skipped);
+                        let ticket = BlobTicket::new(addr, hash, BlobFormat::HashSeq);
+                        emit_event_with_payload(
+                            &app_handle_clone,
+                            "share-ready",
+                            &ticket.to_string(),
+                        );
+                        Some(temp_tag)
+                    }
+                    Err(e) => {
+                        tracing::warn!("hashing the share failed: {}", e);
+                        progress.finished(Err(e.to_string()));
+                        emit_event_with_payload(&app_handle_clone, "share-failed", &e.to_string());
+                        None
+                    }
+                }
+            })
+        });
+
         anyhow::Ok((
             router,
             import_result,
+            import_handle,
             dt,
             blobs_data_dir2,
             store,
@@ -162,77 +357,240 @@ pub async fn start_share(
         ))
     };
 
-    let (router, (temp_tag, size, _collection), _dt, _blobs_data_dir, store, progress_handle) = select! {
+    let (router, import_result, import_handle, _dt, _blobs_data_dir, store, progress_handle) = select! {
         x = setup => x?,
         _ = tokio::signal::ctrl_c() => {
-            anyhow::bail!("Operation cancelled");
+            return Err(Error::Cancelled.into());
         }
     };
-    let hash = temp_tag.hash();
 
     let mut addr = router.endpoint().addr();
 
     apply_options(&mut addr, options.ticket_type);
 
-    let ticket = BlobTicket::new(addr, hash, BlobFormat::HashSeq);
+    let (ticket, hash, size, temp_tag, skipped) = match import_result {
+        Some((temp_tag, size, _collection, skipped)) => {
+            emit_share_warnings(&app_handle, &skipped);
+            let hash = temp_tag.hash();
+            let ticket = BlobTicket::new(addr, hash, BlobFormat::HashSeq);
+            let ticket = match &ticket_lock {
+                Some(lock) => lock.lock(&ticket).to_string(),
+                None => ticket.to_string(),
+            };
+            (
+                ticket,
+                hash.to_hex().to_string(),
+                size,
+                Some(temp_tag),
+                skipped,
+            )
+        }
+        // Still hashing: `share-ready` carries the hash once it is known
+        None => (
+            StreamingTicket::new(addr).to_string(),
+            String::new(),
+            walk_size(&path),
+            None,
+            Vec::new(),
+        ),
+    };
 
     Ok(SendResult {
-        ticket: ticket.to_string(),
-        hash: hash.to_hex().to_string(),
+        ticket,
+        hash,
         size,
         entry_type: entry_type.to_string(),
+        skipped,
         router,
         temp_tag,
         blobs_data_dir,
+        persistent_store,
         _progress_handle: AbortOnDropHandle::new(progress_handle),
+        _import_handle: import_handle.map(AbortOnDropHandle::new),
         _store: store,
+        _proxy_handle: proxy_handle,
+        receivers,
+        approvals,
     })
 }
 
-async fn import(path: PathBuf, db: &Store) -> anyhow::Result<(TempTag, u64, Collection)> {
+/// Holds on to a collection that is already in `db`, as [`import`] would have imported it.
+async fn load_stored(
+    db: &Store,
+    hash: Hash,
+) -> anyhow::Result<(TempTag, u64, Collection, Vec<SkippedFile>)> {
+    let temp_tag = db.tags().temp_tag(HashAndFormat::hash_seq(hash)).await?;
+    let collection = Collection::load(hash, db).await?;
+    let mut size = 0u64;
+    for (_, hash) in collection.iter() {
+        size = size.saturating_add(db.observe(*hash).await?.size());
+    }
+    Ok((temp_tag, size, collection, Vec::new()))
+}
+
+/// Total size of the regular files under `path`, for progress before they are hashed.
+fn walk_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Decides which endpoints a share serves.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionGate {
+    /// Only serve these endpoints, if set.
+    allowed_peers: Option<HashSet<EndpointId>>,
+    /// Endpoints that answered the passphrase challenge, if the share has a passphrase.
+    authorized: Option<Authorized>,
+    /// Asks the user about each endpoint, if the share asks before sending.
+    approvals: Option<Approvals>,
+}
+
+impl ConnectionGate {
+    /// A gate without a passphrase, serving `allowed_peers` or anyone.
+    pub(crate) fn new(allowed_peers: Option<HashSet<EndpointId>>) -> Self {
+        Self {
+            allowed_peers,
+            authorized: None,
+            approvals: None,
+        }
+    }
+
+    pub(crate) fn is_restricted(&self) -> bool {
+        self.allowed_peers.is_some() || self.authorized.is_some() || self.approvals.is_some()
+    }
+
+    pub(crate) fn allows(&self, endpoint_id: Option<EndpointId>) -> bool {
+        let Some(endpoint_id) = endpoint_id else {
+            return !self.is_restricted();
+        };
+        let approved = self
+            .approvals
+            .as_ref()
+            .map_or(true, |approvals| approvals.is_approved(&endpoint_id));
+        self.vetted(&endpoint_id) && approved
+    }
+
+    /// Like [`Self::allows`], but asks the user about an endpoint that still needs approval.
+    pub(crate) async fn admit(&self, endpoint_id: Option<EndpointId>) -> bool {
+        match (&self.approvals, endpoint_id) {
+            (Some(approvals), Some(endpoint_id)) if self.vetted(&endpoint_id) => {
+                approvals.ask(endpoint_id).await
+            }
+            _ => self.allows(endpoint_id),
+        }
+    }
+
+    /// Whether the endpoint is allowed and authorized, approval aside.
+    fn vetted(&self, endpoint_id: &EndpointId) -> bool {
+        let allowed = self
+            .allowed_peers
+            .as_ref()
+            .map_or(true, |peers| peers.contains(endpoint_id));
+        let authorized = self.authorized.as_ref().map_or(true, |authorized| {
+            authorized
+                .lock()
+                .expect("authorized set poisoned")
+                .contains(endpoint_id)
+        });
+        allowed && authorized
+    }
+}
+
+/// Imports the file or directory at `path` into `db` as a collection.
+///
+/// Files that cannot be shared are left out and returned along with the collection,
+/// unless `strict` is set, in which case the first of them fails the import.
+///
+/// With a `cache`, files that are unchanged since an earlier import into the same
+/// store are not hashed again, and the collection is kept among the store's recent
+/// shares (see [`ImportCache::keep_share`]).
+pub(crate) async fn import(
+    path: PathBuf,
+    db: &Store,
+    strict: bool,
+    mut cache: Option<&mut ImportCache>,
+    progress: Option<&ImportProgress>,
+) -> anyhow::Result<(TempTag, u64, Collection, Vec<SkippedFile>)> {
     let parallelism = num_cpus::get();
-    let path = path.canonicalize()?;
-    anyhow::ensure!(path.exists(), "path {} does not exist", path.display());
+    let path = path
+        .canonicalize()
+        .map_err(|e| Error::path_rejected(path.display().to_string(), e.to_string()))?;
     let root = path.parent().context("context get parent")?;
-    let files = WalkDir::new(path.clone()).into_iter();
-    let data_sources: Vec<(String, PathBuf)> = files
-        .filter_map(|entry| {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(e) => {
-                    tracing::warn!("skipping inaccessible entry: {}", e);
-                    return None;
-                }
-            };
-            if !entry.file_type().is_file() {
-                return None;
+    let mut data_sources: Vec<(String, PathBuf)> = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in WalkDir::new(path.clone()) {
+        let source = match entry {
+            Ok(entry) if entry.file_type().is_dir() => continue,
+            Ok(entry) if !entry.file_type().is_file() => {
+                Err((entry.into_path(), "not a regular file".to_string()))
             }
-            let path = entry.into_path();
-            let relative = match path.strip_prefix(root) {
-                Ok(r) => r,
-                Err(e) => {
-                    tracing::warn!("skipping {}: {}", path.display(), e);
-                    return None;
-                }
-            };
-            match canonicalized_path_to_string(relative, true) {
-                Ok(name) => Some((name, path)),
-                Err(e) => {
-                    tracing::warn!("skipping {}: {}", path.display(), e);
-                    None
+            Ok(entry) => {
+                let path = entry.into_path();
+                match path.strip_prefix(root) {
+                    Ok(relative) => canonicalized_path_to_string(relative, true)
+                        .map(|name| (name, path.clone()))
+                        .map_err(|e| (path, e.to_string())),
+                    Err(e) => Err((path.clone(), e.to_string())),
                 }
             }
-        })
-        .collect();
+            Err(e) => Err((
+                e.path().unwrap_or(path.as_path()).to_path_buf(),
+                e.io_error()
+                    .map_or_else(|| e.to_string(), |io| io.to_string()),
+            )),
+        };
+        match source {
+            Ok(source) => data_sources.push(source),
+            Err((path, reason)) if strict => {
+                return Err(Error::path_rejected(path.display().to_string(), reason).into());
+            }
+            Err((path, reason)) => {
+                tracing::warn!("skipping {}: {}", path.display(), reason);
+                skipped.push(SkippedFile { path, reason });
+            }
+        }
+    }
+
+    if data_sources.is_empty() {
+        return Err(
+            Error::path_rejected(path.display().to_string(), "no valid files to share").into(),
+        );
+    }
 
-    anyhow::ensure!(!data_sources.is_empty(), "no valid files to share");
+    if let Some(progress) = progress {
+        let size = data_sources
+            .iter()
+            .filter_map(|(_, path)| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        progress.started(data_sources.len() as u64, size);
+    }
 
-    let mut names_and_tags = n0_future::stream::iter(data_sources)
-        .map(|(name, path)| {
+    let data_sources = data_sources.into_iter().map(|(name, path)| {
+        let key = cache
+            .as_ref()
+            .and_then(|_| FileKey::new(&std::fs::metadata(&path).ok()?));
+        let cached = key.and_then(|key| cache.as_ref()?.get(&path, key));
+        (name, path, key, cached)
+    });
+    let mut names_and_tags = n0_future::stream::iter(data_sources.collect::<Vec<_>>())
+        .map(|(name, path, key, cached)| {
             let db = db.clone();
             async move {
+                if let (Some(hash), Some(key)) = (cached, key) {
+                    if db.has(hash).await? {
+                        trace!("reusing the hash of unchanged {name}");
+                        let temp_tag = db.tags().temp_tag(HashAndFormat::raw(hash)).await?;
+                        return anyhow::Ok((name, temp_tag, key.size, None));
+                    }
+                }
                 let import = db.add_path_with_opts(AddPathOptions {
-                    path,
+                    path: path.clone(),
                     mode: ImportMode::TryReference,
                     format: iroh_blobs::BlobFormat::Raw,
                 });
@@ -259,24 +617,68 @@ async fn import(path: PathBuf, db: &Store) -> anyhow::Result<(TempTag, u64, Coll
                         }
                     }
                 };
-                anyhow::Ok((name, temp_tag, item_size))
+                let imported = key.map(|key| (path, key, temp_tag.hash()));
+                anyhow::Ok((name, temp_tag, item_size, imported))
             }
         })
         .buffered_unordered(parallelism)
+        .inspect(|imported| {
+            if let (Some(progress), Ok((name, temp_tag, size, _))) = (progress, imported) {
+                progress.hashed(name, temp_tag.hash(), *size);
+            }
+        })
         .collect::<Vec<_>>()
         .await
         .into_iter()
         .collect::<anyhow::Result<Vec<_>>>()?;
 
-    names_and_tags.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
-    let size = names_and_tags.iter().map(|(_, _, size)| *size).sum::<u64>();
+    if let Some(cache) = &mut cache {
+        for (_, _, _, imported) in &mut names_and_tags {
+            if let Some((path, key, hash)) = imported.take() {
+                cache.insert(&path, key, hash);
+            }
+        }
+    }
+
+    names_and_tags.sort_by(|(a, _, _, _), (b, _, _, _)| a.cmp(b));
+    let size = names_and_tags
+        .iter()
+        .map(|(_, _, size, _)| *size)
+        .sum::<u64>();
     let (collection, tags) = names_and_tags
         .into_iter()
-        .map(|(name, tag, _)| ((name, tag.hash()), tag))
+        .map(|(name, tag, _, _)| ((name, tag.hash()), tag))
         .unzip::<_, _, Collection, Vec<_>>();
     let temp_tag = collection.clone().store(db).await?;
     drop(tags);
-    Ok((temp_tag, size, collection))
+    if let Some(cache) = cache {
+        if let Err(e) = cache.keep_share(db, temp_tag.hash()).await {
+            tracing::warn!("Failed to keep the share in the store: {}", e);
+        }
+    }
+    Ok((temp_tag, size, collection, skipped))
+}
+
+/// How many bytes of a blob of `size` bytes `ranges` ask for, counting those before `end`.
+///
+/// As for the provider, asking for anything past the end asks for the last chunk.
+fn range_bytes(ranges: &ChunkRanges, size: u64, end: u64) -> u64 {
+    let blob = ChunkRanges::bytes(..size);
+    let mut asked = ranges & &blob;
+    if size > 0 && !(ranges - &blob).is_empty() {
+        asked |= ChunkRanges::chunk((size - 1) / 1024);
+    }
+    let end = end.min(size);
+    asked
+        .boundaries()
+        .chunks(2)
+        .map(|range| {
+            range[1]
+                .to_bytes()
+                .min(end)
+                .saturating_sub(range[0].to_bytes())
+        })
+        .sum()
 }
 
 pub fn canonicalized_path_to_string(
@@ -288,18 +690,8 @@ pub fn canonicalized_path_to_string(
         .as_ref()
         .components()
         .filter_map(|c| match c {
-            Component::Normal(x) => {
-                let c = match x.to_str() {
-                    Some(c) => c,
-                    None => return Some(Err(anyhow::anyhow!("invalid character in path"))),
-                };
-
-                if !c.contains('/') && !c.contains('\\') {
-                    Some(Ok(c))
-                } else {
-                    Some(Err(anyhow::anyhow!("invalid path component {:?}", c)))
-                }
-            }
+            // Names that are not UTF-8 or contain a backslash are escaped, not refused
+            Component::Normal(x) => Some(Ok(escape_component(x))),
             Component::RootDir => {
                 if must_be_relative {
                     Some(Err(anyhow::anyhow!("invalid path component {:?}", c)))
@@ -316,6 +708,307 @@ pub fn canonicalized_path_to_string(
     Ok(path_str)
 }
 
+/// Emits `receiver-completed` for a receiver that got the whole collection.
+fn emit_receiver_completed(app_handle: &AppHandle, connection_id: u64, receiver: Receiver) {
+    let endpoint_id = match receiver {
+        Receiver::Endpoint(endpoint_id) => Some(endpoint_id.to_string()),
+        Receiver::Connection(_) => None,
+    };
+    let payload = serde_json::json!({
+        "connection_id": connection_id,
+        "endpoint_id": endpoint_id,
+    });
+    emit_event_with_payload(app_handle, "receiver-completed", &payload.to_string());
+}
+
+/// Handles the provider events of a share: vets connecting endpoints, logs transfers,
+/// emits progress and tells when a receiver has the whole `collection`.
+pub(crate) async fn show_provide_progress_with_logging(
+    mut recv: mpsc::Receiver<ProviderMessage>,
+    app_handle: AppHandle,
+    total_file_size: u64,
+    collection: CollectionBlobs,
+    gate: ConnectionGate,
+    share_log: Option<std::sync::Arc<ShareLog>>,
+    receivers: Receivers,
+) -> anyhow::Result<()> {
+    use n0_future::FuturesUnordered;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    let mut tasks = FuturesUnordered::<n0_future::boxed::BoxFuture<()>>::new();
+    let completion = Arc::new(Mutex::new(CompletionTracker::new(collection)));
+    // Requests that are sending data, to count the active transfers
+    let active_requests = Arc::new(Mutex::new(HashSet::<(u64, u64)>::new()));
+    let has_emitted_started = Arc::new(AtomicBool::new(false));
+    // One tracker per connection, so that each of its requests carries on where the
+    // previous one left off
+    let trackers = Arc::new(Mutex::new(HashMap::<u64, ProgressTracker>::new()));
+    // The endpoint behind each connection that was let in, to vet its requests
+    let endpoints = Arc::new(Mutex::new(HashMap::<u64, Option<EndpointId>>::new()));
+
+    loop {
+        let item = tokio::select! {
+            biased;
+            item = recv.recv() => match item {
+                Some(item) => item,
+                None => break,
+            },
+            Some(_) = tasks.next(), if !tasks.is_empty() => continue,
+        };
+        // Everything but a request to serve is handled right away
+        let (connection_id, request_id, request, mut rx) = match item {
+            ProviderMessage::ClientConnected(msg) => {
+                let gate = gate.clone();
+                let completion = completion.clone();
+                let share_log = share_log.clone();
+                let receivers = receivers.clone();
+                let endpoints = endpoints.clone();
+                // Asking the user may take a while; other connections carry on meanwhile
+                tasks.push(Box::pin(async move {
+                    let connection_id = msg.inner.connection_id;
+                    let endpoint_id = msg.inner.endpoint_id;
+                    let result = if gate.admit(endpoint_id).await {
+                        Ok(())
+                    } else {
+                        tracing::warn!(
+                            "refusing connection from {:?}: not an allowed peer",
+                            endpoint_id
+                        );
+                        Err(AbortReason::Permission)
+                    };
+                    if result.is_ok() {
+                        if let Some(log) = &share_log {
+                            log.connected(connection_id, endpoint_id);
+                        }
+                        completion
+                            .lock()
+                            .expect("completion tracker poisoned")
+                            .connected(connection_id, endpoint_id);
+                        receivers.connected(connection_id, endpoint_id);
+                        endpoints
+                            .lock()
+                            .expect("endpoints poisoned")
+                            .insert(connection_id, endpoint_id);
+                    }
+                    msg.tx.send(result).await.ok();
+                }));
+                continue;
+            }
+            ProviderMessage::ClientConnectedNotify(msg) => {
+                if let Some(log) = &share_log {
+                    log.connected(msg.connection_id, msg.endpoint_id);
+                }
+                completion
+                    .lock()
+                    .expect("completion tracker poisoned")
+                    .connected(msg.connection_id, msg.endpoint_id);
+                receivers.connected(msg.connection_id, msg.endpoint_id);
+                continue;
+            }
+            ProviderMessage::ConnectionClosed(msg) => {
+                if let Some(log) = &share_log {
+                    log.closed(msg.connection_id);
+                }
+                completion
+                    .lock()
+                    .expect("completion tracker poisoned")
+                    .closed(msg.connection_id);
+                receivers.closed(msg.connection_id);
+                trackers
+                    .lock()
+                    .expect("progress trackers poisoned")
+                    .remove(&msg.connection_id);
+                endpoints
+                    .lock()
+                    .expect("endpoints poisoned")
+                    .remove(&msg.connection_id);
+                continue;
+            }
+            ProviderMessage::GetRequestReceived(msg) => {
+                let connection_id = msg.inner.connection_id;
+                let endpoint_id = endpoints
+                    .lock()
+                    .expect("endpoints poisoned")
+                    .get(&connection_id)
+                    .copied()
+                    .flatten();
+                // Each request is vetted again, the endpoint may have been turned away since
+                if !gate.allows(endpoint_id) {
+                    tracing::warn!(
+                        "refusing request from {:?}: not an allowed peer",
+                        endpoint_id
+                    );
+                    msg.tx.send(Err(AbortReason::Permission)).await.ok();
+                    continue;
+                }
+                msg.tx.send(Ok(())).await.ok();
+                (
+                    connection_id,
+                    msg.inner.request_id,
+                    msg.inner.request,
+                    msg.rx,
+                )
+            }
+            ProviderMessage::GetRequestReceivedNotify(msg) => (
+                msg.inner.connection_id,
+                msg.inner.request_id,
+                msg.inner.request.clone(),
+                msg.rx,
+            ),
+            _ => continue,
+        };
+        let app_handle = app_handle.clone();
+        let completion = completion.clone();
+        let active_requests = active_requests.clone();
+        let has_emitted_started = has_emitted_started.clone();
+        let share_log = share_log.clone();
+        let trackers = trackers.clone();
+        let receivers = receivers.clone();
+
+        tasks.push(Box::pin(async move {
+            let complete = |receiver: Option<Receiver>| {
+                if let Some(receiver) = receiver {
+                    receivers.completed(connection_id);
+                    emit_receiver_completed(&app_handle, connection_id, receiver);
+                    emit_event(&app_handle, "transfer-completed");
+                }
+            };
+            // Records a blob that went out whole, as far as it was asked for
+            let served = |hash: Hash, ranges: &ChunkRanges| {
+                complete(
+                    completion
+                        .lock()
+                        .expect("completion tracker poisoned")
+                        .served(connection_id, hash, ranges),
+                );
+            };
+            // What the receiver already has is not asked for again
+            complete(
+                completion
+                    .lock()
+                    .expect("completion tracker poisoned")
+                    .requested(connection_id, &request),
+            );
+            // Updates the connection's tracker, once the first request started it
+            let progress = |update: &dyn Fn(&mut ProgressTracker)| {
+                let mut trackers = trackers.lock().expect("progress trackers poisoned");
+                if let Some(tracker) = trackers.get_mut(&connection_id) {
+                    update(tracker);
+                }
+            };
+            // The request counts the blobs already sent plus the offset into the current one
+            let mut done_before = 0u64;
+            // The blob being sent, its size and the ranges of it that were asked for
+            let mut current: Option<(Hash, u64, ChunkRanges)> = None;
+            let mut started = false;
+            let mut completed = false;
+
+            while let Ok(Some(update)) = rx.recv().await {
+                match update {
+                    RequestUpdate::Started(m) => {
+                        if !started {
+                            started = true;
+                            let active_count = {
+                                let mut active =
+                                    active_requests.lock().expect("active requests poisoned");
+                                active.insert((connection_id, request_id));
+                                active.len()
+                            };
+                            emit_active_connection_count(&app_handle, active_count);
+                            if !has_emitted_started.swap(true, Ordering::SeqCst) {
+                                emit_event(&app_handle, "transfer-started");
+                            }
+                            // The provider does not know how many files the receiver asks for
+                            trackers
+                                .lock()
+                                .expect("progress trackers poisoned")
+                                .entry(connection_id)
+                                .or_insert_with(|| {
+                                    let mut tracker = ProgressTracker::new(
+                                        app_handle.clone(),
+                                        "transfer-progress",
+                                        Some(receivers.interval()),
+                                    );
+                                    tracker.start(total_file_size, 0);
+                                    tracker
+                                });
+                        }
+                        // Moving on means the previous blob is done
+                        if let Some((hash, size, ranges)) = current.take() {
+                            done_before += range_bytes(&ranges, size, size);
+                            served(hash, &ranges);
+                        }
+                        let ranges = request
+                            .ranges
+                            .iter_infinite()
+                            .nth(m.index as usize)
+                            .cloned()
+                            .unwrap_or_else(ChunkRanges::empty);
+                        current = Some((m.hash, m.size, ranges));
+                        // Index 0 is the hash sequence and 1 the collection metadata
+                        if m.index >= 2 {
+                            progress(&|tracker| tracker.set_file(m.index - 1));
+                        }
+                    }
+                    RequestUpdate::Progress(m) => {
+                        let sent_of_current = current.as_ref().map_or(0, |(_, size, ranges)| {
+                            range_bytes(ranges, *size, m.end_offset)
+                        });
+                        let bytes = done_before + sent_of_current;
+                        let sent = receivers.progress(connection_id, request_id, bytes);
+                        progress(&|tracker| tracker.update(sent));
+                    }
+                    RequestUpdate::Completed(m) => {
+                        if let Some(log) = &share_log {
+                            log.request_finished(connection_id, &m.stats, true);
+                        }
+                        // The receiver's bytes are up to date before it may complete
+                        let current = current.take();
+                        if let Some((_, size, ranges)) = &current {
+                            done_before += range_bytes(ranges, *size, *size);
+                        }
+                        let sent = receivers.progress(connection_id, request_id, done_before);
+                        if let Some((hash, _, ranges)) = current {
+                            served(hash, &ranges);
+                        }
+                        progress(&|tracker| tracker.report(sent));
+                        completed = true;
+                    }
+                    RequestUpdate::Aborted(m) => {
+                        if let Some(log) = &share_log {
+                            log.request_finished(connection_id, &m.stats, false);
+                        }
+                        tracing::warn!(
+                            "Request aborted: conn {} req {}",
+                            connection_id,
+                            request_id
+                        );
+                    }
+                }
+            }
+            receivers.request_finished(connection_id, request_id, completed);
+
+            if started {
+                let active_count = {
+                    let mut active = active_requests.lock().expect("active requests poisoned");
+                    active.remove(&(connection_id, request_id));
+                    active.len()
+                };
+                emit_active_connection_count(&app_handle, active_count);
+                // A request that ended early failed, unless another one is still going
+                if !completed && active_count == 0 {
+                    emit_event(&app_handle, "transfer-failed");
+                }
+            }
+        }));
+    }
+
+    while tasks.next().await.is_some() {}
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -323,9 +1016,24 @@ mod tests {
 
     #[cfg(unix)]
     #[test]
-    fn canonicalized_path_rejects_backslash() {
+    fn canonicalized_path_escapes_backslash() {
         let path = Path::new("system-systemd\\x2dcryptsetup.slice");
-        assert!(canonicalized_path_to_string(path, true).is_err());
+        assert_eq!(
+            canonicalized_path_to_string(path, true).unwrap(),
+            "\\system-systemd%5Cx2dcryptsetup.slice"
+        );
+    }
+
+    #[test]
+    fn range_bytes_counts_what_was_asked_for() {
+        let size = 5 * 1024 + 100;
+        assert_eq!(range_bytes(&ChunkRanges::all(), size, size), size);
+        assert_eq!(range_bytes(&ChunkRanges::empty(), size, size), 0);
+        // The last chunk is only as long as the blob
+        assert_eq!(range_bytes(&ChunkRanges::last_chunk(), size, size), 100);
+        assert_eq!(range_bytes(&ChunkRanges::chunks(1..3), size, size), 2048);
+        // Only what was sent so far
+        assert_eq!(range_bytes(&ChunkRanges::chunks(1..3), size, 2048), 1024);
     }
 
     #[test]
@@ -346,310 +1054,252 @@ mod tests {
 
     #[cfg(unix)]
     #[tokio::test]
-    async fn import_skips_invalid_files() {
-        use tempfile::TempDir;
+    async fn import_round_trips_unusual_names() {
+        use crate::core::filename::FilenamePolicy;
+        use crate::core::receive::export;
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
 
-        let td = TempDir::new().unwrap();
+        let td = tempfile::TempDir::new().unwrap();
         let dir = td.path().join("testdir");
         std::fs::create_dir_all(&dir).unwrap();
-        std::fs::write(dir.join("good.txt"), "hello").unwrap();
-        std::fs::write(dir.join(format!("bad{}file.txt", '\\')), "bad").unwrap();
-
-        let path = dir.canonicalize().unwrap();
-        let root = path.parent().unwrap();
-        let data_sources: Vec<(String, PathBuf)> = WalkDir::new(path.clone())
-            .into_iter()
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
-                if !entry.file_type().is_file() {
-                    return None;
-                }
-                let path = entry.into_path();
-                let relative = path.strip_prefix(root).ok()?;
-                canonicalized_path_to_string(relative, true)
-                    .ok()
-                    .map(|name| (name, path))
-            })
-            .collect();
-
-        assert_eq!(data_sources.len(), 1, "should skip file with backslash");
-        assert!(data_sources[0].0.contains("good.txt"));
-    }
-}
-
-async fn show_provide_progress_with_logging(
-    mut recv: mpsc::Receiver<iroh_blobs::provider::events::ProviderMessage>,
-    app_handle: AppHandle,
-    total_file_size: u64,
-    entry_type: String,
-) -> anyhow::Result<()> {
-    use n0_future::FuturesUnordered;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::sync::Arc;
-    use tokio::sync::Mutex;
+        let names = [
+            OsStr::new("good.txt"),
+            OsStr::new("bad\\file.txt"),
+            OsStr::from_bytes(b"caf\xe9.txt"),
+            OsStr::new("100%41.txt"),
+        ];
+        for name in names {
+            std::fs::write(dir.join(name), name.as_bytes()).unwrap();
+        }
 
-    let mut tasks = FuturesUnordered::new();
+        let store = iroh_blobs::store::mem::MemStore::new();
+        let (_, _, collection, skipped) = import(dir, &store, false, None, None).await.unwrap();
+        assert!(skipped.is_empty(), "{skipped:?}");
+        let mut collected: Vec<_> = collection.iter().map(|(name, _)| name.as_str()).collect();
+        collected.sort();
+        assert_eq!(
+            collected,
+            [
+                "testdir/100%41.txt",
+                "testdir/\\bad%5Cfile.txt",
+                "testdir/\\caf%E9.txt",
+                "testdir/good.txt"
+            ]
+        );
 
-    #[derive(Clone)]
-    struct TransferState {
-        start_time: Instant,
-        total_size: u64,
+        let out = td.path().join("out");
+        export(
+            &store,
+            collection,
+            &out,
+            FilenamePolicy::Reject,
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        for name in names {
+            let exported = std::fs::read(out.join("testdir").join(name)).unwrap();
+            assert_eq!(exported, name.as_bytes());
+        }
     }
 
-    let transfer_states: Arc<Mutex<std::collections::HashMap<(u64, u64), TransferState>>> =
-        Arc::new(Mutex::new(std::collections::HashMap::new()));
-
-    let active_requests = Arc::new(AtomicUsize::new(0));
-    let completed_requests = Arc::new(AtomicUsize::new(0));
-    let has_emitted_started = Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let has_any_transfer = Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let last_request_time: Arc<tokio::sync::Mutex<Option<Instant>>> =
-        Arc::new(tokio::sync::Mutex::new(None));
-
-    loop {
-        tokio::select! {
-            biased;
-            item = recv.recv() => {
-                let Some(item) = item else {
-                    break;
-                };
-
-                match item {
-                    iroh_blobs::provider::events::ProviderMessage::ClientConnectedNotify(_msg) => {
-                    }
-                    iroh_blobs::provider::events::ProviderMessage::ConnectionClosed(_msg) => {
-                    }
-                    iroh_blobs::provider::events::ProviderMessage::GetRequestReceivedNotify(msg) => {
-                        let connection_id = msg.connection_id;
-                        let request_id = msg.request_id;
-
-                        active_requests.fetch_add(1, Ordering::SeqCst);
-
-                        let mut last_time = last_request_time.lock().await;
-                        *last_time = Some(Instant::now());
-
-                        let app_handle_task = app_handle.clone();
-                        let transfer_states_task = transfer_states.clone();
-                        let active_requests_task = active_requests.clone();
-                        let completed_requests_task = completed_requests.clone();
-                        let has_emitted_started_task = has_emitted_started.clone();
-                        let has_any_transfer_task = has_any_transfer.clone();
-                        let last_request_time_task = last_request_time.clone();
-                        let entry_type_task = entry_type.clone();
-
-                        let mut rx = msg.rx;
-                        tasks.push(async move {
-                            let mut transfer_started = false;
-                            let mut request_completed = false;
-
-                            while let Ok(Some(update)) = rx.recv().await {
-                                match update {
-                                    iroh_blobs::provider::events::RequestUpdate::Started(_m) => {
-                                        if !transfer_started {
-                                            let active_count = {
-                                                let mut states = transfer_states_task.lock().await;
-                                                states.insert(
-                                                    (connection_id, request_id),
-                                                    TransferState {
-                                                        start_time: Instant::now(),
-                                                        total_size: total_file_size,
-                                                    }
-                                                );
-                                                states.len()
-                                            };
-
-                                            emit_active_connection_count(&app_handle_task, active_count);
-
-                                            if !has_emitted_started_task.swap(true, Ordering::SeqCst) {
-                                                emit_event(&app_handle_task, "transfer-started");
-                                            }
-
-                                            transfer_started = true;
-                                            has_any_transfer_task.store(true, Ordering::SeqCst);
-                                        }
-                                    }
-                                    iroh_blobs::provider::events::RequestUpdate::Progress(m) => {
-                                        if !transfer_started {
-                                            let active_count = {
-                                                let mut states = transfer_states_task.lock().await;
-                                                states.insert(
-                                                    (connection_id, request_id),
-                                                    TransferState {
-                                                        start_time: Instant::now(),
-                                                        total_size: total_file_size,
-                                                    }
-                                                );
-                                                states.len()
-                                            };
-
-                                            emit_active_connection_count(&app_handle_task, active_count);
-
-                                            if !has_emitted_started_task.swap(true, Ordering::SeqCst) {
-                                                emit_event(&app_handle_task, "transfer-started");
-                                            }
-                                            transfer_started = true;
-                                            has_any_transfer_task.store(true, Ordering::SeqCst);
-                                        }
-
-                                        if let Some(state) = transfer_states_task.lock().await.get(&(connection_id, request_id)) {
-                                            let elapsed = state.start_time.elapsed().as_secs_f64();
-                                            let speed_bps = if elapsed > 0.0 {
-                                                m.end_offset as f64 / elapsed
-                                            } else {
-                                                0.0
-                                            };
-
-                                            emit_progress_event(&app_handle_task, m.end_offset.min(state.total_size), state.total_size, speed_bps);
-                                        }
-                                    }
-                                    iroh_blobs::provider::events::RequestUpdate::Completed(_m) => {
-                                        if transfer_started && !request_completed {
-                                            let active_count = {
-                                                let mut states = transfer_states_task.lock().await;
-                                                states.remove(&(connection_id, request_id));
-                                                states.len()
-                                            };
-
-                                            emit_active_connection_count(&app_handle_task, active_count);
-
-                                            request_completed = true;
-
-                                            let completed = completed_requests_task.fetch_add(1, Ordering::SeqCst) + 1;
-                                            let active = active_requests_task.load(Ordering::SeqCst);
-
-                                            // For directories, require at least 2 completed requests
-                                            // to avoid false completion from metadata transfer
-                                            let min_required = if entry_type_task == "directory" { 2 } else { 1 };
-
-                                            if completed >= active
-                                                && completed >= min_required
-                                                && has_any_transfer_task.load(Ordering::SeqCst) {
-                                                let active_before_wait = active;
-
-                                                tokio::time::sleep(Duration::from_millis(500)).await;
-
-                                                let completed_after = completed_requests_task.load(Ordering::SeqCst);
-                                                let active_after = active_requests_task.load(Ordering::SeqCst);
-
-                                                let new_requests_arrived = active_after > active_before_wait;
-
-                                                let has_active_transfers = {
-                                                    let states = transfer_states_task.lock().await;
-                                                    !states.is_empty()
-                                                };
-
-                                                let last_request_recent = {
-                                                    let last_time = last_request_time_task.lock().await;
-                                                    if let Some(time) = *last_time {
-                                                        time.elapsed() < Duration::from_millis(500)
-                                                    } else {
-                                                        false
-                                                    }
-                                                };
-
-                                                if completed_after >= active_after
-                                                    && completed_after >= min_required
-                                                    && !new_requests_arrived
-                                                    && !has_active_transfers
-                                                    && !last_request_recent {
-                                                    emit_event(&app_handle_task, "transfer-completed");
-                                                }
-                                            }
-                                        }
-                                    }
-                                    iroh_blobs::provider::events::RequestUpdate::Aborted(_m) => {
-                                        tracing::warn!("Request aborted: conn {} req {}",
-                                            connection_id, request_id);
-                                        if transfer_started && !request_completed {
-                                            let active_count = {
-                                                let mut states = transfer_states_task.lock().await;
-                                                states.remove(&(connection_id, request_id));
-                                                states.len()
-                                            };
-
-                                            emit_active_connection_count(&app_handle_task, active_count);
-
-                                            request_completed = true;
-
-                                            let completed = completed_requests_task.fetch_add(1, Ordering::SeqCst) + 1;
-                                            let active = active_requests_task.load(Ordering::SeqCst);
-
-                                            if completed >= active {
-                                                emit_event(&app_handle_task, "transfer-failed");
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+    #[tokio::test]
+    async fn import_cache_skips_unchanged_files() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("data");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("big.bin");
+        std::fs::write(&file, "version 1").unwrap();
+        // Well before the import, so the mtime can be trusted
+        let modified = std::time::SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let store_dir = td.path().join("store");
+        let store = FsStore::load(&store_dir).await.unwrap();
+        let import_cached = || async {
+            let mut cache = ImportCache::load(&store_dir);
+            let (tag, _, _, _) = import(dir.clone(), &store, false, Some(&mut cache), None)
+                .await
+                .unwrap();
+            tag.hash()
+        };
+        let first = import_cached().await;
+        assert_eq!(import_cached().await, first);
+
+        // Same size, inode and mtime: the cached hash is used without reading the file
+        std::fs::write(&file, "version 2").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(import_cached().await, first);
+
+        // Once the mtime moves, the file is hashed again
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        let (uncached, _, _, _) = import(dir.clone(), &store, false, None, None)
+            .await
+            .unwrap();
+        assert_eq!(import_cached().await, uncached.hash());
+        assert_ne!(uncached.hash(), first);
+    }
 
-                            if transfer_started && !request_completed {
-                                let completed = completed_requests_task.fetch_add(1, Ordering::SeqCst) + 1;
-                                let active = active_requests_task.load(Ordering::SeqCst);
-
-                                // For directories, require at least 2 completed requests
-                                // to avoid false completion from metadata transfer
-                                let min_required = if entry_type_task == "directory" { 2 } else { 1 };
-
-                                if completed >= active
-                                    && completed >= min_required
-                                    && has_any_transfer_task.load(Ordering::SeqCst) {
-                                    let active_before_wait = active;
-
-                                    tokio::time::sleep(Duration::from_millis(500)).await;
-
-                                    let completed_after = completed_requests_task.load(Ordering::SeqCst);
-                                    let active_after = active_requests_task.load(Ordering::SeqCst);
-
-                                    let new_requests_arrived = active_after > active_before_wait;
-
-                                    let has_active_transfers = {
-                                        let states = transfer_states_task.lock().await;
-                                        !states.is_empty()
-                                    };
-
-                                    let last_request_recent = {
-                                        let last_time = last_request_time_task.lock().await;
-                                        if let Some(time) = *last_time {
-                                            time.elapsed() < Duration::from_millis(500)
-                                        } else {
-                                            false
-                                        }
-                                    };
-
-                                    if completed_after >= active_after
-                                        && completed_after >= min_required
-                                        && !new_requests_arrived
-                                        && !has_active_transfers
-                                        && !last_request_recent {
-                                        emit_event(&app_handle_task, "transfer-completed");
-                                    }
-                                }
-                            }
-                        });
-                    }
-                    _ => {
-                    }
-                }
-            }
-            Some(_) = tasks.next(), if !tasks.is_empty() => {
-            }
-        }
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn import_reports_skipped_files() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("testdir");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("good.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink(dir.join("good.txt"), dir.join("link.txt")).unwrap();
+
+        let store = iroh_blobs::store::mem::MemStore::new();
+        let (_, _, collection, skipped) = import(dir.clone(), &store, false, None, None)
+            .await
+            .unwrap();
+        assert_eq!(collection.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].path.ends_with("link.txt"));
+        assert_eq!(skipped[0].reason, "not a regular file");
+
+        let err = import(dir, &store, true, None, None).await.unwrap_err();
+        assert_eq!(Error::from(err).code(), "path_rejected");
     }
 
-    while tasks.next().await.is_some() {}
+    #[tokio::test]
+    async fn restricted_share_only_serves_allowed_peers() {
+        use crate::core::receive::download;
+        use crate::core::types::ReceiveOptions;
+        use crate::core::verify::{verify, Expected, Manifest};
+
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("note.txt");
+        std::fs::write(&src, "for your eyes only").unwrap();
+
+        let contact = iroh::SecretKey::generate(&mut rand::rng());
+        let stranger = iroh::SecretKey::generate(&mut rand::rng());
+        let share = start_share(
+            src,
+            SendOptions {
+                allowed_peers: Some([contact.public()].into_iter().collect()),
+                ..SendOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let receive_options = |key: &iroh::SecretKey, out: &Path| ReceiveOptions {
+            output_dir: Some(out.to_path_buf()),
+            secret_key: Some(key.clone()),
+            verify: true,
+            save_manifest: true,
+            ..ReceiveOptions::local()
+        };
+
+        let out = td.path().join("stranger");
+        let result = download(share.ticket.clone(), receive_options(&stranger, &out), None).await;
+        assert!(result.is_err());
+        assert!(!out.join("note.txt").exists());
+
+        let out = td.path().join("contact");
+        let result = download(share.ticket.clone(), receive_options(&contact, &out), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("note.txt")).unwrap(),
+            "for your eyes only"
+        );
+        // The saved manifest checks the file later on
+        let manifest = Manifest::load(&result.manifest.unwrap()).unwrap();
+        let report = verify(out.join("note.txt"), Expected::Manifest(manifest))
+            .await
+            .unwrap();
+        assert_eq!(report.verified, vec!["note.txt".to_string()]);
+
+        share.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+    }
 
-    if has_any_transfer.load(Ordering::SeqCst) {
-        let completed = completed_requests.load(Ordering::SeqCst);
-        let active = active_requests.load(Ordering::SeqCst);
+    #[tokio::test]
+    async fn passphrase_share_requires_the_passphrase() {
+        use crate::core::passphrase::unlock_ticket;
+        use crate::core::receive::download;
+        use crate::core::types::ReceiveOptions;
+        use crate::LockedTicket;
+        use std::str::FromStr;
+
+        let td = tempfile::TempDir::new().unwrap();
+        let src = td.path().join("secret.txt");
+        std::fs::write(&src, "hunter2").unwrap();
+
+        let share = start_share(
+            src,
+            SendOptions {
+                passphrase: Some("open sesame".to_string()),
+                ..SendOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let receive_options = |passphrase: Option<&str>, out: &Path| ReceiveOptions {
+            output_dir: Some(out.to_path_buf()),
+            passphrase: passphrase.map(str::to_string),
+            ..ReceiveOptions::local()
+        };
+        let out = td.path().join("out");
+
+        let err = download(share.ticket.clone(), receive_options(None, &out), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "unauthorized");
+        assert!(err.to_string().contains("passphrase"), "{err}");
+        let err = download(
+            share.ticket.clone(),
+            receive_options(Some("guess"), &out),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), "unauthorized");
+        assert!(err.to_string().contains("wrong passphrase"), "{err}");
+
+        // A peer that decrypted the ticket but skips the challenge is not served
+        let locked = LockedTicket::from_str(&share.ticket).unwrap();
+        let (inner, _) = unlock_ticket(&locked, "open sesame").unwrap();
+        assert!(
+            download(inner.to_string(), receive_options(None, &out), None)
+                .await
+                .is_err()
+        );
+        assert!(!out.join("secret.txt").exists());
 
-        // For directories, require at least 2 completed requests
-        // to avoid false completion from metadata transfer
-        let min_required = if entry_type == "directory" { 2 } else { 1 };
+        download(
+            share.ticket.clone(),
+            receive_options(Some("open sesame"), &out),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("secret.txt")).unwrap(),
+            "hunter2"
+        );
 
-        if completed >= active && completed >= min_required && completed > 0 {
-            emit_event(&app_handle, "transfer-completed");
-        }
+        share.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
     }
-
-    Ok(())
 }
@@ -0,0 +1,26 @@
+use iroh_blobs::store::{
+    fs::{options::Options, FsStore},
+    GcConfig,
+};
+use std::path::Path;
+use std::time::Duration;
+
+/// How often a collected store drops the blobs that nothing keeps.
+#[cfg(not(test))]
+const GC_INTERVAL: Duration = Duration::from_secs(60);
+#[cfg(test)]
+const GC_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Opens the blob store in `dir` with garbage collection, for stores that outlive a
+/// single transfer.
+///
+/// Only blobs reachable from a tag or a temp tag survive. Hold a temp tag for
+/// everything in use and a named tag for everything worth keeping.
+pub(crate) async fn load_collected(dir: &Path) -> anyhow::Result<FsStore> {
+    let mut options = Options::new(dir);
+    options.gc = Some(GcConfig {
+        interval: GC_INTERVAL,
+        add_protected: None,
+    });
+    FsStore::load_with_opts(dir.join("blobs.db"), options).await
+}
@@ -0,0 +1,404 @@
+use crate::core::error::Error;
+use crate::core::offer::fetch_blob;
+use crate::core::preflight::Preflight;
+use crate::core::progress::ProgressTracker;
+use crate::core::send::ConnectionGate;
+use crate::core::types::AppHandle;
+use iroh::{
+    endpoint::{Connection, RecvStream, SendStream, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointAddr,
+};
+use iroh_blobs::{
+    api::{Store, TempTag},
+    hashseq::HashSeq,
+    Hash, HashAndFormat,
+};
+use iroh_tickets::{ParseError, Ticket};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashSet, str::FromStr};
+use tokio::sync::watch;
+
+/// ALPN on which a streaming share announces its files while it is still hashing them.
+///
+/// The share writes a `StreamMessage` for every file as soon as it is hashed, and the
+/// collection hash once all of them are. Receivers fetch the announced files over the
+/// blobs ALPN right away and the collection itself at the end, so everything they get
+/// is still verified against the final hash.
+pub const STREAM_ALPN: &[u8] = b"/sendme/stream/0";
+
+/// Close code for receivers a restricted share does not serve.
+const STREAM_REJECTED: VarInt = VarInt::from_u32(2);
+
+/// Messages are small; anything larger is a broken or hostile peer.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum StreamMessage {
+    /// What the share will contain, sent before the first file.
+    Started {
+        files: u64,
+        size: u64,
+    },
+    /// A hashed file that can be fetched now.
+    File {
+        name: String,
+        hash: Hash,
+        size: u64,
+    },
+    /// Hashing is done and the share is the collection `hash`.
+    Finished {
+        hash: Hash,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+/// A ticket for a share that hands out its ticket before it has finished hashing.
+///
+/// It carries no hash, since the collection hash is only known once every file is hashed.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+#[display("{}", Ticket::serialize(self))]
+pub struct StreamingTicket {
+    addr: EndpointAddr,
+}
+
+impl StreamingTicket {
+    pub fn new(addr: EndpointAddr) -> Self {
+        Self { addr }
+    }
+
+    pub fn addr(&self) -> &EndpointAddr {
+        &self.addr
+    }
+}
+
+impl Ticket for StreamingTicket {
+    const KIND: &'static str = "stream";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(&self.addr).expect("postcard serialization failed")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let addr = postcard::from_bytes(bytes)?;
+        Ok(Self { addr })
+    }
+}
+
+impl FromStr for StreamingTicket {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ticket::deserialize(s)
+    }
+}
+
+/// Whether `ticket` is for a share that may still be hashing.
+pub fn is_streaming_ticket(ticket: &str) -> bool {
+    StreamingTicket::from_str(ticket.trim()).is_ok()
+}
+
+/// How far the import of a streaming share has got.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ImportState {
+    totals: Option<(u64, u64)>,
+    files: Vec<(String, Hash, u64)>,
+    hashed_bytes: u64,
+    result: Option<Result<Hash, String>>,
+}
+
+/// Publishes each file of an import as soon as it is hashed.
+#[derive(Clone)]
+pub(crate) struct ImportProgress {
+    state: watch::Sender<ImportState>,
+    app_handle: AppHandle,
+}
+
+impl std::fmt::Debug for ImportProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImportProgress")
+            .field("state", &*self.state.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ImportProgress {
+    pub(crate) fn new(app_handle: AppHandle) -> Self {
+        Self {
+            state: watch::Sender::new(ImportState::default()),
+            app_handle,
+        }
+    }
+
+    pub(crate) fn started(&self, files: u64, size: u64) {
+        self.state
+            .send_modify(|state| state.totals = Some((files, size)));
+        self.emit();
+    }
+
+    pub(crate) fn hashed(&self, name: &str, hash: Hash, size: u64) {
+        self.state.send_modify(|state| {
+            state.files.push((name.to_string(), hash, size));
+            state.hashed_bytes += size;
+        });
+        self.emit();
+    }
+
+    pub(crate) fn finished(&self, result: Result<Hash, String>) {
+        self.state.send_modify(|state| state.result = Some(result));
+    }
+
+    /// Emits `share-hashing-progress` as `files_done:files_total:bytes_done:bytes_total`.
+    fn emit(&self) {
+        let Some(handle) = &self.app_handle else {
+            return;
+        };
+        let payload = {
+            let state = self.state.borrow();
+            let (files, size) = state.totals.unwrap_or_default();
+            format!(
+                "{}:{}:{}:{}",
+                state.files.len(),
+                files,
+                state.hashed_bytes,
+                size
+            )
+        };
+        if let Err(e) = handle.emit_event_with_payload("share-hashing-progress", &payload) {
+            tracing::warn!("Failed to emit hashing progress: {}", e);
+        }
+    }
+}
+
+/// Serves [`STREAM_ALPN`] for a streaming share.
+#[derive(Debug, Clone)]
+pub(crate) struct StreamProtocol {
+    pub(crate) progress: ImportProgress,
+    pub(crate) gate: ConnectionGate,
+}
+
+impl StreamProtocol {
+    async fn announce(&self, connection: &Connection) -> anyhow::Result<()> {
+        let mut send = connection.open_uni().await?;
+        let mut state = self.progress.state.subscribe();
+        let mut started = false;
+        let mut sent = 0;
+        loop {
+            let (totals, files, result) = {
+                let current = state.borrow_and_update();
+                (
+                    current.totals,
+                    current.files[sent..].to_vec(),
+                    current.result.clone(),
+                )
+            };
+            if let (false, Some((files, size))) = (started, totals) {
+                write_message(&mut send, &StreamMessage::Started { files, size }).await?;
+                started = true;
+            }
+            sent += files.len();
+            for (name, hash, size) in files {
+                write_message(&mut send, &StreamMessage::File { name, hash, size }).await?;
+            }
+            if let Some(result) = result {
+                let message = match result {
+                    Ok(hash) => StreamMessage::Finished { hash },
+                    Err(reason) => StreamMessage::Failed { reason },
+                };
+                write_message(&mut send, &message).await?;
+                send.finish()?;
+                send.stopped().await?;
+                return Ok(());
+            }
+            state.changed().await?;
+        }
+    }
+}
+
+impl ProtocolHandler for StreamProtocol {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        if !self.gate.admit(Some(connection.remote_id())).await {
+            connection.close(STREAM_REJECTED, b"not allowed");
+            return Ok(());
+        }
+        if let Err(e) = self.announce(&connection).await {
+            tracing::warn!(
+                "announcing files to {} failed: {}",
+                connection.remote_id(),
+                e
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Writes one length-prefixed postcard message.
+pub(crate) async fn write_message<T: Serialize>(
+    send: &mut SendStream,
+    message: &T,
+) -> anyhow::Result<()> {
+    let bytes = postcard::to_stdvec(message)?;
+    send.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
+    send.write_all(&bytes).await?;
+    Ok(())
+}
+
+/// Reads one message written by [`write_message`].
+pub(crate) async fn read_message<T: DeserializeOwned>(recv: &mut RecvStream) -> anyhow::Result<T> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await?;
+    let len = u32::from_le_bytes(len) as usize;
+    anyhow::ensure!(len <= MAX_MESSAGE_LEN, "stream message too large");
+    let mut bytes = vec![0u8; len];
+    recv.read_exact(&mut bytes).await?;
+    Ok(postcard::from_bytes(&bytes)?)
+}
+
+/// Follows a streaming share until it has finished hashing, fetching each file into
+/// `db` as soon as it is announced if `fetch` is set. Returns the hash of the collection,
+/// and temp tags that keep the files fetched so far until the collection is fetched.
+///
+/// While fetching, the files announced so far are checked against `preflight` before
+/// each one is fetched, and a collection holding files that were never announced is
+/// refused.
+pub(crate) async fn follow_stream(
+    endpoint: &Endpoint,
+    addr: EndpointAddr,
+    db: &Store,
+    preflight: &Preflight,
+    progress: &mut ProgressTracker,
+    fetch: bool,
+) -> anyhow::Result<(Hash, Vec<TempTag>)> {
+    let announcements = endpoint
+        .connect(addr.clone(), STREAM_ALPN)
+        .await
+        .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+    let blobs = endpoint
+        .connect(addr, iroh_blobs::protocol::ALPN)
+        .await
+        .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+    let mut recv = announcements
+        .accept_uni()
+        .await
+        .map_err(|e| Error::PeerAborted(e.to_string()))?;
+
+    let mut fetched = 0u64;
+    let mut announced = 0u64;
+    let mut announced_hashes = HashSet::new();
+    let mut fetched_tags = Vec::new();
+    let mut file = 0;
+    loop {
+        let message: StreamMessage = read_message(&mut recv)
+            .await
+            .map_err(|e| Error::PeerAborted(e.to_string()))?;
+        match message {
+            StreamMessage::Started { .. } | StreamMessage::File { .. } if !fetch => {}
+            StreamMessage::Started { files, size } => {
+                // Nothing is stored yet, so everything is still missing
+                preflight.check(files, size, size)?;
+                progress.start(size, files);
+            }
+            StreamMessage::File { name, hash, size } => {
+                tracing::debug!("fetching {} while the sender is still hashing", name);
+                file += 1;
+                // The announced totals may have been a lie, the running ones are not
+                announced = announced.saturating_add(size);
+                announced_hashes.insert(hash);
+                let stored = db.has(hash).await?;
+                preflight.check(file, announced, if stored { 0 } else { size })?;
+                progress.set_file(file);
+                fetched_tags.push(db.tags().temp_tag(HashAndFormat::raw(hash)).await?);
+                if !stored {
+                    db.remote()
+                        .fetch(blobs.clone(), HashAndFormat::raw(hash))
+                        .complete()
+                        .await
+                        .map_err(|e| Error::PeerAborted(e.to_string()))?;
+                }
+                fetched = fetched.saturating_add(size);
+                progress.update(fetched);
+            }
+            StreamMessage::Finished { hash } => {
+                if fetch {
+                    if !db.has(hash).await? {
+                        fetch_blob(db, &blobs, hash).await?;
+                    }
+                    let hash_seq = HashSeq::try_from(db.get_bytes(hash).await?)?;
+                    // The first child is the metadata, the rest are the files
+                    if hash_seq
+                        .into_iter()
+                        .skip(1)
+                        .any(|child| !announced_hashes.contains(&child))
+                    {
+                        return Err(Error::PeerAborted(
+                            "the collection holds files that were never announced".to_string(),
+                        )
+                        .into());
+                    }
+                }
+                progress.finish();
+                return Ok((hash, fetched_tags));
+            }
+            StreamMessage::Failed { reason } => {
+                return Err(
+                    Error::PeerAborted(format!("the sender failed to hash: {reason}")).into(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::receive::download;
+    use crate::core::send::start_share;
+    use crate::core::types::{ReceiveOptions, SendOptions};
+
+    #[tokio::test]
+    async fn streaming_share_hands_out_the_ticket_before_hashing() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("album");
+        std::fs::create_dir(&dir).unwrap();
+        for i in 0..4 {
+            std::fs::write(dir.join(format!("track{i}.flac")), vec![i as u8; 50_000]).unwrap();
+        }
+
+        let share = start_share(
+            dir,
+            SendOptions {
+                streaming: true,
+                ..SendOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(is_streaming_ticket(&share.ticket));
+        assert!(share.hash.is_empty());
+        assert_eq!(share.size, 200_000);
+
+        let out = td.path().join("out");
+        let result = download(
+            share.ticket.clone(),
+            ReceiveOptions {
+                output_dir: Some(out.clone()),
+                ..ReceiveOptions::local()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.files.len(), 4);
+        assert_eq!(result.size, 200_000);
+        assert_eq!(result.hash.len(), 64);
+        assert_eq!(
+            std::fs::read(out.join("album/track3.flac")).unwrap(),
+            vec![3u8; 50_000]
+        );
+        share.router.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&share.blobs_data_dir).ok();
+    }
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 // Import the EventEmitter trait - we'll define it here or import it
@@ -10,52 +11,495 @@ pub trait EventEmitter: Send + Sync {
 // Type alias for the app handle - we use Arc<dyn EventEmitter> to allow cloning and avoid direct tauri dependency in core
 pub type AppHandle = Option<Arc<dyn EventEmitter>>;
 
+#[derive(Debug)]
 pub struct SendResult {
     pub ticket: String,
     pub hash: String,
     pub size: u64,
     pub entry_type: String, // "file" or "directory"
+    /// Files under the shared path that were left out, e.g. because they could not be read.
+    pub skipped: Vec<SkippedFile>,
 
     // CRITICAL: These fields must be kept alive for the duration of the share
     pub router: iroh::protocol::Router, // Keeps the server running and protocols active
-    pub temp_tag: iroh_blobs::api::TempTag, // Prevents data from being garbage collected
-    pub blobs_data_dir: PathBuf,        // Path for cleanup when share stops
+    pub temp_tag: Option<iroh_blobs::api::TempTag>, // Prevents data from being garbage collected; `None` while a streaming share hashes
+    pub blobs_data_dir: PathBuf,                    // Path for cleanup when share stops
+    pub persistent_store: bool, // The store is `SendOptions::store_dir`; keep it when the share stops
     pub _progress_handle: n0_future::task::AbortOnDropHandle<anyhow::Result<()>>, // Keeps event channel open
+    pub _import_handle:
+        Option<n0_future::task::AbortOnDropHandle<Option<iroh_blobs::api::TempTag>>>, // Hashes a streaming share; its output holds the temp tag
     pub _store: iroh_blobs::store::fs::FsStore, // Keeps the blob storage alive
+    pub _proxy_handle: Option<n0_future::task::AbortOnDropHandle<()>>, // Keeps a SOCKS5 bridge running
+    /// The receivers currently connected, see [`crate::Receivers`].
+    pub receivers: crate::core::receivers::Receivers,
+    /// The endpoints waiting to be let in, if [`SendOptions::approval`] was set.
+    pub approvals: Option<crate::core::approval::Approvals>,
+}
+
+/// A running inbox. Dropping it stops accepting pushes.
+pub struct InboxResult {
+    pub ticket: String,
+    pub output_dir: PathBuf,
+
+    // These fields must be kept alive for as long as the inbox is open
+    pub router: iroh::protocol::Router, // Accepts push connections
+    pub blobs_data_dir: PathBuf,        // Path for cleanup when the inbox closes
+    pub _store: iroh_blobs::store::fs::FsStore, // Holds pushed blobs until they are exported
+    pub _proxy_handle: Option<n0_future::task::AbortOnDropHandle<()>>, // Keeps a SOCKS5 bridge running
+}
+
+/// A running mirror. Dropping it stops watching the folder and serving subscribers.
+#[derive(Debug)]
+pub struct MirrorResult {
+    pub ticket: String,
+
+    // These fields must be kept alive for as long as the mirror runs
+    pub router: iroh::protocol::Router, // Serves the versions and their blobs
+    pub blobs_data_dir: PathBuf,        // Path for cleanup when the mirror stops
+    pub persistent_store: bool, // The store is `SendOptions::store_dir`; keep it when the mirror stops
+    pub _watch_handle: n0_future::task::AbortOnDropHandle<()>, // Publishes new versions of the folder
+    pub _progress_handle: n0_future::task::AbortOnDropHandle<anyhow::Result<()>>, // Keeps event channel open
+    pub _store: iroh_blobs::store::fs::FsStore, // Holds the published versions
+    pub _proxy_handle: Option<n0_future::task::AbortOnDropHandle<()>>, // Keeps a SOCKS5 bridge running
+}
+
+/// A subscription to a mirror. Dropping it stops applying new versions.
+#[derive(Debug)]
+pub struct MirrorSubscription {
+    /// The directory kept in step with the mirror.
+    pub target: PathBuf,
+    /// The plan of the latest version, once one arrived.
+    pub plans: tokio::sync::watch::Receiver<Option<crate::core::mirror::MirrorPlan>>,
+
+    // These fields must be kept alive for as long as the subscription runs
+    pub blobs_data_dir: PathBuf, // Path for cleanup when the subscription ends
+    pub persistent_store: bool, // The store is `ReceiveOptions::store_dir`; keep it when the subscription ends
+    pub _task: n0_future::task::AbortOnDropHandle<()>, // Follows the mirror
+    pub _store: iroh_blobs::store::fs::FsStore, // Holds the fetched versions
+    pub _proxy_handle: Option<n0_future::task::AbortOnDropHandle<()>>, // Keeps a SOCKS5 bridge running
+}
+
+/// One line of an inbox transfer log.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct InboxTransferRecord {
+    /// Seconds since the unix epoch.
+    pub received_at: u64,
+    pub peer: String,
+    pub hash: String,
+    pub files: Vec<String>,
+    pub size: u64,
+    pub output_dir: PathBuf,
+}
+
+/// A file that was left out of a share or push, and why.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct PushResult {
+    pub hash: String,
+    pub size: u64,
+    pub skipped: Vec<SkippedFile>,
 }
 
 #[derive(Debug)]
 pub struct ReceiveResult {
     pub message: String,
     pub file_path: PathBuf,
+    /// Hex hash of the offered collection, for checking the received files later with
+    /// `verify`. If only some of its files were accepted, it is still the hash of the whole
+    /// collection, so the files that were left out show up as missing.
+    pub hash: String,
+    pub files: Vec<String>,
+    /// Payload bytes downloaded; zero if everything was already stored locally.
+    pub size: u64,
+    /// The endpoint the collection was downloaded from.
+    pub peer: iroh::EndpointId,
+    /// What each provider sent; empty unless [`ReceiveOptions::providers`] was set.
+    pub providers: Vec<ProviderStats>,
+    /// The saved manifest, if [`ReceiveOptions::save_manifest`] was set.
+    pub manifest: Option<PathBuf>,
+    /// The share of the received collection, if [`ReceiveOptions::reseed`] was set and it
+    /// could be started. Never set when only some of the offered files were accepted.
+    pub reseed: Option<SendResult>,
 }
 
-#[derive(Debug, Default)]
+/// How much one provider of a multi-source download sent.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProviderStats {
+    pub endpoint_id: iroh::EndpointId,
+    /// Payload bytes received from this provider.
+    pub bytes: u64,
+    /// Average bytes per second this provider sent over the whole download.
+    pub speed_bps: f64,
+    /// Why the provider was dropped, if it was.
+    pub error: Option<String>,
+}
+
+/// Where a receiver connected to a share stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverState {
+    /// Connected, but nothing was requested yet.
+    Connected,
+    Transferring,
+    /// Has the whole collection.
+    Completed,
+    /// A request ended before everything it asked for was sent.
+    Failed,
+    /// The connection closed. Sent once, then the receiver is dropped from the list.
+    Disconnected,
+}
+
+/// One connection to a share, as reported in `receiver-progress` events.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReceiverProgress {
+    pub connection_id: u64,
+    /// The receiver's endpoint, if the provider was told about it.
+    pub endpoint_id: Option<iroh::EndpointId>,
+    /// Bytes sent on this connection.
+    pub bytes: u64,
+    /// Bytes per second over the last few seconds.
+    pub speed_bps: f64,
+    pub state: ReceiverState,
+}
+
+/// How a share asks before serving the endpoints that connect.
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalOptions {
+    /// How long an endpoint waits for an answer before it is turned away. Defaults to 60 s.
+    pub timeout: Option<std::time::Duration>,
+    /// Names to show for known endpoints, e.g. the nicknames of contacts.
+    pub names: std::collections::HashMap<iroh::EndpointId, String>,
+}
+
+#[derive(Default)]
 pub struct SendOptions {
     pub relay_mode: RelayModeOption,
+    /// Identity to share or push as; a fresh one is generated if unset.
+    pub secret_key: Option<iroh::SecretKey>,
+    /// Only serve these endpoints, e.g. a single contact. `None` serves anyone holding the ticket.
+    pub allowed_peers: Option<HashSet<iroh::EndpointId>>,
+    /// Encrypt the ticket with this passphrase and only serve receivers that prove they know it.
+    pub passphrase: Option<String>,
+    /// Transfer history file that each receiver's download is appended to.
+    pub history: Option<PathBuf>,
+    /// Fail instead of leaving out files that cannot be shared.
+    pub strict: bool,
+    /// Keep the blob store in this directory across shares, so that files which did not
+    /// change since an earlier share are not hashed again. Only one share can use it at a time;
+    /// pushes always use a temporary store.
+    pub store_dir: Option<PathBuf>,
+    /// Hand out the ticket before hashing finishes, see [`crate::StreamingTicket`].
+    /// Receivers fetch files as they are hashed. Ignored for passphrase shares.
+    pub streaming: bool,
+    /// How often `transfer-progress` is emitted per request. Defaults to 250 ms.
+    pub progress_interval: Option<std::time::Duration>,
+    /// Ask before serving each endpoint that connects, see [`crate::Approvals`].
+    pub approval: Option<ApprovalOptions>,
     pub ticket_type: AddrInfoOptions,
     pub magic_ipv4_addr: Option<std::net::SocketAddrV4>,
     pub magic_ipv6_addr: Option<std::net::SocketAddrV6>,
+    pub discovery: DiscoveryOption,
+    pub proxy: ProxyOption,
 }
 
-#[derive(Debug, Default)]
+/// Leaves the passphrase out, so options can be logged.
+impl std::fmt::Debug for SendOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            relay_mode,
+            secret_key,
+            allowed_peers,
+            passphrase,
+            history,
+            strict,
+            store_dir,
+            streaming,
+            progress_interval,
+            approval,
+            ticket_type,
+            magic_ipv4_addr,
+            magic_ipv6_addr,
+            discovery,
+            proxy,
+        } = self;
+        f.debug_struct("SendOptions")
+            .field("relay_mode", relay_mode)
+            .field("secret_key", secret_key)
+            .field("allowed_peers", allowed_peers)
+            .field("passphrase", &passphrase.as_ref().map(|_| "<redacted>"))
+            .field("history", history)
+            .field("strict", strict)
+            .field("store_dir", store_dir)
+            .field("streaming", streaming)
+            .field("progress_interval", progress_interval)
+            .field("approval", approval)
+            .field("ticket_type", ticket_type)
+            .field("magic_ipv4_addr", magic_ipv4_addr)
+            .field("magic_ipv6_addr", magic_ipv6_addr)
+            .field("discovery", discovery)
+            .field("proxy", proxy)
+            .finish()
+    }
+}
+
+#[derive(Default)]
 pub struct ReceiveOptions {
     pub output_dir: Option<PathBuf>,
+    /// Identity to download as, so shares restricted to this device let it in.
+    pub secret_key: Option<iroh::SecretKey>,
+    /// Only present `secret_key` to these senders; any other sender sees a key made for
+    /// this download, so it cannot link the download to the device. `None` presents it
+    /// to every sender.
+    pub identify_to: Option<HashSet<iroh::EndpointId>>,
+    /// Passphrase for a locked ticket.
+    pub passphrase: Option<String>,
+    /// More providers of the same collection, as tickets or endpoint ids. The download
+    /// is split between them and the ticket's own provider, and carries on without any
+    /// that drop out.
+    pub providers: Vec<String>,
+    /// Re-hash the exported files and fail if any of them does not match.
+    pub verify: bool,
+    /// Save the collection's [`crate::Manifest`] in the output directory as
+    /// `.sendme-<hash>.manifest.json`, next to the received files, so they can be checked
+    /// later with [`crate::verify`].
+    pub save_manifest: bool,
+    /// Transfer history file that the download is appended to, whether it succeeds or not.
+    pub history: Option<PathBuf>,
+    /// Refuse collections whose files add up to more than this many bytes.
+    pub max_total_bytes: Option<u64>,
+    /// Refuse collections with more than this many files.
+    pub max_files: Option<u64>,
+    /// How to handle names that are not valid on every platform.
+    pub filename_policy: crate::core::filename::FilenamePolicy,
+    /// Record the collection hash and the sender in extended attributes of each received
+    /// file, see [`crate::provenance`]. Linux only.
+    pub tag_origin: bool,
+    /// Keep received blobs in this directory across downloads, so that a later collection
+    /// only fetches the files that are not in it yet. Only one download can use it at a time.
+    pub store_dir: Option<PathBuf>,
+    /// Update files that already exist in the output directory instead of refusing to
+    /// overwrite them. Files that already have the right content are left alone.
+    pub update: bool,
+    /// Keep the received collection and share it with these options once it is exported,
+    /// serving the downloaded blobs as they are. `store_dir` and `streaming` are ignored,
+    /// and so is the whole option if only some of the offered files are accepted.
+    pub reseed: Option<SendOptions>,
+    /// How often `receive-progress` is emitted. Defaults to 250 ms.
+    pub progress_interval: Option<std::time::Duration>,
+    /// Offer the collection's file list with a `receive-offer` event and wait for the
+    /// answer on this prompt before fetching any file. Only used by [`crate::download`];
+    /// a subset of the files is fetched from the ticket's provider alone.
+    pub confirm: Option<crate::core::offer::OfferPrompt>,
+    pub relay_mode: RelayModeOption,
+    pub magic_ipv4_addr: Option<std::net::SocketAddrV4>,
+    pub magic_ipv6_addr: Option<std::net::SocketAddrV6>,
+    pub discovery: DiscoveryOption,
+    pub proxy: ProxyOption,
+}
+
+/// Leaves the passphrase out, so options can be logged.
+impl std::fmt::Debug for ReceiveOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            output_dir,
+            secret_key,
+            identify_to,
+            passphrase,
+            providers,
+            verify,
+            save_manifest,
+            history,
+            max_total_bytes,
+            max_files,
+            filename_policy,
+            tag_origin,
+            store_dir,
+            update,
+            reseed,
+            progress_interval,
+            confirm,
+            relay_mode,
+            magic_ipv4_addr,
+            magic_ipv6_addr,
+            discovery,
+            proxy,
+        } = self;
+        f.debug_struct("ReceiveOptions")
+            .field("output_dir", output_dir)
+            .field("secret_key", secret_key)
+            .field("identify_to", identify_to)
+            .field("passphrase", &passphrase.as_ref().map(|_| "<redacted>"))
+            .field("providers", providers)
+            .field("verify", verify)
+            .field("save_manifest", save_manifest)
+            .field("history", history)
+            .field("max_total_bytes", max_total_bytes)
+            .field("max_files", max_files)
+            .field("filename_policy", filename_policy)
+            .field("tag_origin", tag_origin)
+            .field("store_dir", store_dir)
+            .field("update", update)
+            .field("reseed", reseed)
+            .field("progress_interval", progress_interval)
+            .field("confirm", confirm)
+            .field("relay_mode", relay_mode)
+            .field("magic_ipv4_addr", magic_ipv4_addr)
+            .field("magic_ipv6_addr", magic_ipv6_addr)
+            .field("discovery", discovery)
+            .field("proxy", proxy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InboxOptions {
+    pub output_dir: Option<PathBuf>,
+    /// Identity of the inbox. Set this to keep the same endpoint id across restarts.
+    pub secret_key: Option<iroh::SecretKey>,
+    /// Only accept pushes from these endpoints. `None` accepts anyone holding the ticket.
+    pub allowed_peers: Option<HashSet<iroh::EndpointId>>,
+    /// Contact book whose devices are accepted on top of `allowed_peers`. It is read for
+    /// every push, so devices paired while the inbox runs are let in straight away.
+    pub contacts: Option<PathBuf>,
+    /// File that each accepted transfer is appended to, one JSON record per line.
+    pub transfer_log: Option<PathBuf>,
+    /// Transfer history file that each push from a trusted device is appended to, whether
+    /// it succeeds or not.
+    pub history: Option<PathBuf>,
+    /// Tag each pushed file with its origin, as [`ReceiveOptions::tag_origin`] does.
+    pub tag_origin: bool,
+    /// Refuse pushes whose files add up to more than this many bytes.
+    pub max_total_bytes: Option<u64>,
+    /// Refuse pushes with more than this many files.
+    pub max_files: Option<u64>,
     pub relay_mode: RelayModeOption,
+    pub ticket_type: AddrInfoOptions,
     pub magic_ipv4_addr: Option<std::net::SocketAddrV4>,
     pub magic_ipv6_addr: Option<std::net::SocketAddrV6>,
+    pub discovery: DiscoveryOption,
+    pub proxy: ProxyOption,
+}
+
+/// How a mirror publishes and a subscriber applies versions, on top of
+/// [`SendOptions`] and [`ReceiveOptions`].
+#[derive(Debug, Clone, Default)]
+pub struct MirrorOptions {
+    /// Subscriber only: work out and report what each version would change without
+    /// touching the target directory.
+    pub dry_run: bool,
+    /// Mirror only: how often to check the folder for changes. Defaults to two seconds.
+    pub poll_interval: Option<std::time::Duration>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug, Default)]
+pub struct PairingOptions {
+    /// Name this device introduces itself with; the other side stores it as the nickname.
+    pub name: String,
+    /// Identity to pair as. This should be the device's persistent key.
+    pub secret_key: Option<iroh::SecretKey>,
+    pub relay_mode: RelayModeOption,
+    pub ticket_type: AddrInfoOptions,
+    pub magic_ipv4_addr: Option<std::net::SocketAddrV4>,
+    pub magic_ipv6_addr: Option<std::net::SocketAddrV6>,
+    pub discovery: DiscoveryOption,
+    pub proxy: ProxyOption,
+}
+
+#[derive(Clone, Debug, Default)]
 pub enum RelayModeOption {
     Disabled,
+    #[default]
     Default,
     Custom(iroh::RelayUrl),
 }
 
-impl Default for RelayModeOption {
-    fn default() -> Self {
-        Self::Default
+#[cfg(test)]
+impl SendOptions {
+    /// A share that tests reach over loopback only, without relays, discovery or a proxy.
+    pub(crate) fn local() -> Self {
+        Self {
+            relay_mode: RelayModeOption::Disabled,
+            ticket_type: AddrInfoOptions::Addresses,
+            discovery: DiscoveryOption::Disabled,
+            proxy: ProxyOption::Disabled,
+            ..Default::default()
+        }
+    }
+}
+
+/// Records the events a transfer emits, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct Events(std::sync::Mutex<Vec<(String, String)>>);
+
+#[cfg(test)]
+impl EventEmitter for Events {
+    fn emit_event(&self, event_name: &str) -> Result<(), String> {
+        self.emit_event_with_payload(event_name, "")
+    }
+
+    fn emit_event_with_payload(&self, event_name: &str, payload: &str) -> Result<(), String> {
+        let mut events = self.0.lock().unwrap();
+        events.push((event_name.to_string(), payload.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Events {
+    /// The payloads of the `event_name` events, oldest first.
+    pub(crate) fn named(&self, event_name: &str) -> Vec<String> {
+        let events = self.0.lock().unwrap();
+        let named = events.iter().filter(|(name, _)| name == event_name);
+        named.map(|(_, payload)| payload.clone()).collect()
+    }
+}
+
+impl ReceiveOptions {
+    /// A download for tests, from shares made with [`SendOptions::local`].
+    #[cfg(test)]
+    pub(crate) fn local() -> Self {
+        Self {
+            relay_mode: RelayModeOption::Disabled,
+            discovery: DiscoveryOption::Disabled,
+            proxy: ProxyOption::Disabled,
+            ..Default::default()
+        }
+    }
+
+    /// The key to connect to `sender` with, see [`ReceiveOptions::identify_to`].
+    pub(crate) fn secret_key_for(
+        &self,
+        sender: &iroh::EndpointId,
+    ) -> anyhow::Result<iroh::SecretKey> {
+        match &self.secret_key {
+            Some(secret_key)
+                if self
+                    .identify_to
+                    .as_ref()
+                    .map_or(true, |peers| peers.contains(sender)) =>
+            {
+                Ok(secret_key.clone())
+            }
+            _ => get_or_create_secret(),
+        }
+    }
+}
+
+impl RelayModeOption {
+    /// Reads a self-hosted relay from `SENDME_RELAY_URL`, falling back to the default relays.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("SENDME_RELAY_URL") {
+            Ok(url) => Ok(Self::Custom(
+                url.parse().context("invalid SENDME_RELAY_URL")?,
+            )),
+            Err(_) => Ok(Self::Default),
+        }
     }
 }
 
@@ -69,6 +513,98 @@ impl From<RelayModeOption> for iroh::RelayMode {
     }
 }
 
+/// How relay connections reach the internet.
+///
+/// Supported proxy URLs are `http://`, `https://`, `socks5://` and `socks5h://`,
+/// optionally with `user:password@` credentials. With `socks5://` the relay name is
+/// resolved locally, with `socks5h://` by the proxy.
+#[derive(Clone, Debug, Default)]
+pub enum ProxyOption {
+    /// Always connect directly.
+    Disabled,
+    /// Use `HTTPS_PROXY`, `HTTP_PROXY` or `ALL_PROXY` if set, unless `NO_PROXY` covers the relay.
+    #[default]
+    Environment,
+    Custom(url::Url),
+}
+
+/// Where endpoint ids are published to and resolved from.
+///
+/// `Default` uses the public n0 infrastructure. `Custom` points both sides at
+/// self-hosted n0-compatible services (an iroh-dns-server, or anything speaking
+/// the same pkarr relay and DNS protocols), so no third-party hosts are contacted.
+#[derive(Clone, Debug, Default)]
+pub enum DiscoveryOption {
+    /// No publishing or resolving. Only tickets carrying addresses will work.
+    Disabled,
+    #[default]
+    Default,
+    Custom {
+        /// Pkarr relay the sender publishes its signed address records to.
+        pkarr_relay: url::Url,
+        /// Origin domain under which `_iroh.<endpoint-id>.<origin>` TXT records are looked up.
+        dns_origin: String,
+        /// Nameserver to query instead of the system resolver.
+        dns_server: Option<std::net::SocketAddr>,
+    },
+}
+
+impl DiscoveryOption {
+    /// Reads a self-hosted configuration from `SENDME_PKARR_RELAY`, `SENDME_DNS_ORIGIN`
+    /// and (optionally) `SENDME_DNS_SERVER`, falling back to the default services.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let pkarr_relay = std::env::var("SENDME_PKARR_RELAY").ok();
+        let dns_origin = std::env::var("SENDME_DNS_ORIGIN").ok();
+        let (pkarr_relay, dns_origin) = match (pkarr_relay, dns_origin) {
+            (None, None) => return Ok(Self::Default),
+            (Some(pkarr_relay), Some(dns_origin)) => (pkarr_relay, dns_origin),
+            _ => anyhow::bail!("SENDME_PKARR_RELAY and SENDME_DNS_ORIGIN must be set together"),
+        };
+        let pkarr_relay = url::Url::parse(&pkarr_relay).context("invalid SENDME_PKARR_RELAY")?;
+        let dns_server = std::env::var("SENDME_DNS_SERVER")
+            .ok()
+            .map(|addr| addr.parse().context("invalid SENDME_DNS_SERVER"))
+            .transpose()?;
+        Ok(Self::Custom {
+            pkarr_relay,
+            dns_origin,
+            dns_server,
+        })
+    }
+}
+
+/// Creates an endpoint builder that only talks to the configured relay and discovery services.
+///
+/// Unlike `Endpoint::builder()`, this does not install the n0 preset, so a `Custom`
+/// discovery option never falls back to n0 hosts behind the caller's back.
+pub fn endpoint_builder(
+    relay_mode: &RelayModeOption,
+    discovery: &DiscoveryOption,
+) -> iroh::endpoint::Builder {
+    let mut builder = iroh::Endpoint::empty_builder(relay_mode.clone().into());
+    match discovery {
+        DiscoveryOption::Disabled => {}
+        DiscoveryOption::Default => {
+            builder = builder
+                .discovery(PkarrPublisher::n0_dns())
+                .discovery(DnsDiscovery::n0_dns());
+        }
+        DiscoveryOption::Custom {
+            pkarr_relay,
+            dns_origin,
+            dns_server,
+        } => {
+            if let Some(dns_server) = dns_server {
+                builder = builder.dns_resolver(DnsResolver::with_nameserver(*dns_server));
+            }
+            builder = builder
+                .discovery(PkarrPublisher::builder(pkarr_relay.clone()))
+                .discovery(DnsDiscovery::builder(dns_origin.clone()));
+        }
+    }
+    builder
+}
+
 #[derive(
     Copy,
     Clone,
@@ -118,6 +654,82 @@ pub fn get_or_create_secret() -> anyhow::Result<iroh::SecretKey> {
     }
 }
 
+/// Loads the secret key stored at `path`, creating and saving a new one if there is none.
+///
+/// This gives long-running endpoints, like an always-on inbox, an endpoint id that
+/// survives restarts.
+pub fn load_or_create_secret(path: &Path) -> anyhow::Result<iroh::SecretKey> {
+    if path.exists() {
+        let secret = std::fs::read_to_string(path)?;
+        return iroh::SecretKey::from_str(secret.trim())
+            .with_context(|| format!("invalid secret key in {}", path.display()));
+    }
+    let key = iroh::SecretKey::generate(&mut rand::rng());
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, data_encoding::HEXLOWER.encode(&key.to_bytes()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(key)
+}
+
 use anyhow::Context;
-use iroh::TransportAddr;
+use iroh::{
+    discovery::{dns::DnsDiscovery, pkarr::PkarrPublisher},
+    dns::DnsResolver,
+    TransportAddr,
+};
 use std::str::FromStr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn load_or_create_secret_is_stable() {
+        let td = tempfile::TempDir::new().unwrap();
+        let path = td.path().join("keys/secret");
+        let first = load_or_create_secret(&path).unwrap();
+        let second = load_or_create_secret(&path).unwrap();
+        assert_eq!(first.public(), second.public());
+    }
+
+    #[tokio::test]
+    async fn custom_discovery_publishes_to_configured_pkarr_relay() {
+        // A local stand-in for a self-hosted pkarr relay: accept one request and record it.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay_addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+
+        let discovery = DiscoveryOption::Custom {
+            pkarr_relay: format!("http://{relay_addr}/pkarr").parse().unwrap(),
+            dns_origin: "sendme.internal".to_string(),
+            dns_server: None,
+        };
+        let endpoint = endpoint_builder(&RelayModeOption::Disabled, &discovery)
+            .bind()
+            .await
+            .unwrap();
+
+        let request = tokio::time::timeout(std::time::Duration::from_secs(10), server)
+            .await
+            .expect("no publish request reached the configured pkarr relay")
+            .unwrap();
+        assert!(request.starts_with("PUT /pkarr/"), "{request}");
+        endpoint.close().await;
+    }
+}
@@ -0,0 +1,207 @@
+use crate::core::error::{Error, Result};
+use crate::core::filename::{export_paths, FilenamePolicy};
+use crate::core::send::canonicalized_path_to_string;
+use anyhow::Context;
+use iroh_blobs::{format::collection::Collection, Hash};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use walkdir::WalkDir;
+
+/// The names and content hashes of a received collection.
+///
+/// Save it next to (not inside) a received folder to check that folder later with [`verify`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub collection: Hash,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub hash: Hash,
+}
+
+impl Manifest {
+    pub fn from_collection(collection_hash: Hash, collection: &Collection) -> Self {
+        Self {
+            collection: collection_hash,
+            files: collection
+                .iter()
+                .map(|(name, hash)| ManifestEntry {
+                    name: name.clone(),
+                    hash: *hash,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read manifest {}", path.display()))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// What to check a received file or folder against.
+#[derive(Debug, Clone)]
+pub enum Expected {
+    /// The collection hash from the ticket. The path must hold exactly the received files.
+    Collection(Hash),
+    /// A saved manifest; extra files next to the received ones are ignored.
+    Manifest(Manifest),
+}
+
+impl FromStr for Expected {
+    type Err = anyhow::Error;
+
+    /// Parses a collection hash, or else reads the manifest file at that path.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match Hash::from_str(s.trim()) {
+            Ok(hash) => Ok(Self::Collection(hash)),
+            Err(_) => Ok(Self::Manifest(Manifest::load(Path::new(s))?)),
+        }
+    }
+}
+
+/// Outcome of re-hashing received files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    pub verified: Vec<String>,
+    pub mismatched: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+pub(crate) async fn hash_file(path: PathBuf) -> std::io::Result<Hash> {
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(std::fs::File::open(path)?)?;
+        Ok(hasher.finalize().into())
+    })
+    .await?
+}
+
+/// Re-hashes every manifest entry below `root`, the directory the collection was exported to.
+pub(crate) async fn check_manifest(
+    root: &Path,
+    manifest: &Manifest,
+) -> anyhow::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    // Names were mapped the same way when the collection was exported
+    let paths = export_paths(
+        root,
+        manifest.files.iter().map(|entry| entry.name.as_str()),
+        FilenamePolicy::Map,
+    )?;
+    for (entry, path) in manifest.files.iter().zip(paths) {
+        match hash_file(path).await {
+            Ok(hash) if hash == entry.hash => report.verified.push(entry.name.clone()),
+            Ok(_) => report.mismatched.push(entry.name.clone()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                report.missing.push(entry.name.clone())
+            }
+            Err(e) => return Err(e).with_context(|| format!("failed to hash {}", entry.name)),
+        }
+    }
+    Ok(report)
+}
+
+/// Checks a previously received file or folder against its collection hash or manifest.
+///
+/// `path` is the received item itself, e.g. `~/Downloads/project`, so names resolve the
+/// same way they did when the sender imported it.
+pub async fn verify(path: impl AsRef<Path>, expected: Expected) -> Result<VerifyReport> {
+    check(path.as_ref(), expected).await.map_err(Error::from)
+}
+
+async fn check(path: &Path, expected: Expected) -> anyhow::Result<VerifyReport> {
+    let path = path.canonicalize()?;
+    let root = path.parent().context("cannot verify a filesystem root")?;
+    match expected {
+        Expected::Manifest(manifest) => check_manifest(root, &manifest).await,
+        Expected::Collection(expected) => {
+            let mut files = Vec::new();
+            for entry in WalkDir::new(&path) {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let name = canonicalized_path_to_string(entry.path().strip_prefix(root)?, true)?;
+                let hash = hash_file(entry.into_path()).await?;
+                files.push((name, hash));
+            }
+            files.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let names = files.iter().map(|(name, _)| name.clone()).collect();
+            let collection = files.into_iter().collect::<Collection>();
+            let root_blob = collection
+                .to_blobs()
+                .last()
+                .context("collection has no root blob")?;
+            let actual = Hash::new(root_blob);
+            anyhow::ensure!(
+                actual == expected,
+                "{} does not match collection {}: it hashes to {}",
+                path.display(),
+                expected,
+                actual
+            );
+            Ok(VerifyReport {
+                verified: names,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::send::import;
+
+    #[tokio::test]
+    async fn verify_detects_altered_files() {
+        let td = tempfile::TempDir::new().unwrap();
+        let dir = td.path().join("project");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.txt"), "alpha").unwrap();
+        std::fs::write(dir.join("sub/b.txt"), "beta").unwrap();
+
+        let store = iroh_blobs::store::mem::MemStore::new();
+        let (tag, _, collection, _) = import(dir.clone(), &store, false, None, None)
+            .await
+            .unwrap();
+        let manifest = Manifest::from_collection(tag.hash(), &collection);
+
+        let report = verify(&dir, Expected::Collection(tag.hash()))
+            .await
+            .unwrap();
+        assert_eq!(report.verified, vec!["project/a.txt", "project/sub/b.txt"]);
+        let report = verify(&dir, Expected::Manifest(manifest.clone()))
+            .await
+            .unwrap();
+        assert!(report.is_ok());
+
+        std::fs::write(dir.join("a.txt"), "alphA").unwrap();
+        std::fs::remove_file(dir.join("sub/b.txt")).unwrap();
+        let report = verify(&dir, Expected::Manifest(manifest)).await.unwrap();
+        assert_eq!(report.mismatched, vec!["project/a.txt"]);
+        assert_eq!(report.missing, vec!["project/sub/b.txt"]);
+        assert!(verify(&dir, Expected::Collection(tag.hash()))
+            .await
+            .is_err());
+    }
+}
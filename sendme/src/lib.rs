@@ -1,10 +1,33 @@
 pub mod core;
 
 pub use core::{
+    approval::{Approvals, ConnectionRequest},
+    contacts::{Contact, ContactBook},
+    error::{Error, Result},
+    filename::FilenamePolicy,
+    history::{HistoryFormat, TransferDirection, TransferHistory, TransferOutcome, TransferRecord},
+    inbox::{push, read_transfer_log, start_inbox, InboxTicket, PUSH_ALPN},
+    mirror::{
+        mirror_conflicts, start_mirror, subscribe_mirror, MirrorConflict, MirrorPlan, MirrorTicket,
+        MIRROR_ALPN,
+    },
+    offer::{OfferAnswer, OfferPrompt, OfferedFile, ReceiveOffer},
+    pairing::{host_pairing, join_pairing, PairingHost, PairingTicket, PendingPairing, PAIR_ALPN},
+    passphrase::{is_locked_ticket, LockedTicket, AUTH_ALPN},
+    provenance::{provenance, Provenance, ORIGIN_URL_ATTR, SENDER_ATTR},
+    proxy::ProxyError,
     receive::download,
+    receivers::Receivers,
     send::start_share,
+    streaming::{is_streaming_ticket, StreamingTicket, STREAM_ALPN},
     types::{
-        AddrInfoOptions, AppHandle, EventEmitter, ReceiveOptions, ReceiveResult, RelayModeOption,
-        SendOptions, SendResult,
+        load_or_create_secret, AddrInfoOptions, AppHandle, ApprovalOptions, DiscoveryOption,
+        EventEmitter, InboxOptions, InboxResult, InboxTransferRecord, MirrorOptions, MirrorResult,
+        MirrorSubscription, PairingOptions, ProviderStats, ProxyOption, PushResult, ReceiveOptions,
+        ReceiveResult, ReceiverProgress, ReceiverState, RelayModeOption, SendOptions, SendResult,
+        SkippedFile,
     },
+    verify::{verify, Expected, Manifest, ManifestEntry, VerifyReport},
 };
+
+pub use iroh::{EndpointId, SecretKey};
@@ -1,15 +1,25 @@
-use crate::state::{AppStateMutex, ShareHandle};
+use crate::daemon::{self, DeviceIdentity, DropFolderConfig, NetworkOptions};
+use crate::state::{
+    AppStateMutex, InboxHandle, MirrorHandle, MirrorSubscriptionHandle, ShareHandle,
+};
 use sendme::{
-    download, start_share, AddrInfoOptions, AppHandle, EventEmitter, ReceiveOptions,
-    RelayModeOption, SendOptions,
+    download, host_pairing, is_locked_ticket, join_pairing, mirror_conflicts, push,
+    read_transfer_log, start_inbox, start_mirror, start_share, subscribe_mirror, AddrInfoOptions,
+    AppHandle, Contact, EndpointId, Error, EventEmitter, Expected, FilenamePolicy, HistoryFormat,
+    InboxOptions, InboxTicket, InboxTransferRecord, MirrorConflict, MirrorOptions, PairingOptions,
+    PendingPairing, Provenance, ProxyOption, ReceiveOptions, ReceiverProgress, SendOptions,
+    TransferRecord, VerifyReport,
 };
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use tauri::{Emitter, State};
 
 // Wrapper for Tauri AppHandle that implements EventEmitter
-struct TauriEventEmitter {
-    app_handle: tauri::AppHandle,
+pub(crate) struct TauriEventEmitter {
+    pub(crate) app_handle: tauri::AppHandle,
 }
 
 impl EventEmitter for TauriEventEmitter {
@@ -26,21 +36,34 @@ impl EventEmitter for TauriEventEmitter {
     }
 }
 
+/// The error transfer commands return when the user picked a path that has since gone away
+fn path_does_not_exist(path: &std::path::Path) -> Error {
+    Error::PathRejected {
+        path: path.display().to_string(),
+        reason: "path does not exist".to_string(),
+    }
+}
+
+/// The error for a path that exists but is neither a file nor a directory, e.g. a socket
+fn not_a_file_or_directory(path: &std::path::Path) -> Error {
+    Error::PathRejected {
+        path: path.display().to_string(),
+        reason: "path is neither a file nor a directory".to_string(),
+    }
+}
+
 /// Get file or directory size
 #[tauri::command]
-pub async fn get_file_size(path: String) -> Result<u64, String> {
+pub async fn get_file_size(path: String) -> Result<u64, Error> {
     let path = PathBuf::from(path);
 
     if !path.exists() {
-        return Err("Path does not exist".to_string());
+        return Err(path_does_not_exist(&path));
     }
 
     if path.is_file() {
         // For files, get the file size directly
-        match std::fs::metadata(&path) {
-            Ok(metadata) => Ok(metadata.len()),
-            Err(e) => Err(format!("Failed to get file metadata: {}", e)),
-        }
+        Ok(std::fs::metadata(&path)?.len())
     } else if path.is_dir() {
         // For directories, calculate total size recursively
         let mut total_size = 0u64;
@@ -63,36 +86,70 @@ pub async fn get_file_size(path: String) -> Result<u64, String> {
 
         Ok(total_size)
     } else {
-        Err("Path is neither a file nor a directory".to_string())
+        Err(not_a_file_or_directory(&path))
     }
 }
 
-/// Start sharing a file or directory
+/// Start sharing a file or directory, optionally restricted to a single contact
+/// and/or protected by a passphrase
 #[tauri::command]
 pub async fn start_sharing(
     path: String,
+    contact: Option<String>,
+    passphrase: Option<String>,
+    strict: Option<bool>,
+    streaming: Option<bool>,
     state: State<'_, AppStateMutex>,
     app_handle: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, Error> {
     let path = PathBuf::from(path);
 
     // Check if already sharing
     let mut app_state = state.lock().await;
     if app_state.current_share.is_some() {
-        return Err("Already sharing a file. Please stop current share first.".to_string());
+        return Err(Error::other(
+            "Already sharing a file. Please stop current share first.",
+        ));
     }
 
     // Validate path exists
     if !path.exists() {
-        return Err(format!("Path does not exist: {}", path.display()));
+        return Err(path_does_not_exist(&path));
     }
 
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+
+    let allowed_peers = match contact {
+        Some(contact) => {
+            let contact = find_contact(&app_handle, &contact)?;
+            Some([contact.endpoint_id].into())
+        }
+        None => None,
+    };
+
     // Create send options with defaults
     let options = SendOptions {
-        relay_mode: RelayModeOption::Default,
+        relay_mode,
+        secret_key: None,
+        allowed_peers,
+        passphrase: passphrase.filter(|p| !p.is_empty()),
+        history: history_file(&app_handle),
+        // Skipped files are reported through `share-warnings` unless the caller wants a hard failure
+        strict: strict.unwrap_or(false),
+        // Without an app data directory every share hashes its files from scratch
+        store_dir: daemon::share_store_dir(&app_handle).ok(),
+        // The final ticket follows in `share-ready` once every file is hashed
+        streaming: streaming.unwrap_or(false),
+        progress_interval: None,
+        approval: None,
         ticket_type: AddrInfoOptions::RelayAndAddresses,
         magic_ipv4_addr: None,
         magic_ipv6_addr: None,
+        discovery,
+        proxy: ProxyOption::Environment,
     };
 
     // Wrap the app_handle in our EventEmitter implementation
@@ -109,20 +166,21 @@ pub async fn start_sharing(
             app_state.current_share = Some(ShareHandle::new(ticket.clone(), path, result));
             Ok(ticket)
         }
-        Err(e) => Err(format!("Failed to start sharing: {}", e)),
+        Err(e) => {
+            tracing::error!("Failed to start sharing: {}", e);
+            Err(e)
+        }
     }
 }
 
 /// Stop the current sharing session
 #[tauri::command]
-pub async fn stop_sharing(state: State<'_, AppStateMutex>) -> Result<(), String> {
+pub async fn stop_sharing(state: State<'_, AppStateMutex>) -> Result<(), Error> {
     let mut app_state = state.lock().await;
 
     if let Some(mut share) = app_state.current_share.take() {
         // Explicitly clean up the share session
-        if let Err(e) = share.stop().await {
-            return Err(e);
-        }
+        share.stop().await.map_err(Error::other)?;
 
         #[cfg(target_os = "android")]
         std::fs::remove_dir_all(&share._path);
@@ -131,20 +189,115 @@ pub async fn stop_sharing(state: State<'_, AppStateMutex>) -> Result<(), String>
     Ok(())
 }
 
+/// Throw away the blob store kept across shares, so the next share hashes every file again
+#[tauri::command]
+pub async fn clear_share_store(
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), Error> {
+    let dir = daemon::share_store_dir(&app_handle).map_err(Error::other)?;
+    let app_state = state.lock().await;
+    let sharing = app_state
+        .current_share
+        .as_ref()
+        .is_some_and(|share| share.send_result.blobs_data_dir == dir);
+    if sharing {
+        return Err(Error::other("Stop sharing before clearing the share store"));
+    }
+
+    match tokio::fs::remove_dir_all(&dir).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Payload of `receive-collection`: what to check a download against later
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceivedCollection {
+    pub hash: String,
+    pub manifest: Option<PathBuf>,
+}
+
 /// Receive a file using a ticket
 #[tauri::command]
 pub async fn receive_file(
     ticket: String,
     output_path: String,
+    passphrase: Option<String>,
+    verify: Option<bool>,
+    save_manifest: Option<bool>,
+    max_total_bytes: Option<u64>,
+    max_files: Option<u64>,
+    filename_policy: Option<FilenamePolicy>,
+    tag_origin: Option<bool>,
+    providers: Option<Vec<String>>,
+    reseed: Option<bool>,
+    incremental: Option<bool>,
+    state: State<'_, AppStateMutex>,
     app_handle: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, Error> {
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+
+    // The reseeded collection becomes the current share, so there must not be one yet
+    let reseed = reseed.unwrap_or(false);
+    if reseed && state.lock().await.current_share.is_some() {
+        return Err(Error::other(
+            "Already sharing a file. Please stop current share first.",
+        ));
+    }
+
+    // Downloads from contacts run as this device, which pauses the drop folder meanwhile
+    let identity = daemon::lend_device_identity(&app_handle)
+        .await
+        .map_err(Error::other)?;
+
     // Create receive options with user-specified output path
     let output_dir = PathBuf::from(output_path);
     let options = ReceiveOptions {
         output_dir: Some(output_dir),
-        relay_mode: RelayModeOption::Default,
+        // Download as this device from contacts, so shares restricted to it let it in;
+        // anyone else sees a key made for this download
+        secret_key: Some(identity.secret()),
+        identify_to: Some(contact_ids(&app_handle)?),
+        passphrase,
+        // Per-provider throughput is reported through `receive-providers`
+        providers: providers.unwrap_or_default(),
+        verify: verify.unwrap_or(false),
+        // Lets `verify_received` check the files later against what was sent. The manifest
+        // is a dotfile next to the download, so it is only written when asked for
+        save_manifest: save_manifest.unwrap_or(false),
+        history: history_file(&app_handle),
+        // Collections over these limits are refused before any data moves
+        max_total_bytes,
+        max_files,
+        filename_policy: filename_policy.unwrap_or_default(),
+        tag_origin: tag_origin.unwrap_or(false),
+        // Incremental downloads fetch only what changed and update the previous copy in place
+        store_dir: match incremental {
+            Some(true) => Some(daemon::receive_store_dir(&app_handle).map_err(Error::other)?),
+            _ => None,
+        },
+        update: incremental.unwrap_or(false),
+        // The new ticket is announced through `receive-reseeded`
+        reseed: reseed.then(|| SendOptions {
+            relay_mode: relay_mode.clone(),
+            history: history_file(&app_handle),
+            ticket_type: AddrInfoOptions::RelayAndAddresses,
+            discovery: discovery.clone(),
+            proxy: ProxyOption::Environment,
+            ..Default::default()
+        }),
+        progress_interval: None,
+        confirm: None,
+        relay_mode,
         magic_ipv4_addr: None,
         magic_ipv6_addr: None,
+        discovery,
+        proxy: ProxyOption::Environment,
     };
 
     // Wrap the app_handle in our EventEmitter implementation
@@ -154,18 +307,689 @@ pub async fn receive_file(
     let boxed_handle: AppHandle = Some(emitter);
 
     // Download using the core library
-    match download(ticket, options, boxed_handle).await {
-        Ok(result) => Ok(result.message),
+    let result = download(ticket, options, boxed_handle).await;
+    drop(identity);
+    match result {
+        Ok(result) => {
+            let received = ReceivedCollection {
+                hash: result.hash.clone(),
+                manifest: result.manifest.clone(),
+            };
+            if let Err(e) = app_handle.emit("receive-collection", received) {
+                tracing::warn!("Failed to emit receive-collection event: {}", e);
+            }
+            if let Some(share) = result.reseed {
+                let handle =
+                    ShareHandle::new(share.ticket.clone(), result.file_path.clone(), share);
+                let mut app_state = state.lock().await;
+                // If another share started meanwhile, dropping the handle removes the store
+                if app_state.current_share.is_none() {
+                    app_state.current_share = Some(handle);
+                } else if let Err(e) = app_handle.emit(
+                    "receive-reseed-failed",
+                    "another share started while downloading",
+                ) {
+                    tracing::warn!("Failed to emit receive-reseed-failed event: {}", e);
+                }
+            }
+            Ok(result.message)
+        }
         Err(e) => {
             tracing::error!("Failed to receive file: {}", e);
-            Err(format!("Failed to receive file: {}", e))
+            Err(e)
         }
     }
 }
 
+/// Re-check a received file or folder against its collection hash or a saved manifest
+#[tauri::command]
+pub async fn verify_received(path: String, expected: String) -> Result<VerifyReport, Error> {
+    let expected = Expected::from_str(&expected)
+        .map_err(|e| Error::other(format!("Invalid collection hash or manifest: {}", e)))?;
+    sendme::verify(PathBuf::from(path), expected).await
+}
+
+/// Where a received file came from, if it was tagged when it arrived
+#[tauri::command]
+pub async fn get_file_provenance(path: String) -> Result<Provenance, Error> {
+    sendme::provenance(Path::new(&path))
+}
+
+/// Whether a ticket needs a passphrase before it can be received
+#[tauri::command]
+pub async fn is_ticket_locked(ticket: String) -> Result<bool, Error> {
+    Ok(is_locked_ticket(&ticket))
+}
+
+/// Open an inbox that accepts files pushed by senders
+#[tauri::command]
+pub async fn open_inbox(
+    output_path: String,
+    tag_origin: Option<bool>,
+    max_total_bytes: Option<u64>,
+    max_files: Option<u64>,
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, Error> {
+    let mut app_state = state.lock().await;
+    if app_state.current_inbox.is_some() {
+        return Err(Error::other(
+            "Inbox is already open. Please close it first.",
+        ));
+    }
+
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+
+    let options = InboxOptions {
+        output_dir: Some(PathBuf::from(output_path)),
+        secret_key: None,
+        allowed_peers: None,
+        contacts: None,
+        transfer_log: None,
+        history: history_file(&app_handle),
+        tag_origin: tag_origin.unwrap_or(false),
+        max_total_bytes,
+        max_files,
+        relay_mode,
+        ticket_type: AddrInfoOptions::RelayAndAddresses,
+        magic_ipv4_addr: None,
+        magic_ipv6_addr: None,
+        discovery,
+        proxy: ProxyOption::Environment,
+    };
+
+    let emitter = Arc::new(TauriEventEmitter {
+        app_handle: app_handle.clone(),
+    });
+    let boxed_handle: AppHandle = Some(emitter);
+
+    match start_inbox(options, boxed_handle).await {
+        Ok(result) => {
+            let ticket = result.ticket.clone();
+            app_state.current_inbox = Some(InboxHandle::new(result));
+            Ok(ticket)
+        }
+        Err(e) => {
+            tracing::error!("Failed to open inbox: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Close the current inbox
+#[tauri::command]
+pub async fn close_inbox(state: State<'_, AppStateMutex>) -> Result<(), Error> {
+    let mut app_state = state.lock().await;
+
+    if let Some(mut inbox) = app_state.current_inbox.take() {
+        inbox.stop().await.map_err(Error::other)?;
+    }
+
+    Ok(())
+}
+
+/// Get the ticket of the open inbox, if any
+#[tauri::command]
+pub async fn get_inbox_status(state: State<'_, AppStateMutex>) -> Result<Option<String>, Error> {
+    let app_state = state.lock().await;
+    Ok(app_state
+        .current_inbox
+        .as_ref()
+        .map(|inbox| inbox.ticket.clone()))
+}
+
+/// Mirror a folder: subscribers receive every change to it, including deletions and renames
+#[tauri::command]
+pub async fn start_mirroring(
+    path: String,
+    contact: Option<String>,
+    strict: Option<bool>,
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, Error> {
+    let path = PathBuf::from(path);
+    let mut app_state = state.lock().await;
+    if app_state.current_mirror.is_some() {
+        return Err(Error::other(
+            "Already mirroring a folder. Please stop the current mirror first.",
+        ));
+    }
+    if !path.exists() {
+        return Err(path_does_not_exist(&path));
+    }
+
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+    let allowed_peers = match contact {
+        Some(contact) => {
+            let contact = find_contact(&app_handle, &contact)?;
+            Some([contact.endpoint_id].into())
+        }
+        None => None,
+    };
+    let options = SendOptions {
+        relay_mode,
+        allowed_peers,
+        history: history_file(&app_handle),
+        strict: strict.unwrap_or(false),
+        ticket_type: AddrInfoOptions::RelayAndAddresses,
+        discovery,
+        proxy: ProxyOption::Environment,
+        // Only the files that changed are hashed again, also after a restart
+        store_dir: daemon::mirror_store_dir(&app_handle).ok(),
+        ..Default::default()
+    };
+
+    let emitter = Arc::new(TauriEventEmitter {
+        app_handle: app_handle.clone(),
+    });
+    let boxed_handle: AppHandle = Some(emitter);
+
+    // Every new version is announced through `mirror-published`
+    match start_mirror(
+        path.clone(),
+        options,
+        MirrorOptions::default(),
+        boxed_handle,
+    )
+    .await
+    {
+        Ok(result) => {
+            let ticket = result.ticket.clone();
+            app_state.current_mirror = Some(MirrorHandle::new(path, result));
+            Ok(ticket)
+        }
+        Err(e) => {
+            tracing::error!("Failed to start mirroring: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Stop mirroring the current folder
+#[tauri::command]
+pub async fn stop_mirroring(state: State<'_, AppStateMutex>) -> Result<(), Error> {
+    let mut app_state = state.lock().await;
+
+    if let Some(mut mirror) = app_state.current_mirror.take() {
+        mirror.stop().await.map_err(Error::other)?;
+    }
+
+    Ok(())
+}
+
+/// Keep a local folder in step with a mirror
+#[tauri::command]
+pub async fn subscribe_to_mirror(
+    ticket: String,
+    output_path: String,
+    dry_run: Option<bool>,
+    filename_policy: Option<FilenamePolicy>,
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), Error> {
+    if state.lock().await.mirror_subscription.is_some() {
+        return Err(Error::other(
+            "Already following a mirror. Please unsubscribe first.",
+        ));
+    }
+    // Lending the identity locks the state too, so it is taken before the state
+    let identity = daemon::lend_device_identity(&app_handle)
+        .await
+        .map_err(Error::other)?;
+    let mut app_state = state.lock().await;
+
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+    let options = ReceiveOptions {
+        output_dir: Some(PathBuf::from(output_path)),
+        secret_key: Some(identity.secret()),
+        identify_to: Some(contact_ids(&app_handle)?),
+        filename_policy: filename_policy.unwrap_or_default(),
+        relay_mode,
+        discovery,
+        proxy: ProxyOption::Environment,
+        ..Default::default()
+    };
+    // A dry run only reports `mirror-plan`; otherwise each version ends in `mirror-applied`
+    let mirror = MirrorOptions {
+        dry_run: dry_run.unwrap_or(false),
+        ..Default::default()
+    };
+
+    let emitter = Arc::new(TauriEventEmitter {
+        app_handle: app_handle.clone(),
+    });
+    let boxed_handle: AppHandle = Some(emitter);
+
+    let subscription = subscribe_mirror(ticket, options, mirror, boxed_handle).await?;
+    app_state.mirror_subscription = Some(MirrorSubscriptionHandle {
+        subscription,
+        _identity: identity,
+    });
+    Ok(())
+}
+
+/// Stop following the current mirror; the folder keeps what was applied so far
+#[tauri::command]
+pub async fn unsubscribe_from_mirror(state: State<'_, AppStateMutex>) -> Result<(), Error> {
+    state.lock().await.mirror_subscription.take();
+    Ok(())
+}
+
+/// Files a mirror subscription left alone in `output_path` because they were changed locally
+#[tauri::command]
+pub async fn get_mirror_conflicts(output_path: String) -> Result<Vec<MirrorConflict>, Error> {
+    mirror_conflicts(Path::new(&output_path))
+}
+
+/// Push a file or directory into someone else's inbox
+#[tauri::command]
+pub async fn push_to_inbox(
+    path: String,
+    ticket: String,
+    strict: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, Error> {
+    let path = PathBuf::from(path);
+    if !path.exists() {
+        return Err(path_does_not_exist(&path));
+    }
+
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+
+    let identity = daemon::lend_device_identity(&app_handle)
+        .await
+        .map_err(Error::other)?;
+    let options = SendOptions {
+        relay_mode,
+        // Push as this device so drop folders that paired with it accept the transfer
+        secret_key: Some(identity.secret()),
+        allowed_peers: None,
+        passphrase: None,
+        history: history_file(&app_handle),
+        // Skipped files are reported through `share-warnings` unless the caller wants a hard failure
+        strict: strict.unwrap_or(false),
+        store_dir: None,
+        streaming: false,
+        progress_interval: None,
+        approval: None,
+        ticket_type: AddrInfoOptions::RelayAndAddresses,
+        magic_ipv4_addr: None,
+        magic_ipv6_addr: None,
+        discovery,
+        proxy: ProxyOption::Environment,
+    };
+
+    let emitter = Arc::new(TauriEventEmitter {
+        app_handle: app_handle.clone(),
+    });
+    let boxed_handle: AppHandle = Some(emitter);
+
+    match push(path, ticket, options, boxed_handle).await {
+        Ok(result) => Ok(format!("Pushed {} bytes", result.size)),
+        Err(e) => {
+            tracing::error!("Failed to push to inbox: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Get this device's id, which other devices add to their trusted devices
+#[tauri::command]
+pub async fn get_device_id(app_handle: tauri::AppHandle) -> Result<String, Error> {
+    Ok(daemon::device_secret(&app_handle)
+        .map_err(Error::other)?
+        .public()
+        .to_string())
+}
+
+/// Get the drop folder settings
+#[tauri::command]
+pub async fn get_drop_folder_config(
+    app_handle: tauri::AppHandle,
+) -> Result<DropFolderConfig, Error> {
+    daemon::load_config(&app_handle).map_err(Error::other)
+}
+
+/// Save the drop folder settings, restarting the drop folder so they take effect
+#[tauri::command]
+pub async fn set_drop_folder_config(
+    config: DropFolderConfig,
+    app_handle: tauri::AppHandle,
+) -> Result<(), Error> {
+    daemon::save_config(&app_handle, &config).map_err(Error::other)?;
+    daemon::stop(&app_handle).await.map_err(Error::other)?;
+    if config.enabled {
+        daemon::start(&app_handle).await.map_err(Error::other)?;
+    }
+    Ok(())
+}
+
+/// Bring the drop folder online and return its ticket
+#[tauri::command]
+pub async fn start_drop_folder(app_handle: tauri::AppHandle) -> Result<String, Error> {
+    daemon::start(&app_handle).await.map_err(Error::other)
+}
+
+/// Take the drop folder offline
+#[tauri::command]
+pub async fn stop_drop_folder(app_handle: tauri::AppHandle) -> Result<(), Error> {
+    daemon::stop(&app_handle).await.map_err(Error::other)
+}
+
+/// Get the ticket of the drop folder if it is online
+#[tauri::command]
+pub async fn get_drop_folder_status(
+    state: State<'_, AppStateMutex>,
+) -> Result<Option<String>, Error> {
+    let app_state = state.lock().await;
+    Ok(app_state
+        .drop_folder
+        .as_ref()
+        .map(|inbox| inbox.ticket.clone()))
+}
+
+/// Get the transfers the drop folder has accepted, oldest first
+#[tauri::command]
+pub async fn get_drop_folder_log(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<InboxTransferRecord>, Error> {
+    let path = daemon::log_path(&app_handle).map_err(Error::other)?;
+    read_transfer_log(&path)
+}
+
+/// Where transfers are recorded; a broken app data directory only costs the record
+fn history_file(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    match daemon::history(app_handle) {
+        Ok(history) => Some(history.path().to_path_buf()),
+        Err(e) => {
+            tracing::warn!("Transfer history unavailable: {}", e);
+            None
+        }
+    }
+}
+
+/// Get every recorded share, download and push, newest first
+#[tauri::command]
+pub async fn list_history(app_handle: tauri::AppHandle) -> Result<Vec<TransferRecord>, Error> {
+    let mut records = daemon::history(&app_handle).map_err(Error::other)?.list()?;
+    records.reverse();
+    Ok(records)
+}
+
+/// Forget all recorded transfers
+#[tauri::command]
+pub async fn clear_history(app_handle: tauri::AppHandle) -> Result<(), Error> {
+    daemon::history(&app_handle).map_err(Error::other)?.clear()
+}
+
+/// Write the transfer history to a file, as "json" or "csv"
+#[tauri::command]
+pub async fn export_history(
+    path: String,
+    format: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), Error> {
+    let format = HistoryFormat::from_str(&format)?;
+    daemon::history(&app_handle)
+        .map_err(Error::other)?
+        .export(&PathBuf::from(path), format)
+}
+
+/// What the user compares on both screens before confirming a pairing
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingInfo {
+    pub peer: String,
+    pub peer_name: String,
+    pub sas: String,
+}
+
+impl From<&PendingPairing> for PairingInfo {
+    fn from(pending: &PendingPairing) -> Self {
+        Self {
+            peer: pending.peer.to_string(),
+            peer_name: pending.peer_name.clone(),
+            sas: pending.sas.clone(),
+        }
+    }
+}
+
+fn pairing_options(identity: &DeviceIdentity, name: String) -> Result<PairingOptions, Error> {
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = daemon::network_options().map_err(Error::other)?;
+    Ok(PairingOptions {
+        name,
+        secret_key: Some(identity.secret()),
+        relay_mode,
+        ticket_type: AddrInfoOptions::RelayAndAddresses,
+        magic_ipv4_addr: None,
+        magic_ipv6_addr: None,
+        discovery,
+        proxy: ProxyOption::Environment,
+    })
+}
+
+/// Endpoint ids of the paired devices, the senders a download presents the device key to
+fn contact_ids(app_handle: &tauri::AppHandle) -> Result<HashSet<EndpointId>, Error> {
+    Ok(daemon::load_contacts(app_handle)
+        .map_err(Error::other)?
+        .endpoint_ids())
+}
+
+fn find_contact(app_handle: &tauri::AppHandle, contact: &str) -> Result<Contact, Error> {
+    daemon::load_contacts(app_handle)
+        .map_err(Error::other)?
+        .find(contact)
+        .cloned()
+        .ok_or_else(|| Error::other(format!("Unknown contact: {}", contact)))
+}
+
+fn parse_device_id(endpoint_id: &str) -> Result<EndpointId, Error> {
+    EndpointId::from_str(endpoint_id).map_err(|e| Error::other(format!("Invalid device id: {}", e)))
+}
+
+fn pairing_already_in_progress() -> Error {
+    Error::other("A pairing is already in progress. Please cancel it first.")
+}
+
+async fn pairing_in_progress(state: &State<'_, AppStateMutex>) -> bool {
+    let app_state = state.lock().await;
+    app_state.pairing_host.is_some() || app_state.pending_pairing.is_some()
+}
+
+/// Open a pairing session and return the ticket for the other device.
+/// Emits `pairing-sas` once a device has joined, or `pairing-failed`.
+#[tauri::command]
+pub async fn start_pairing(
+    name: String,
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, Error> {
+    if pairing_in_progress(&state).await {
+        return Err(pairing_already_in_progress());
+    }
+    let identity = daemon::lend_device_identity(&app_handle)
+        .await
+        .map_err(Error::other)?;
+    let mut app_state = state.lock().await;
+
+    let options = pairing_options(&identity, name)?;
+    let host = Arc::new(host_pairing(options).await?);
+    let ticket = host.ticket.clone();
+    app_state.pairing_host = Some(host.clone());
+    app_state.pairing_identity = Some(identity);
+    drop(app_state);
+
+    let state = state.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        let result = host.accept().await;
+        let mut app_state = state.lock().await;
+        match result {
+            Ok(pending) => {
+                let info = PairingInfo::from(&pending);
+                app_state.pending_pairing = Some(pending);
+                if let Err(e) = app_handle.emit("pairing-sas", info) {
+                    tracing::warn!("Failed to emit pairing-sas event: {}", e);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Pairing failed: {}", e);
+                app_state.pairing_host = None;
+                app_state.pairing_identity = None;
+                if let Err(e) = app_handle.emit("pairing-failed", e.to_string()) {
+                    tracing::warn!("Failed to emit pairing-failed event: {}", e);
+                }
+            }
+        }
+    });
+
+    Ok(ticket)
+}
+
+/// Join another device's pairing session and return the string to compare
+#[tauri::command]
+pub async fn join_pairing_session(
+    ticket: String,
+    name: String,
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<PairingInfo, Error> {
+    if pairing_in_progress(&state).await {
+        return Err(pairing_already_in_progress());
+    }
+    let identity = daemon::lend_device_identity(&app_handle)
+        .await
+        .map_err(Error::other)?;
+    let mut app_state = state.lock().await;
+
+    let options = pairing_options(&identity, name)?;
+    let pending = join_pairing(ticket, options).await?;
+    let info = PairingInfo::from(&pending);
+    app_state.pending_pairing = Some(pending);
+    app_state.pairing_identity = Some(identity);
+    Ok(info)
+}
+
+/// Confirm or reject the short authentication string.
+/// Returns the new contact once both devices have confirmed.
+#[tauri::command]
+pub async fn confirm_pairing(
+    accept: bool,
+    state: State<'_, AppStateMutex>,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<Contact>, Error> {
+    let mut app_state = state.lock().await;
+    let pending = app_state
+        .pending_pairing
+        .take()
+        .ok_or_else(|| Error::other("No pairing is waiting for confirmation"))?;
+    let host = app_state.pairing_host.take();
+    let identity = app_state.pairing_identity.take();
+    drop(app_state);
+
+    let result = pending.confirm(accept).await;
+    if let Some(host) = host {
+        host.close().await;
+    }
+    drop(identity);
+    let contact = result?;
+
+    if let Some(contact) = &contact {
+        let mut contacts = daemon::load_contacts(&app_handle).map_err(Error::other)?;
+        contacts.upsert(contact.clone());
+        contacts.save()?;
+    }
+    Ok(contact)
+}
+
+/// Abandon the current pairing session
+#[tauri::command]
+pub async fn cancel_pairing(state: State<'_, AppStateMutex>) -> Result<(), Error> {
+    let mut app_state = state.lock().await;
+    app_state.pending_pairing = None;
+    if let Some(host) = app_state.pairing_host.take() {
+        host.close().await;
+    }
+    app_state.pairing_identity = None;
+    Ok(())
+}
+
+/// List paired devices
+#[tauri::command]
+pub async fn list_contacts(app_handle: tauri::AppHandle) -> Result<Vec<Contact>, Error> {
+    Ok(daemon::load_contacts(&app_handle)
+        .map_err(Error::other)?
+        .contacts()
+        .to_vec())
+}
+
+/// Forget a paired device
+#[tauri::command]
+pub async fn remove_contact(
+    endpoint_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), Error> {
+    let endpoint_id = parse_device_id(&endpoint_id)?;
+    let mut contacts = daemon::load_contacts(&app_handle).map_err(Error::other)?;
+    if contacts.remove(&endpoint_id) {
+        contacts.save()?;
+    }
+    Ok(())
+}
+
+/// Change the nickname of a paired device
+#[tauri::command]
+pub async fn rename_contact(
+    endpoint_id: String,
+    nickname: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), Error> {
+    let endpoint_id = parse_device_id(&endpoint_id)?;
+    let mut contacts = daemon::load_contacts(&app_handle).map_err(Error::other)?;
+    if !contacts.rename(&endpoint_id, nickname) {
+        return Err(Error::other("Unknown contact"));
+    }
+    contacts.save()
+}
+
+/// Push a file or directory straight into a contact's drop folder
+#[tauri::command]
+pub async fn send_to_contact(
+    path: String,
+    contact: String,
+    strict: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, Error> {
+    let contact = find_contact(&app_handle, &contact)?;
+    // Contacts are addressed by endpoint id alone; discovery finds where they are
+    let ticket = InboxTicket::new(contact.endpoint_id.into()).to_string();
+    let message = push_to_inbox(path, ticket, strict, app_handle.clone()).await?;
+
+    let mut contacts = daemon::load_contacts(&app_handle).map_err(Error::other)?;
+    if contacts.touch(&contact.endpoint_id) {
+        if let Err(e) = contacts.save() {
+            tracing::warn!("Failed to save contacts: {}", e);
+        }
+    }
+    Ok(message)
+}
+
 /// Get the current sharing status
 #[tauri::command]
-pub async fn get_sharing_status(state: State<'_, AppStateMutex>) -> Result<Option<String>, String> {
+pub async fn get_sharing_status(state: State<'_, AppStateMutex>) -> Result<Option<String>, Error> {
     let app_state = state.lock().await;
     Ok(app_state
         .current_share
@@ -173,13 +997,26 @@ pub async fn get_sharing_status(state: State<'_, AppStateMutex>) -> Result<Optio
         .map(|share| share.ticket.clone()))
 }
 
+/// List the receivers connected to the current share, with what each was sent
+#[tauri::command]
+pub async fn list_receivers(
+    state: State<'_, AppStateMutex>,
+) -> Result<Vec<ReceiverProgress>, Error> {
+    let app_state = state.lock().await;
+    Ok(app_state
+        .current_share
+        .as_ref()
+        .map(|share| share.send_result.receivers.list())
+        .unwrap_or_default())
+}
+
 /// Check if a path is a file or directory
 #[tauri::command]
-pub async fn check_path_type(path: String) -> Result<String, String> {
+pub async fn check_path_type(path: String) -> Result<String, Error> {
     let path = PathBuf::from(path);
 
     if !path.exists() {
-        return Err("Path does not exist".to_string());
+        return Err(path_does_not_exist(&path));
     }
 
     if path.is_dir() {
@@ -187,13 +1024,13 @@ pub async fn check_path_type(path: String) -> Result<String, String> {
     } else if path.is_file() {
         Ok("file".to_string())
     } else {
-        Err("Path is neither a file nor a directory".to_string())
+        Err(not_a_file_or_directory(&path))
     }
 }
 
 /// Get the current transport status (whether bytes are actively being transferred)
 #[tauri::command]
-pub async fn get_transport_status(state: State<'_, AppStateMutex>) -> Result<bool, String> {
+pub async fn get_transport_status(state: State<'_, AppStateMutex>) -> Result<bool, Error> {
     let app_state = state.lock().await;
     Ok(app_state.is_transporting)
 }
@@ -201,9 +1038,7 @@ pub async fn get_transport_status(state: State<'_, AppStateMutex>) -> Result<boo
 /// Check if there was a launch intent (file path passed via CLI)
 /// Returns the path if present and clears it from state
 #[tauri::command]
-pub async fn check_launch_intent(
-    state: State<'_, AppStateMutex>,
-) -> Result<Option<String>, String> {
+pub async fn check_launch_intent(state: State<'_, AppStateMutex>) -> Result<Option<String>, Error> {
     let mut app_state = state.lock().await;
     Ok(app_state.launch_intent.take())
 }
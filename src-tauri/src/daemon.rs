@@ -0,0 +1,299 @@
+// Always-on drop folder: an inbox with a persistent identity that accepts pushes
+// from paired devices only and exports them straight into a configured folder.
+
+use crate::commands::TauriEventEmitter;
+use crate::state::{AppStateMutex, InboxHandle};
+use sendme::{
+    load_or_create_secret, start_inbox, AddrInfoOptions, AppHandle, ContactBook, DiscoveryOption,
+    EndpointId, InboxOptions, ProxyOption, RelayModeOption, SecretKey, TransferHistory,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use tauri::Manager;
+
+const CONFIG_FILE: &str = "drop-folder.json";
+const SECRET_FILE: &str = "device.key";
+const LOG_FILE: &str = "drop-folder-log.jsonl";
+const CONTACTS_FILE: &str = "contacts.json";
+const HISTORY_FILE: &str = "history.jsonl";
+const SHARE_STORE_DIR: &str = "share-store";
+const RECEIVE_STORE_DIR: &str = "receive-store";
+const MIRROR_STORE_DIR: &str = "mirror-store";
+
+/// Persisted drop folder settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DropFolderConfig {
+    pub enabled: bool,
+    pub drop_folder: Option<PathBuf>,
+    pub trusted_devices: Vec<String>, // Endpoint ids of paired devices
+    /// Pushes bigger than this are refused
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+    /// Pushes with more files than this are refused
+    #[serde(default)]
+    pub max_files: Option<u64>,
+}
+
+fn data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data directory: {}", e))
+}
+
+pub fn log_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(data_dir(app)?.join(LOG_FILE))
+}
+
+/// Blob store kept across shares so re-sharing unchanged files skips hashing them
+pub fn share_store_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(data_dir(app)?.join(SHARE_STORE_DIR))
+}
+
+/// Blob store kept across mirrors, its own since a share and a mirror can run at once
+pub fn mirror_store_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(data_dir(app)?.join(MIRROR_STORE_DIR))
+}
+
+/// Blob store kept across downloads so a changed folder only fetches the files that changed
+pub fn receive_store_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(data_dir(app)?.join(RECEIVE_STORE_DIR))
+}
+
+/// Every share, download and push this device took part in
+pub fn history(app: &tauri::AppHandle) -> Result<TransferHistory, String> {
+    Ok(TransferHistory::new(data_dir(app)?.join(HISTORY_FILE)))
+}
+
+pub fn load_config(app: &tauri::AppHandle) -> Result<DropFolderConfig, String> {
+    let path = data_dir(app)?.join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(DropFolderConfig::default());
+    }
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read drop folder config: {}", e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Invalid drop folder config: {}", e))
+}
+
+pub fn save_config(app: &tauri::AppHandle, config: &DropFolderConfig) -> Result<(), String> {
+    let dir = data_dir(app)?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    let contents = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize drop folder config: {}", e))?;
+    std::fs::write(dir.join(CONFIG_FILE), contents)
+        .map_err(|e| format!("Failed to write drop folder config: {}", e))
+}
+
+/// The identity this device uses for the drop folder and for pushing to paired devices
+pub fn device_secret(app: &tauri::AppHandle) -> Result<SecretKey, String> {
+    load_or_create_secret(&data_dir(app)?.join(SECRET_FILE))
+        .map_err(|e| format!("Failed to load device key: {}", e))
+}
+
+/// Only one endpoint may run as this device at a time: two endpoints with the same id
+/// take over each other's relay slot and discovery record
+static DEVICE_IDENTITY: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// The device's identity, lent to one download, push, mirror subscription or pairing.
+/// The drop folder is offline while it is lent out and comes back once it is dropped.
+pub struct DeviceIdentity {
+    secret: SecretKey,
+    app: tauri::AppHandle,
+    resume_drop_folder: bool,
+    _lent: tokio::sync::MutexGuard<'static, ()>,
+}
+
+impl DeviceIdentity {
+    pub fn secret(&self) -> SecretKey {
+        self.secret.clone()
+    }
+}
+
+impl Drop for DeviceIdentity {
+    fn drop(&mut self) {
+        if !self.resume_drop_folder {
+            return;
+        }
+        // Starting waits for the identity, which is handed back right after this
+        let app = self.app.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = start(&app).await {
+                tracing::warn!("Failed to bring the drop folder back online: {}", e);
+            }
+        });
+    }
+}
+
+/// Takes the drop folder offline for as long as the returned identity is held. Refuses
+/// while something else runs as this device. Must not be called with the app state locked.
+pub async fn lend_device_identity(app: &tauri::AppHandle) -> Result<DeviceIdentity, String> {
+    let secret = device_secret(app)?;
+    let lent = DEVICE_IDENTITY.try_lock().map_err(|_| {
+        "Another download, push, mirror or pairing is running as this device. \
+         Try again once it is done."
+            .to_string()
+    })?;
+    let state = app.state::<AppStateMutex>();
+    let drop_folder = state.lock().await.drop_folder.take();
+    let resume_drop_folder = drop_folder.is_some();
+    if let Some(mut handle) = drop_folder {
+        handle.stop().await?;
+        tracing::info!("Drop folder offline while this device's identity is in use");
+    }
+    Ok(DeviceIdentity {
+        secret,
+        app: app.clone(),
+        resume_drop_folder,
+        _lent: lent,
+    })
+}
+
+/// Devices this one has paired with
+pub fn load_contacts(app: &tauri::AppHandle) -> Result<ContactBook, String> {
+    ContactBook::load(data_dir(app)?.join(CONTACTS_FILE))
+        .map_err(|e| format!("Failed to load contacts: {}", e))
+}
+
+/// The relay and discovery services every endpoint of the app uses
+pub struct NetworkOptions {
+    pub relay_mode: RelayModeOption,
+    pub discovery: DiscoveryOption,
+}
+
+/// Self-hosted deployments point the relay and discovery at their own services via
+/// `SENDME_RELAY_URL`, `SENDME_PKARR_RELAY` and `SENDME_DNS_ORIGIN`
+pub fn network_options() -> Result<NetworkOptions, String> {
+    let relay_mode =
+        RelayModeOption::from_env().map_err(|e| format!("Invalid relay configuration: {}", e))?;
+    let discovery = DiscoveryOption::from_env()
+        .map_err(|e| format!("Invalid discovery configuration: {}", e))?;
+    Ok(NetworkOptions {
+        relay_mode,
+        discovery,
+    })
+}
+
+fn parse_trusted_devices(devices: &[String]) -> Result<HashSet<EndpointId>, String> {
+    devices
+        .iter()
+        .map(|id| {
+            EndpointId::from_str(id.trim()).map_err(|e| format!("Invalid device id {}: {}", id, e))
+        })
+        .collect()
+}
+
+/// Start the drop folder inbox, returning its ticket. Does nothing if it is already running.
+pub async fn start(app: &tauri::AppHandle) -> Result<String, String> {
+    // The drop folder runs as this device, so it waits until nothing else does
+    let _identity = DEVICE_IDENTITY.lock().await;
+    let state = app.state::<AppStateMutex>();
+    let mut app_state = state.lock().await;
+    if let Some(handle) = &app_state.drop_folder {
+        return Ok(handle.ticket.clone());
+    }
+
+    let config = load_config(app)?;
+    let drop_folder = config
+        .drop_folder
+        .clone()
+        .ok_or_else(|| "No drop folder configured".to_string())?;
+    std::fs::create_dir_all(&drop_folder)
+        .map_err(|e| format!("Failed to create drop folder: {}", e))?;
+
+    let NetworkOptions {
+        relay_mode,
+        discovery,
+    } = network_options()?;
+
+    let allowed_peers = parse_trusted_devices(&config.trusted_devices)?;
+
+    let options = InboxOptions {
+        output_dir: Some(drop_folder),
+        secret_key: Some(device_secret(app)?),
+        allowed_peers: Some(allowed_peers),
+        // Paired contacts are always trusted, including ones paired after the folder started
+        contacts: Some(data_dir(app)?.join(CONTACTS_FILE)),
+        transfer_log: Some(log_path(app)?),
+        history: Some(data_dir(app)?.join(HISTORY_FILE)),
+        // Several devices drop files here; record which one sent each of them
+        tag_origin: true,
+        max_total_bytes: config.max_total_bytes,
+        max_files: config.max_files,
+        relay_mode,
+        // The endpoint id is stable, so an id-only ticket stays valid across restarts
+        ticket_type: AddrInfoOptions::Id,
+        magic_ipv4_addr: None,
+        magic_ipv6_addr: None,
+        discovery,
+        proxy: ProxyOption::Environment,
+    };
+
+    let emitter = Arc::new(TauriEventEmitter {
+        app_handle: app.clone(),
+    });
+    let boxed_handle: AppHandle = Some(emitter);
+
+    match start_inbox(options, boxed_handle).await {
+        Ok(result) => {
+            let ticket = result.ticket.clone();
+            app_state.drop_folder = Some(InboxHandle::new(result));
+            tracing::info!("Drop folder online");
+            Ok(ticket)
+        }
+        Err(e) => Err(format!("Failed to start drop folder: {}", e)),
+    }
+}
+
+/// Take the drop folder offline
+pub async fn stop(app: &tauri::AppHandle) -> Result<(), String> {
+    let state = app.state::<AppStateMutex>();
+    let mut app_state = state.lock().await;
+    if let Some(mut handle) = app_state.drop_folder.take() {
+        handle.stop().await?;
+        tracing::info!("Drop folder offline");
+    }
+    Ok(())
+}
+
+/// Whether the drop folder is currently online
+#[cfg(desktop)]
+pub async fn is_running(app: &tauri::AppHandle) -> bool {
+    let state = app.state::<AppStateMutex>();
+    let app_state = state.lock().await;
+    app_state.drop_folder.is_some()
+}
+
+/// Turn the drop folder on or off and remember the choice for the next launch.
+/// Returns whether it is online afterwards.
+#[cfg(desktop)]
+pub async fn toggle(app: &tauri::AppHandle) -> Result<bool, String> {
+    let enable = !is_running(app).await;
+    if enable {
+        start(app).await?;
+    } else {
+        stop(app).await?;
+    }
+    let mut config = load_config(app)?;
+    config.enabled = enable;
+    save_config(app, &config)?;
+    Ok(enable)
+}
+
+/// Bring the drop folder online at launch if it was left enabled
+pub fn autostart(app: &tauri::AppHandle) {
+    match load_config(app) {
+        Ok(config) if config.enabled => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = start(&app).await {
+                    tracing::warn!("Failed to start drop folder: {}", e);
+                }
+            });
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("{}", e),
+    }
+}
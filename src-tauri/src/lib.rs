@@ -1,6 +1,7 @@
 // Library entry point for Tauri. Used by the binary (desktop) and by the native Android/iOS app (mobile).
 
 mod commands;
+mod daemon;
 mod platform;
 mod state;
 #[cfg(not(target_os = "android"))]
@@ -10,8 +11,15 @@ mod version;
 pub use version::get_app_version;
 
 use commands::{
-    check_launch_intent, check_path_type, get_file_size, get_sharing_status, get_transport_status,
-    receive_file, start_sharing, stop_sharing,
+    cancel_pairing, check_launch_intent, check_path_type, clear_history, clear_share_store,
+    close_inbox, confirm_pairing, export_history, get_device_id, get_drop_folder_config,
+    get_drop_folder_log, get_drop_folder_status, get_file_provenance, get_file_size,
+    get_inbox_status, get_mirror_conflicts, get_sharing_status, get_transport_status,
+    is_ticket_locked, join_pairing_session, list_contacts, list_history, list_receivers,
+    open_inbox, push_to_inbox, receive_file, remove_contact, rename_contact, send_to_contact,
+    set_drop_folder_config, start_drop_folder, start_mirroring, start_pairing, start_sharing,
+    stop_drop_folder, stop_mirroring, stop_sharing, subscribe_to_mirror, unsubscribe_from_mirror,
+    verify_received,
 };
 use state::AppState;
 use std::fs;
@@ -26,7 +34,10 @@ fn cleanup_orphaned_directories() {
         if let Ok(entries) = fs::read_dir(&base_dir) {
             for entry in entries.flatten() {
                 if let Some(name) = entry.file_name().to_str() {
-                    if (name.starts_with(".sendme-send-") || name.starts_with(".sendme-recv-"))
+                    if (name.starts_with(".sendme-send-")
+                        || name.starts_with(".sendme-recv-")
+                        || name.starts_with(".sendme-inbox-")
+                        || name.starts_with(".sendme-mirror-"))
                         && entry.path().is_dir()
                     {
                         if let Err(e) = fs::remove_dir_all(&entry.path()) {
@@ -67,17 +78,50 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             start_sharing,
             stop_sharing,
+            clear_share_store,
             receive_file,
+            is_ticket_locked,
+            verify_received,
+            get_file_provenance,
             get_sharing_status,
             check_path_type,
             get_transport_status,
             get_file_size,
             check_launch_intent,
+            open_inbox,
+            close_inbox,
+            get_inbox_status,
+            push_to_inbox,
+            start_mirroring,
+            stop_mirroring,
+            subscribe_to_mirror,
+            unsubscribe_from_mirror,
+            get_mirror_conflicts,
+            list_receivers,
+            get_device_id,
+            get_drop_folder_config,
+            set_drop_folder_config,
+            start_drop_folder,
+            stop_drop_folder,
+            get_drop_folder_status,
+            get_drop_folder_log,
+            list_history,
+            clear_history,
+            export_history,
+            start_pairing,
+            join_pairing_session,
+            confirm_pairing,
+            cancel_pairing,
+            list_contacts,
+            remove_contact,
+            rename_contact,
+            send_to_contact,
         ])
         .setup(|app| {
             setup_common(app);
             #[cfg(desktop)]
             tray::setup_tray(&app.handle())?;
+            daemon::autostart(app.handle());
             Ok(())
         });
 
@@ -1,4 +1,7 @@
-use sendme::SendResult;
+use crate::daemon::DeviceIdentity;
+use sendme::{
+    InboxResult, MirrorResult, MirrorSubscription, PairingHost, PendingPairing, SendResult,
+};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -7,6 +10,19 @@ use tokio::sync::Mutex;
 #[derive(Default)]
 pub struct AppState {
     pub current_share: Option<ShareHandle>,
+    pub current_inbox: Option<InboxHandle>,
+    /// Always-on inbox for trusted devices
+    pub drop_folder: Option<InboxHandle>,
+    /// Folder published to mirror subscribers
+    pub current_mirror: Option<MirrorHandle>,
+    /// Mirror this device keeps a folder in step with
+    pub mirror_subscription: Option<MirrorSubscriptionHandle>,
+    /// Pairing session waiting for another device to join
+    pub pairing_host: Option<Arc<PairingHost>>,
+    /// Pairing handshake waiting for the user to compare the short authentication string
+    pub pending_pairing: Option<PendingPairing>,
+    /// Identity the pairing runs as, held until it is confirmed or given up
+    pub pairing_identity: Option<DeviceIdentity>,
     pub is_transporting: bool, // True when actual data transfer is happening
     pub launch_intent: Option<String>, // Path to file/folder passed via CLI (e.g. context menu)
 }
@@ -21,6 +37,11 @@ pub struct ShareHandle {
 
 impl Drop for ShareHandle {
     fn drop(&mut self) {
+        // The persistent store is the import cache for the next share
+        if self.send_result.persistent_store {
+            return;
+        }
+
         // Clean up the temporary blobs directory when share is stopped
         // Use blocking cleanup since Drop is synchronous
         // Spawn a thread to avoid blocking the async runtime
@@ -67,6 +88,11 @@ impl ShareHandle {
             }
         }
 
+        // Release the store's lock now, so the next share can open a persistent store again
+        if let Err(e) = self.send_result._store.shutdown().await {
+            tracing::warn!("Store shutdown error: {}", e);
+        }
+
         // temp_tag, _store, and _progress_handle will be dropped automatically when the method ends
         // Cleanup of blobs directory will happen in Drop trait
 
@@ -74,5 +100,126 @@ impl ShareHandle {
     }
 }
 
+/// Handle for an open inbox that accepts pushed files
+pub struct InboxHandle {
+    pub ticket: String,
+    pub inbox_result: InboxResult, // Keeps the router and store alive
+}
+
+impl Drop for InboxHandle {
+    fn drop(&mut self) {
+        let blobs_dir = self.inbox_result.blobs_data_dir.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = std::fs::remove_dir_all(&blobs_dir) {
+                tracing::warn!(
+                    "Failed to clean up inbox directory {}: {}",
+                    blobs_dir.display(),
+                    e
+                );
+            }
+        });
+    }
+}
+
+impl InboxHandle {
+    pub fn new(inbox_result: InboxResult) -> Self {
+        Self {
+            ticket: inbox_result.ticket.clone(),
+            inbox_result,
+        }
+    }
+
+    /// Stop accepting pushes; cleanup happens in Drop
+    pub async fn stop(&mut self) -> Result<(), String> {
+        use std::time::Duration;
+
+        match tokio::time::timeout(Duration::from_secs(2), self.inbox_result.router.shutdown())
+            .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::warn!("Inbox router shutdown error: {}", e);
+            }
+            Err(_) => {
+                tracing::warn!("Inbox router shutdown timeout after 2 seconds");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes a temporary blob store once its mirror or subscription is gone
+fn remove_store_dir(blobs_dir: PathBuf) {
+    std::thread::spawn(move || {
+        if let Err(e) = std::fs::remove_dir_all(&blobs_dir) {
+            tracing::warn!(
+                "Failed to clean up mirror directory {}: {}",
+                blobs_dir.display(),
+                e
+            );
+        }
+    });
+}
+
+/// Handle for a folder being mirrored to subscribers
+pub struct MirrorHandle {
+    pub ticket: String,
+    pub path: PathBuf,
+    pub mirror_result: MirrorResult, // Keeps the router, the folder watch and the store alive
+}
+
+impl Drop for MirrorHandle {
+    fn drop(&mut self) {
+        if !self.mirror_result.persistent_store {
+            remove_store_dir(self.mirror_result.blobs_data_dir.clone());
+        }
+    }
+}
+
+impl MirrorHandle {
+    pub fn new(path: PathBuf, mirror_result: MirrorResult) -> Self {
+        Self {
+            ticket: mirror_result.ticket.clone(),
+            path,
+            mirror_result,
+        }
+    }
+
+    /// Stop publishing; cleanup happens in Drop
+    pub async fn stop(&mut self) -> Result<(), String> {
+        use std::time::Duration;
+
+        match tokio::time::timeout(Duration::from_secs(2), self.mirror_result.router.shutdown())
+            .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::warn!("Mirror router shutdown error: {}", e);
+            }
+            Err(_) => {
+                tracing::warn!("Mirror router shutdown timeout after 2 seconds");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Handle for a subscription that keeps a local folder in step with a mirror
+pub struct MirrorSubscriptionHandle {
+    pub subscription: MirrorSubscription, // Keeps the follower task and the store alive
+    /// The follower runs as this device; dropped after it, so the drop folder comes back
+    pub _identity: DeviceIdentity,
+}
+
+impl Drop for MirrorSubscriptionHandle {
+    fn drop(&mut self) {
+        if !self.subscription.persistent_store {
+            remove_store_dir(self.subscription.blobs_data_dir.clone());
+        }
+    }
+}
+
 /// Thread-safe wrapper for AppState
 pub type AppStateMutex = Arc<Mutex<AppState>>;
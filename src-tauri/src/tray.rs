@@ -1,6 +1,6 @@
 // imports for tray
 use tauri::{
-    menu::{Menu, MenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem},
     tray::{MouseButton, TrayIconBuilder, TrayIconEvent},
     AppHandle, Manager,
 };
@@ -40,8 +40,19 @@ fn open_and_focus(app: &AppHandle) -> bool {
 
 pub fn setup_tray(app: &AppHandle) -> tauri::Result<()> {
     let open = MenuItem::with_id(app, "open", "Open", true, None::<&str>)?; // open button
+    let drop_folder_enabled = crate::daemon::load_config(app)
+        .map(|config| config.enabled)
+        .unwrap_or(false);
+    let drop_folder = CheckMenuItem::with_id(
+        app,
+        "drop_folder",
+        "Drop Folder",
+        true,
+        drop_folder_enabled,
+        None::<&str>,
+    )?; // always-on inbox toggle
     let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?; // quit button
-    let menu = Menu::with_items(app, &[&open, &quit])?;
+    let menu = Menu::with_items(app, &[&open, &drop_folder, &quit])?;
 
     let mut builder = TrayIconBuilder::new()
         .menu(&menu)
@@ -51,6 +62,20 @@ pub fn setup_tray(app: &AppHandle) -> tauri::Result<()> {
             "open" => {
                 open_and_focus(app);
             }
+            "drop_folder" => {
+                let app = app.clone();
+                let drop_folder = drop_folder.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = crate::daemon::toggle(&app).await {
+                        tracing::warn!("Failed to toggle drop folder: {}", e);
+                    }
+                    // Reflect the actual state, whether or not the toggle worked
+                    let running = crate::daemon::is_running(&app).await;
+                    if let Err(e) = drop_folder.set_checked(running) {
+                        tracing::warn!("Failed to update drop folder menu item: {}", e);
+                    }
+                });
+            }
             "quit" => {
                 tracing::info!("Quit requested from tray");
 
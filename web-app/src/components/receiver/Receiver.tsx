@@ -122,6 +122,18 @@ export function Receiver({ onTransferStateChange }: ReceiverProps) {
 						</AlertDialogDescription>
 					</AlertDialogHeader>
 					<AlertDialogFooter>
+						{alertDialog.retryable && (
+							<Button
+								size="sm"
+								variant="outline"
+								onClick={() => {
+									closeAlert()
+									handleReceive()
+								}}
+							>
+								{t('common:errors.retry')}
+							</Button>
+						)}
 						<AlertDialogClose
 							onClick={closeAlert}
 							render={<Button size="sm">{t('common:ok')}</Button>}
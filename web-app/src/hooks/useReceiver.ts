@@ -6,6 +6,7 @@ import { revealItemInDir } from '@tauri-apps/plugin-opener'
 import { selectDownloadFolder } from '@/plugins/nativeUtils'
 import { useEffect, useRef, useState } from 'react'
 import { useTranslation } from '../i18n/react-i18next-compat'
+import { describeError, isRetryable } from '../lib/errors'
 import { sendSystemNotification } from '../lib/systemNotification'
 import type { AlertDialogState, AlertType } from '../types/ui'
 import type { TransferMetadata, TransferProgress } from '../types/transfer'
@@ -173,7 +174,8 @@ export function useReceiver(): UseReceiverReturn {
 					const payload = event.payload as string
 					const parts = payload.split(':')
 
-					if (parts.length === 3) {
+					// bytes:total:speed, then eta:file:files from newer cores
+					if (parts.length >= 3) {
 						const bytesTransferred = parseInt(parts[0], 10)
 						const totalBytes = parseInt(parts[1], 10)
 						const speedInt = parseInt(parts[2], 10)
@@ -183,11 +185,17 @@ export function useReceiver(): UseReceiverReturn {
 								? Math.min((bytesTransferred / totalBytes) * 100, 100)
 								: 0
 
+						// The core averages speed over a moving window and estimates the ETA itself
+						const coreEta = parts.length >= 6 ? parseInt(parts[3], 10) : -1
+						const currentFile = parts.length >= 6 ? parseInt(parts[4], 10) : 0
+						const fileCount = parts.length >= 6 ? parseInt(parts[5], 10) : 0
+
 						// Add speed sample and calculate ETA
 						speedAveragerRef.current.addSample(speedBps)
 						const avgSpeed = speedAveragerRef.current.getAverage()
 						const bytesRemaining = Math.max(totalBytes - bytesTransferred, 0)
-						const eta = calculateETA(bytesRemaining, avgSpeed)
+						const eta =
+							coreEta >= 0 ? coreEta : calculateETA(bytesRemaining, avgSpeed)
 
 						setTransferProgress({
 							bytesTransferred,
@@ -195,6 +203,8 @@ export function useReceiver(): UseReceiverReturn {
 							speedBps,
 							percentage,
 							etaSeconds: eta ?? undefined,
+							currentFile: currentFile || undefined,
+							fileCount: fileCount || undefined,
 						})
 					}
 				} catch (error) {
@@ -333,7 +343,13 @@ export function useReceiver(): UseReceiverReturn {
 			})
 		} catch (error) {
 			console.error('Failed to receive file:', error)
-			showAlert(t('common:errors.receiveFailed'), String(error), 'error')
+			setAlertDialog({
+				isOpen: true,
+				title: t('common:errors.receiveFailed'),
+				description: describeError(error, t),
+				type: 'error',
+				retryable: isRetryable(error),
+			})
 			setIsReceiving(false)
 			setIsTransporting(false)
 			setIsCompleted(false)
@@ -2,6 +2,7 @@ import { useEffect, useRef } from 'react'
 import { invoke } from '@tauri-apps/api/core'
 import { listen, type UnlistenFn } from '@tauri-apps/api/event'
 import { useTranslation } from '../i18n/react-i18next-compat'
+import { describeError } from '../lib/errors'
 import type { AlertType } from '../types/ui'
 import type { TransferMetadata, TransferProgress } from '../types/transfer'
 import { SpeedAverager, calculateETA } from '../utils/etaUtils'
@@ -99,6 +100,7 @@ export function useSender(): UseSenderReturn {
 		let unlistenComplete: UnlistenFn | undefined
 		let unlistenFailed: UnlistenFn | undefined
 		let unlistenActiveCount: UnlistenFn | undefined
+		let unlistenWarnings: UnlistenFn | undefined
 
 		const setupListeners = async () => {
 			unlistenActiveCount = await listen(
@@ -119,6 +121,22 @@ export function useSender(): UseSenderReturn {
 				}
 			)
 
+			unlistenWarnings = await listen('share-warnings', (event: any) => {
+				try {
+					const skipped = JSON.parse(event.payload as string) as {
+						path: string
+						reason: string
+					}[]
+					showAlert(
+						t('common:errors.filesSkipped', { count: skipped.length }),
+						skipped.map((file) => `${file.path}: ${file.reason}`).join('\n'),
+						'info'
+					)
+				} catch (error) {
+					console.error('Failed to parse share warnings event:', error)
+				}
+			})
+
 			unlistenStart = await listen('transfer-started', () => {
 				const storeState = useSenderStore.getState()
 				// console.log('[useSender] transfer-started event received:', {
@@ -174,7 +192,8 @@ export function useSender(): UseSenderReturn {
 					const payload = event.payload as string
 					const parts = payload.split(':')
 
-					if (parts.length === 3) {
+					// bytes:total:speed, then eta:file:files from newer cores
+					if (parts.length >= 3) {
 						const bytesTransferred = parseInt(parts[0], 10)
 						const totalBytes = parseInt(parts[1], 10)
 						const speedInt = parseInt(parts[2], 10)
@@ -184,11 +203,17 @@ export function useSender(): UseSenderReturn {
 								? Math.min((bytesTransferred / totalBytes) * 100, 100)
 								: 0
 
+						// The core averages speed over a moving window and estimates the ETA itself
+						const coreEta = parts.length >= 6 ? parseInt(parts[3], 10) : -1
+						const currentFile = parts.length >= 6 ? parseInt(parts[4], 10) : 0
+						const fileCount = parts.length >= 6 ? parseInt(parts[5], 10) : 0
+
 						// Add speed sample and calculate ETA
 						speedAveragerRef.current.addSample(speedBps)
 						const avgSpeed = speedAveragerRef.current.getAverage()
 						const bytesRemaining = Math.max(totalBytes - bytesTransferred, 0)
-						const eta = calculateETA(bytesRemaining, avgSpeed)
+						const eta =
+							coreEta >= 0 ? coreEta : calculateETA(bytesRemaining, avgSpeed)
 
 						latestProgressRef.current = {
 							bytesTransferred,
@@ -196,6 +221,8 @@ export function useSender(): UseSenderReturn {
 							speedBps,
 							percentage,
 							etaSeconds: eta ?? undefined,
+							currentFile: currentFile || undefined,
+							fileCount: fileCount || undefined,
 						}
 					}
 				} catch (error) {
@@ -439,6 +466,7 @@ export function useSender(): UseSenderReturn {
 			if (unlistenComplete) unlistenComplete()
 			if (unlistenFailed) unlistenFailed()
 			if (unlistenActiveCount) unlistenActiveCount()
+			if (unlistenWarnings) unlistenWarnings()
 		}
 	}, [
 		setViewState,
@@ -501,7 +529,7 @@ export function useSender(): UseSenderReturn {
 			console.error('[useSender] startSharing: failed:', error)
 			showAlert(
 				t('common:errors.sharingFailed'),
-				`${t('common:errors.sharingFailedDesc')}: ${error}`,
+				`${t('common:errors.sharingFailedDesc')}: ${describeError(error, t)}`,
 				'error'
 			)
 		} finally {
@@ -0,0 +1,29 @@
+type TranslateFn = (key: string, options?: Record<string, unknown>) => string
+
+/** Error returned by transfer commands, serialized from `sendme::Error` */
+export interface SendmeError {
+	code: string
+	message: string
+	retryable: boolean
+}
+
+export function isSendmeError(error: unknown): error is SendmeError {
+	return (
+		typeof error === 'object' &&
+		error !== null &&
+		typeof (error as SendmeError).code === 'string' &&
+		typeof (error as SendmeError).message === 'string'
+	)
+}
+
+/** A user facing message for the error, falling back to the backend message for unknown codes */
+export function describeError(error: unknown, t: TranslateFn): string {
+	if (!isSendmeError(error)) {
+		return String(error)
+	}
+	return t(`common:errors.codes.${error.code}`, { defaultValue: error.message })
+}
+
+export function isRetryable(error: unknown): boolean {
+	return isSendmeError(error) && error.retryable
+}
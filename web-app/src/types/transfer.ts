@@ -15,6 +15,9 @@ export interface TransferProgress {
 	speedBps: number
 	percentage: number
 	etaSeconds?: number
+	/** 1-based position of the file in transfer, when the core reports it */
+	currentFile?: number
+	fileCount?: number
 }
 
 export interface SuccessScreenProps {
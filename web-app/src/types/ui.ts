@@ -3,6 +3,8 @@ export interface AlertDialogState {
 	title: string
 	description: string
 	type: 'success' | 'error' | 'info'
+	/** Offer to run the failed action again */
+	retryable?: boolean
 }
 
 export type AlertType = 'success' | 'error' | 'info'